@@ -0,0 +1 @@
+cargo run -p analyzer -r -- --heatmap testdata\heatmap.png testdata\tex.rvc
\ No newline at end of file
@@ -0,0 +1,240 @@
+use anyhow::Result;
+use clap::Parser;
+use rvc_codec::{
+    cost::CostModel,
+    decoder::{Decoder, FrameInfo},
+    frame::BlockOp,
+};
+use rvc_shared::{
+    colors::{FloatColor, IntColor},
+    palette::Palette,
+    picture::save_image,
+    plane::Plane,
+};
+use std::{
+    fs,
+    io::{BufReader, BufWriter, Read, Write, stdin, stdout},
+    path::PathBuf,
+};
+
+/// Heatmap colors of block operations.
+const OP_COLORS: [(i32, i32, i32); 5] = [(0, 0, 0), (40, 90, 255), (230, 40, 40), (40, 200, 60), (240, 210, 40)];
+
+#[derive(Clone, Copy, Debug, Default)]
+struct OpCounts {
+    skip: u32,
+    motion: u32,
+    raw: u32,
+    fill: u32,
+    entry: u32,
+}
+
+/// Heatmap color of an operation.
+fn op_index(op: &BlockOp) -> i32 {
+    match op {
+        BlockOp::Skip => 0,
+        BlockOp::Motion(_) => 1,
+        BlockOp::Raw => 2,
+        BlockOp::Fill(_) => 3,
+        BlockOp::Entry(_) => 4,
+    }
+}
+
+impl OpCounts {
+    fn new(ops: &[BlockOp]) -> OpCounts {
+        let mut counts = OpCounts::default();
+        for op in ops {
+            match op {
+                BlockOp::Skip => counts.skip += 1,
+                BlockOp::Motion(_) => counts.motion += 1,
+                BlockOp::Raw => counts.raw += 1,
+                BlockOp::Fill(_) => counts.fill += 1,
+                BlockOp::Entry(_) => counts.entry += 1,
+            }
+        }
+        counts
+    }
+}
+
+fn updates(info: &FrameInfo) -> String {
+    let mut updates = vec![];
+    if info.palette_update {
+        updates.push(String::from("palette"));
+    }
+    if let Some(entries) = info.codebook_entries {
+        updates.push(format!("codebook {}", entries));
+    }
+    updates.join(" ")
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Stream to analyze, `-` reads it from standard input
+    file: PathBuf,
+    /// Write comma-separated values instead of a table
+    #[arg(long)]
+    csv: bool,
+    /// Write the report to a file instead of the console
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// PNG with one row of block operations per frame
+    #[arg(long)]
+    heatmap: Option<PathBuf>,
+    /// Decoder cycles of an operation for the cost estimate, as NAME=CYCLES. Can be repeated
+    #[arg(long = "cost", value_name = "NAME=CYCLES")]
+    costs: Vec<String>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse_from(wild::args());
+    let mut cost_model = CostModel::default();
+    for assignment in args.costs.iter() {
+        cost_model.parse_assignment(assignment)?;
+    }
+
+    // The analysis reads the stream once from start to end, so it doesn't need to seek
+    let input: Box<dyn Read> = if args.file.as_os_str() == "-" {
+        Box::new(stdin().lock())
+    } else {
+        Box::new(fs::File::open(&args.file)?)
+    };
+    let mut decoder = Decoder::new(BufReader::new(input))?;
+    let header = decoder.header().clone();
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(filename) => Box::new(BufWriter::new(fs::File::create(filename)?)),
+        None => Box::new(stdout()),
+    };
+
+    if args.csv {
+        writeln!(
+            out,
+            "frame,type,bytes,payload,audio,cycles,skip,motion,raw,fill,entry,updates"
+        )?;
+    } else {
+        writeln!(
+            out,
+            "{}x{}, {}/{} fps, {} frames, block size {}",
+            header.width, header.height, header.fps_num, header.fps_den, header.frame_count, header.block_size
+        )?;
+        writeln!(
+            out,
+            "{:>6} {:>4} {:>8} {:>8} {:>6} {:>9} {:>6} {:>6} {:>6} {:>6} {:>6}  updates",
+            "frame", "type", "bytes", "payload", "audio", "cycles", "skip", "motion", "raw", "fill", "entry"
+        )?;
+    }
+
+    let mut heatmap: Vec<i32> = vec![];
+    let mut blocks = 0;
+    let mut number = 0;
+    let mut keyframes = 0;
+    let mut total = 0;
+    let mut largest = (0, 0);
+    let mut total_cycles = 0;
+    let mut slowest = (0, 0);
+    let mut counts = OpCounts::default();
+    while decoder.next_frame()?.is_some() {
+        let info = decoder.info();
+        let frame = OpCounts::new(&info.ops);
+        let kind = if info.keyframe { "K" } else { "D" };
+        let cycles = cost_model.frame_cycles(
+            header.width,
+            header.height,
+            header.block_size,
+            &info.ops,
+            info.codebook_entries,
+            &info.entropy,
+        );
+        if args.csv {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{}",
+                number,
+                kind,
+                info.size,
+                info.payload_size,
+                info.audio_size,
+                cycles,
+                frame.skip,
+                frame.motion,
+                frame.raw,
+                frame.fill,
+                frame.entry,
+                updates(info)
+            )?;
+        } else {
+            writeln!(
+                out,
+                "{:>6} {:>4} {:>8} {:>8} {:>6} {:>9} {:>6} {:>6} {:>6} {:>6} {:>6}  {}",
+                number,
+                kind,
+                info.size,
+                info.payload_size,
+                info.audio_size,
+                cycles,
+                frame.skip,
+                frame.motion,
+                frame.raw,
+                frame.fill,
+                frame.entry,
+                updates(info)
+            )?;
+        }
+        if args.heatmap.is_some() {
+            blocks = info.ops.len() as u32;
+            heatmap.extend(info.ops.iter().map(op_index));
+        }
+
+        keyframes += info.keyframe as u32;
+        total += info.size + info.audio_size;
+        if info.size > largest.1 {
+            largest = (number, info.size);
+        }
+        total_cycles += cycles;
+        if cycles > slowest.1 {
+            slowest = (number, cycles);
+        }
+        counts.skip += frame.skip;
+        counts.motion += frame.motion;
+        counts.raw += frame.raw;
+        counts.fill += frame.fill;
+        counts.entry += frame.entry;
+        number += 1;
+    }
+
+    if !args.csv {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{} frames, {} keyframes, {} bytes, {:.0} bytes per frame on average",
+            number,
+            keyframes,
+            total,
+            total as f64 / number.max(1) as f64
+        )?;
+        writeln!(out, "Largest frame: {} with {} bytes", largest.0, largest.1)?;
+        writeln!(
+            out,
+            "Slowest frame: {} with {} cycles, {:.0} cycles per frame on average",
+            slowest.0,
+            slowest.1,
+            total_cycles as f64 / number.max(1) as f64
+        )?;
+        writeln!(
+            out,
+            "Blocks: {} skipped, {} motion, {} raw, {} filled, {} codebook",
+            counts.skip, counts.motion, counts.raw, counts.fill, counts.entry
+        )?;
+    }
+    out.flush()?;
+
+    if let Some(filename) = args.heatmap.as_ref().filter(|_| number > 0) {
+        let mut colors = Palette::new();
+        for (r, g, b) in OP_COLORS {
+            colors.add(FloatColor::from(IntColor::new(r, g, b)));
+        }
+        let mut image = Plane::new(blocks, number, 0);
+        image.data = heatmap;
+        save_image(filename, &image, &colors)?;
+    }
+    Ok(())
+}
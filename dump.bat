@@ -0,0 +1 @@
+cargo run -p dump -r -- -o testdata\dump testdata\tex.rvc
\ No newline at end of file
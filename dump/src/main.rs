@@ -0,0 +1,123 @@
+use anyhow::{Result, bail};
+use clap::Parser;
+use rvc_codec::decoder::Decoder;
+use rvc_shared::{colors::IntColor, picture::save_image, plane::Plane, wav::WavWriter, y4m::Y4mWriter};
+use std::{
+    fs,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+    time::Instant,
+};
+
+/// Enlarges every pixel to a `scale` x `scale` square.
+fn upscale<T: Copy>(source: &Plane<T>, target: &mut Plane<T>, scale: u32) {
+    for y in 0..target.height {
+        for x in 0..target.width {
+            target.set(x, y, source.get(x / scale, y / scale));
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    file: PathBuf,
+    /// Directory for numbered PNG frames
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Y4M file with all dumped frames
+    #[arg(long)]
+    y4m: Option<PathBuf>,
+    /// WAV file with the sound of the dumped frames
+    #[arg(long)]
+    wav: Option<PathBuf>,
+    /// First frame to dump
+    #[arg(short, long, default_value_t = 0)]
+    start: u32,
+    /// Frame after the last one to dump, the end of the stream by default
+    #[arg(short, long)]
+    end: Option<u32>,
+    /// Integer upscaling factor
+    #[arg(short = 'x', long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16))]
+    scale: u32,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse_from(wild::args());
+    if args.output.is_none() && args.y4m.is_none() && args.wav.is_none() {
+        bail!("Nothing to dump, set a PNG directory, a Y4M or a WAV file");
+    }
+
+    let mut decoder = Decoder::new(BufReader::new(fs::File::open(&args.file)?))?;
+    let header = decoder.header().clone();
+    let end = args.end.unwrap_or(header.frame_count).min(header.frame_count);
+    if args.start >= end {
+        bail!(
+            "Frame range {}..{} is empty, the stream has {} frames",
+            args.start,
+            end,
+            header.frame_count
+        );
+    }
+    decoder.seek(args.start)?;
+
+    let (width, height) = (header.width * args.scale, header.height * args.scale);
+    if let Some(dir) = &args.output {
+        fs::create_dir_all(dir)?;
+    }
+    let mut y4m = match &args.y4m {
+        Some(filename) => Some(Y4mWriter::new(
+            BufWriter::new(fs::File::create(filename)?),
+            width,
+            height,
+            header.fps_num,
+            header.fps_den,
+        )?),
+        None => None,
+    };
+    let mut wav = match (&args.wav, header.audio) {
+        (Some(filename), Some(audio)) => Some(WavWriter::new(
+            BufWriter::new(fs::File::create(filename)?),
+            audio.sample_rate,
+            audio.channels,
+        )?),
+        (Some(_), None) => bail!("{:?} has no audio track", args.file),
+        _ => None,
+    };
+    let stem = args.file.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+
+    let now = Instant::now();
+    let mut scaled = Plane::new(width, height, 0i32);
+    let mut colors = Plane::new(width, height, IntColor::BLACK);
+    for number in args.start..end {
+        let frame = match decoder.next_frame()? {
+            Some(frame) => frame,
+            None => bail!("Stream ends at frame {}", number),
+        };
+        upscale(frame, &mut scaled, args.scale);
+        if let Some(dir) = &args.output {
+            save_image(
+                &dir.join(format!("{}_{:05}.png", stem, number)),
+                &scaled,
+                decoder.palette(),
+            )?;
+        }
+        if let Some(y4m) = &mut y4m {
+            for (color, index) in colors.data.iter_mut().zip(scaled.data.iter()) {
+                *color = IntColor::from(decoder.palette().get(*index));
+            }
+            y4m.write_frame(&colors)?;
+        }
+        if let Some(wav) = &mut wav {
+            wav.write_samples(decoder.audio())?;
+        }
+    }
+    if let Some(y4m) = y4m {
+        y4m.finish()?;
+    }
+    if let Some(wav) = wav {
+        wav.finish()?;
+    }
+    println!("Dumped {} frames", end - args.start);
+    println!("Elapsed: {:.2?}", now.elapsed());
+    Ok(())
+}
@@ -0,0 +1 @@
+cargo run -p encoder -r -- -p testdata\tex64.pal -m testdata\noise480x270x16.ptrn -o testdata\tex.rvc testdata\tex*.png
\ No newline at end of file
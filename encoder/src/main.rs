@@ -0,0 +1,325 @@
+use anyhow::{Result, bail};
+use clap::{Parser, ValueEnum};
+use rvc::{
+    pipeline::{Pipeline, Preprocessor, Source, output_size},
+    preprocess::ScaleArgs,
+    quantize::MatrixDither,
+    source::{FileSource, WavSource},
+};
+use rvc_codec::{
+    audio::Codec,
+    cost::CostModel,
+    encoder::{Encoder, EncoderConfig, SkipError},
+    entropy::Backend,
+    rate::{Budget, PassStats},
+};
+use rvc_shared::{
+    container::{AudioFormat, Header},
+    dmatrix::DitherMatrix,
+    palette::{CycleDirection, CycleRange, Palette, scene_filename},
+    wav::WavReader,
+};
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Seek, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+/// Parses `FRAME:FILE` of a palette switch.
+fn parse_palette_switch(value: &str) -> Result<(u32, PathBuf), String> {
+    match value.split_once(':') {
+        Some((frame, file)) if !file.is_empty() => match frame.parse() {
+            Ok(frame) => Ok((frame, PathBuf::from(file))),
+            Err(_) => Err(format!("Invalid frame number {:?}", frame)),
+        },
+        _ => Err(String::from("Expected FRAME:FILE")),
+    }
+}
+
+/// Parses `START:END:STEPS` of a cycling range, negative steps per second rotate backwards.
+fn parse_cycle(value: &str) -> Result<CycleRange, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 3 {
+        return Err(String::from("Expected START:END:STEPS"));
+    }
+    let start = parts[0].parse().map_err(|_| format!("Invalid start {:?}", parts[0]))?;
+    let end = parts[1].parse().map_err(|_| format!("Invalid end {:?}", parts[1]))?;
+    let steps: f64 = parts[2].parse().map_err(|_| format!("Invalid rate {:?}", parts[2]))?;
+    let direction = if steps < 0.0 {
+        CycleDirection::Backward
+    } else {
+        CycleDirection::Forward
+    };
+    Ok(CycleRange::new(start, end, steps.abs(), direction))
+}
+
+/// Destination of the encoded stream.
+trait Output: Write + Seek {}
+
+impl<T: Write + Seek> Output for T {}
+
+fn add_cycles(palette: &mut Palette, ranges: &[CycleRange]) -> Result<()> {
+    for range in ranges {
+        palette.add_cycle(*range)?;
+    }
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// Stream file, not needed by the first pass of a two-pass encode
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[arg(short, long)]
+    palette: PathBuf,
+    /// Switch to another palette starting with a frame, as FRAME:FILE. Can be repeated
+    #[arg(long = "switch-palette", value_name = "FRAME:FILE", value_parser = parse_palette_switch)]
+    palette_switches: Vec<(u32, PathBuf)>,
+    /// Switch to the palette of every scene of the statistics file as calculated by palcalc, named
+    /// after --palette with the first frame of the scene before the extension. Needs --pass 2
+    #[arg(long, conflicts_with = "palette_switches")]
+    scene_palettes: bool,
+    /// Rotate palette entries START to END at STEPS per second, backwards if negative. Can be repeated
+    #[arg(long = "cycle", value_name = "START:END:STEPS", value_parser = parse_cycle)]
+    cycles: Vec<CycleRange>,
+    #[arg(short, long)]
+    matrix: String,
+    /// Frame rate, taken from the first Y4M file or 25 by default
+    #[arg(short, long)]
+    fps: Option<u32>,
+    #[arg(long, default_value_t = 1)]
+    fps_den: u32,
+    #[command(flatten)]
+    scale: ScaleArgs,
+    #[arg(short, long, default_value_t = 8)]
+    block_size: u32,
+    #[arg(short = 'r', long, default_value_t = 8)]
+    motion_radius: u32,
+    #[arg(short, long, default_value_t = 0)]
+    codebook: u32,
+    #[arg(short, long, default_value_t = 250)]
+    keyframe_interval: u32,
+    #[arg(short, long, default_value_t = 0.4)]
+    scene_threshold: f64,
+    #[arg(long, conflicts_with = "byte_rate")]
+    frame_budget: Option<u32>,
+    /// Average bytes per second. Frames may use up to a second's worth of bytes that earlier
+    /// frames left unused, so a single second may take up to twice as much
+    #[arg(long)]
+    byte_rate: Option<u32>,
+    /// Skip changed blocks in which no pixel differs more than this, in 8-bit brightness steps
+    #[arg(long, default_value_t = 0.0)]
+    skip_threshold: f64,
+    /// Pass of a two-pass encode. The first one ignores the byte and cycle budgets and writes only
+    /// the statistics file, the second one spreads the byte rate over the clip by it and needs
+    /// --byte-rate
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=2))]
+    pass: Option<u32>,
+    /// Statistics file of a two-pass encode
+    #[arg(long, default_value = "rvc.stats")]
+    stats: PathBuf,
+    /// Estimated decoder cycles a delta frame may take
+    #[arg(long, default_value_t = 0)]
+    cycle_budget: u64,
+    /// Decoder cycles of an operation, as NAME=CYCLES. Can be repeated
+    #[arg(long = "cost", value_name = "NAME=CYCLES")]
+    costs: Vec<String>,
+    /// Number of groups of frames coded in parallel, not available with a budget
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+    #[arg(short, long, value_enum, default_value_t = Entropy::None)]
+    entropy: Entropy,
+    /// WAV file with the sound track
+    #[arg(short, long)]
+    audio: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = AudioCodec::Pcm16)]
+    audio_codec: AudioCodec,
+    /// Sample rate of the stored sound, the WAV rate by default
+    #[arg(long)]
+    audio_rate: Option<u32>,
+    /// Mix the sound down to one channel
+    #[arg(long)]
+    mono: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum AudioCodec {
+    Pcm16,
+    Pcm8,
+    Adpcm,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Entropy {
+    None,
+    Rle,
+    Lz77,
+    Huffman,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse_from(wild::args());
+    if args.pass == Some(2) && args.byte_rate.is_none() {
+        bail!("The second pass needs a --byte-rate to spread over the clip");
+    }
+    if args.output.is_none() && args.pass != Some(1) {
+        bail!("Missing --output file");
+    }
+    if args.scene_palettes && args.pass != Some(2) {
+        bail!("Scene palettes need the statistics of --pass 2");
+    }
+    // The budget carries over from frame to frame, so frames are coded one after another
+    let limited = args.pass != Some(1) && (args.frame_budget.is_some() || args.byte_rate.is_some());
+    if args.jobs > 1 && limited {
+        bail!("--jobs can't be combined with --frame-budget or --byte-rate");
+    }
+    let first_pass = match args.pass {
+        Some(2) => Some(PassStats::from_file(&args.stats)?),
+        _ => None,
+    };
+
+    let mut palette_switches = args.palette_switches.clone();
+    let palette_file = match &first_pass {
+        Some(stats) if args.scene_palettes => {
+            for scene in stats.scenes().iter().skip(1) {
+                palette_switches.push((scene.start, scene_filename(&args.palette, scene.start)));
+            }
+            scene_filename(&args.palette, 0)
+        }
+        _ => args.palette.clone(),
+    };
+    let mut pal = Palette::from_file(palette_file)?;
+    add_cycles(&mut pal, &args.cycles)?;
+    let pat = DitherMatrix::from_file(args.matrix)?;
+    let mut quantizer = MatrixDither::new(pal.clone(), pat);
+    for (frame, filename) in palette_switches.iter() {
+        let mut palette = Palette::from_file(filename.clone())?;
+        add_cycles(&mut palette, &args.cycles)?;
+        quantizer.switch_palette(*frame, palette);
+    }
+
+    let source = FileSource::new(args.files)?;
+    let format = source.format();
+    let mut preprocessors: Vec<Box<dyn Preprocessor>> = vec![];
+    if let Some(scaler) = args.scale.scaler()? {
+        preprocessors.push(Box::new(scaler));
+    }
+    let (width, height) = output_size(&format, &preprocessors);
+    let mut header = match args.fps {
+        Some(fps) => Header::new(width, height, fps, args.fps_den),
+        None => Header::new(width, height, format.fps_num, format.fps_den),
+    };
+    let sound = match &args.audio {
+        Some(filename) => {
+            let wav = WavReader::new(BufReader::new(fs::File::open(filename)?))?;
+            let format = AudioFormat {
+                sample_rate: args.audio_rate.unwrap_or(wav.sample_rate),
+                channels: if args.mono { 1 } else { wav.channels },
+                codec: match args.audio_codec {
+                    AudioCodec::Pcm16 => Codec::Pcm16,
+                    AudioCodec::Pcm8 => Codec::Pcm8,
+                    AudioCodec::Adpcm => Codec::Adpcm,
+                }
+                .id(),
+            };
+            if format.sample_rate == 0 {
+                bail!("Audio sample rate must not be 0");
+            }
+            header.audio = Some(format);
+            Some(WavSource::new(wav, format.sample_rate, args.mono)?)
+        }
+        None => None,
+    };
+    // The first pass measures the frames as they come without any budget
+    let cycle_budget = if args.pass == Some(1) { 0 } else { args.cycle_budget };
+    let mut cost_model = CostModel::default();
+    for assignment in args.costs.iter() {
+        cost_model.parse_assignment(assignment)?;
+    }
+    let config = EncoderConfig {
+        block_size: args.block_size,
+        motion_radius: args.motion_radius,
+        codebook_size: args.codebook,
+        keyframe_interval: args.keyframe_interval,
+        scene_threshold: args.scene_threshold,
+        budget: match (args.frame_budget, args.byte_rate) {
+            _ if args.pass == Some(1) => Budget::Unlimited,
+            (Some(bytes), _) => Budget::BytesPerFrame(bytes),
+            (_, Some(bytes)) => Budget::BytesPerSecond(bytes),
+            _ => Budget::Unlimited,
+        },
+        entropy: match args.entropy {
+            Entropy::None => Backend::None,
+            Entropy::Rle => Backend::Rle,
+            Entropy::Lz77 => Backend::Lz77,
+            Entropy::Huffman => Backend::Huffman,
+        },
+        skip_threshold: args.skip_threshold,
+        parallel_groups: args.jobs,
+        cost_model,
+        cycle_budget,
+        first_pass,
+        ..Default::default()
+    };
+    let output: Box<dyn Output> = match &args.output {
+        Some(filename) if args.pass != Some(1) => Box::new(BufWriter::new(fs::File::create(filename)?)),
+        _ => Box::new(io::empty()),
+    };
+    let encoder = Encoder::new(output, header, &pal, config)?;
+    let mut pipeline = Pipeline::new(Box::new(source), preprocessors, Box::new(quantizer), encoder);
+    if let Some(sound) = sound {
+        pipeline.set_audio(Box::new(sound));
+    }
+
+    let now = Instant::now();
+    let mut over_budget = 0;
+    let mut over_cycles = 0;
+    let mut skip_error = SkipError::default();
+    let mut pass_stats = PassStats::default();
+    let frames = pipeline.run(|number, stats| {
+        skip_error.add(&stats.skip_error);
+        pass_stats.frames.push(stats.pass_frame());
+        if stats.over_budget() {
+            over_budget += 1;
+            println!(
+                "Frame {} doesn't fit the budget: {} bytes of {:.0}",
+                number,
+                stats.size + stats.audio_size + stats.palette_size,
+                stats.budget
+            );
+        }
+        if cycle_budget > 0 && stats.cycles > cycle_budget {
+            over_cycles += 1;
+            println!(
+                "Frame {} is too slow to decode: {} cycles of {}",
+                number, stats.cycles, cycle_budget
+            );
+        }
+        Ok(())
+    })?;
+    pipeline.into_coder().finish()?;
+    if args.pass == Some(1) {
+        pass_stats.save(&args.stats)?;
+    }
+    let elapsed = now.elapsed();
+    if over_budget > 0 {
+        println!("{} frames exceeded the budget", over_budget);
+    }
+    if over_cycles > 0 {
+        println!("{} frames exceeded the cycle budget", over_cycles);
+    }
+    println!("Encoded {} frames", frames);
+    if skip_error.blocks > 0 {
+        println!(
+            "Skipped {} blocks below the threshold, mean error {:.2}, max {:.2}",
+            skip_error.blocks,
+            skip_error.mean(),
+            skip_error.max
+        );
+    }
+    println!("Elapsed: {:.2?}", elapsed);
+    Ok(())
+}
@@ -35,6 +35,7 @@ impl StatusCalculating {
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn update(
         &mut self,
         tui: &mut Tui,
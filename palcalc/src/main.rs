@@ -1,13 +1,26 @@
 use anyhow::Result;
 use clap::Parser;
-use colorcalc::{ColorCalc, ColorData};
-use image::ImageReader;
-use interface::StatusLoading;
-use std::path::PathBuf;
+use image::{ImageReader, RgbImage};
+use interface::{StatusCalculating, StatusLoading};
+use rvc::{
+    codec::rate::PassStats,
+    colorcalc::{ColorCalc, ColorData},
+    preprocess::{ScaleArgs, Scaler},
+};
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
 
-use rvc_shared::interface::Tui;
+use rvc_shared::{
+    colors::IntColor,
+    interface::Tui,
+    palette::{Palette, scene_filename},
+    plane::Plane,
+    y4m::Y4mReader,
+};
 
-mod colorcalc;
 mod interface;
 
 #[derive(Parser, Debug)]
@@ -22,31 +35,118 @@ struct Args {
     attempts: u32,
     #[arg(short, long, default_value_t = 1000)]
     steps: u32,
+    /// First-pass statistics of the encoder. One palette is calculated for every scene and named after
+    /// the output with the first frame of the scene before the extension, see --scene-palettes of the encoder
+    #[arg(long)]
+    stats: Option<PathBuf>,
+    // Scaling of the encoder, the palette then covers the frames as they are encoded
+    #[command(flatten)]
+    scale: ScaleArgs,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse_from(wild::args());
-
-    let mut tui = Tui::new()?;
-    tui.show_intro()?;
+fn image_plane(image: &RgbImage) -> Plane<IntColor> {
+    let mut plane = Plane::new(image.width(), image.height(), IntColor::BLACK);
+    for (pixel, color) in image.pixels().zip(plane.data.iter_mut()) {
+        *color = IntColor::new(pixel.0[0] as i32, pixel.0[1] as i32, pixel.0[2] as i32);
+    }
+    plane
+}
 
+/// Collects the colors of all frames in a single pass, after scaling them if `scaler` is set.
+/// `scene` tells which scene a frame belongs to, scenes follow each other in frame order. `done`
+/// gets the colors of a scene as soon as its last frame has been read.
+fn load(
+    tui: &mut Tui,
+    files: &[PathBuf],
+    mut scaler: Option<Scaler>,
+    scene: impl Fn(u32) -> usize,
+    mut done: impl FnMut(&mut Tui, usize, ColorData) -> Result<()>,
+) -> Result<()> {
     let mut color_data = ColorData::new();
-    let mut loading_status = StatusLoading::new(&mut tui, args.files.len() as u32)?;
+    let mut loading_status = StatusLoading::new(tui, files.len() as u32)?;
     loading_status.timer.start();
 
-    for (progress, filename) in args.files.iter().enumerate() {
-        let img = ImageReader::open(filename)?.decode()?.to_rgb8();
-        color_data.add(&img);
-        if loading_status.timer.needs_update() || progress == 0 || progress == args.files.len() - 1 {
-            loading_status.update(&mut tui, filename, progress as u32)?;
+    let mut current = 0;
+    let mut frame_number = 0;
+    for (progress, filename) in files.iter().enumerate() {
+        let mut add = |tui: &mut Tui, color_data: &mut ColorData, frame: &Plane<IntColor>| -> Result<()> {
+            let next = scene(frame_number);
+            if next != current {
+                done(tui, current, std::mem::replace(color_data, ColorData::new()))?;
+                tui.separator()?;
+                loading_status = StatusLoading::new(tui, files.len() as u32)?;
+                loading_status.timer.start();
+                current = next;
+            }
+            match &mut scaler {
+                Some(scaler) => color_data.add_plane(&scaler.scale(frame)?),
+                None => color_data.add_plane(frame),
+            }
+            frame_number += 1;
+            Ok(())
+        };
+        if filename.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("y4m")) {
+            let mut video = Y4mReader::new(BufReader::new(fs::File::open(filename)?))?;
+            let mut frame = Plane::new(video.width, video.height, IntColor::BLACK);
+            while video.read_frame(&mut frame)? {
+                add(tui, &mut color_data, &frame)?;
+            }
+        } else {
+            let img = ImageReader::open(filename)?.decode()?.to_rgb8();
+            add(tui, &mut color_data, &image_plane(&img))?;
+        }
+        if loading_status.timer.needs_update() || progress == 0 || progress == files.len() - 1 {
+            loading_status.update(tui, filename, progress as u32)?;
         };
     }
+    done(tui, current, color_data)
+}
 
+fn calculate(tui: &mut Tui, args: &Args, color_data: ColorData) -> Result<Palette> {
     tui.separator()?;
 
-    let mut calculator = ColorCalc::new(args.colors, color_data, &mut tui, args.attempts, args.steps)?;
-    let palette = calculator.run(&mut tui)?;
-    palette.save(args.output)?;
+    let mut calculator = ColorCalc::new(args.colors, color_data, args.attempts, args.steps);
+    let mut status = StatusCalculating::new(tui, args.attempts, args.steps, calculator.total_colors())?;
+    calculator.run(|p| {
+        if status.timer.needs_update() || p.last {
+            status.update(tui, p.attempt, p.step, p.moved, p.distance * 100.0, p.current, p.total)?;
+        }
+        Ok(())
+    })
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse_from(wild::args());
+
+    let mut tui = Tui::new()?;
+    tui.show_intro()?;
+
+    match &args.stats {
+        Some(filename) => {
+            let scenes = PassStats::from_file(filename)?.scenes();
+            // Frames after the end of the statistics belong to the last scene
+            let scene = |frame: u32| {
+                scenes
+                    .partition_point(|scene| scene.end <= frame)
+                    .min(scenes.len().max(1) - 1)
+            };
+            load(&mut tui, &args.files, args.scale.scaler()?, scene, |tui, i, color_data| {
+                let palette = calculate(tui, &args, color_data)?;
+                let start = scenes.get(i).map_or(0, |scene| scene.start);
+                palette.save(scene_filename(Path::new(&args.output), start).to_string_lossy().into_owned())
+            })?;
+        }
+        None => load(
+            &mut tui,
+            &args.files,
+            args.scale.scaler()?,
+            |_| 0,
+            |tui, _, color_data| {
+                let palette = calculate(tui, &args, color_data)?;
+                palette.save(args.output.clone())
+            },
+        )?,
+    }
 
     Ok(())
 }
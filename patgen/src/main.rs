@@ -1,11 +1,9 @@
 use anyhow::Result;
-use bluenoise::generate_points;
 use clap::{Args, Parser};
 use image::{ImageBuffer, ImageFormat};
+use rvc::pattern::{bayer, blue_noise};
 use rvc_shared::dmatrix::DitherMatrix;
 
-mod bluenoise;
-
 #[derive(Parser, Debug)]
 #[command(disable_help_flag = true)]
 struct CliArgs {
@@ -39,12 +37,6 @@ struct BayerParams {
     bayer: u32,
 }
 
-fn is_power_of_two(value: u32) -> bool {
-    (value != 0) && (value & (value - 1) == 0)
-}
-
-const BAYER2: [u32; 4] = [0, 2, 3, 1];
-
 fn save_preview(pattern: &DitherMatrix, filename: &String) {
     let mut img = ImageBuffer::new(pattern.width, pattern.height);
     for (x, y, pixel) in img.enumerate_pixels_mut() {
@@ -58,45 +50,13 @@ fn main() -> Result<()> {
     let args = CliArgs::parse();
     if let Some(BayerParams { bayer: size }) = args.bayer_params {
         // Bayer pattern
-
-        if size < 2 {
-            println!("Size must be at least 2");
-            return Ok(());
-        }
-
-        if !is_power_of_two(size) {
-            println!("Size must be power of two.");
-            return Ok(());
-        }
-
-        let mut pattern = DitherMatrix::new(size, size, size * size);
-
-        let mut block_size = 2;
-        loop {
-            let step = size / block_size;
-
-            for by in 0..step {
-                for bx in 0..step {
-                    for py in 0..2 {
-                        for px in 0..2 {
-                            let color = BAYER2[(px + 2 * py) as usize];
-                            for iy in 0..block_size / 2 {
-                                for ix in 0..block_size / 2 {
-                                    let fx = bx * block_size + px * block_size / 2 + ix;
-                                    let fy = by * block_size + py * block_size / 2 + iy;
-                                    let prev_color = pattern.get(fx, fy);
-                                    pattern.set(fx, fy, prev_color * 4 + color);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            block_size *= 2;
-            if block_size > size {
-                break;
+        let pattern = match bayer(size) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                println!("{}", err);
+                return Ok(());
             }
-        }
+        };
 
         pattern.save(args.output)?;
 
@@ -105,17 +65,7 @@ fn main() -> Result<()> {
         }
     } else if let Some(params) = args.noise_params {
         // Blue noise pattern
-        let points = generate_points(params.width, params.height);
-
-        let divisor = points.len() as f64 / params.levels as f64;
-        let mut statistic = vec![0u32; params.levels as usize];
-        let mut pattern = DitherMatrix::new(params.width, params.height, params.levels);
-
-        for (i, point) in points.iter().enumerate() {
-            let level = (i as f64 / divisor).floor() as u32;
-            pattern.set(point.x, point.y, level);
-            statistic[level as usize] += 1;
-        }
+        let pattern = blue_noise(params.width, params.height, params.levels);
 
         pattern.save(args.output)?;
 
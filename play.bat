@@ -0,0 +1 @@
+cargo run -p player -r -- testdata\tex.rvc
\ No newline at end of file
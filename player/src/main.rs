@@ -0,0 +1,162 @@
+use anyhow::Result;
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use rvc_codec::decoder::Decoder;
+use rvc_shared::plane::Plane;
+use std::{
+    fs,
+    io::BufReader,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::screen::{Screen, palette_colors};
+
+mod screen;
+
+const SHORT_SEEK: f64 = 5.0;
+const LONG_SEEK: f64 = 30.0;
+/// How often cycling palette ranges are redrawn.
+const CYCLE_TICK: Duration = Duration::from_millis(1000 / 60);
+
+#[derive(Parser, Debug)]
+struct Args {
+    file: PathBuf,
+    /// Start over when the stream ends
+    #[arg(short, long)]
+    r#loop: bool,
+}
+
+fn time_format(frame: u32, fps: f64) -> String {
+    let seconds = frame as f64 / fps;
+    format!("{:0>2}:{:05.2}", (seconds / 60.0) as u32, seconds % 60.0)
+}
+
+fn show_status(screen: &mut Screen, position: u32, frame_count: u32, fps: f64, paused: bool) -> Result<()> {
+    let shown = position.saturating_sub(1);
+    screen.status(&format!(
+        "{} / {}  frame {}/{}{}   space: pause  .: step  \u{2190}\u{2192}: {}s  \u{2193}\u{2191}: {}s  home: start  q: quit",
+        time_format(shown, fps),
+        time_format(frame_count, fps),
+        shown + 1,
+        frame_count,
+        if paused { "  [paused]" } else { "" },
+        SHORT_SEEK,
+        LONG_SEEK,
+    ))
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse_from(wild::args());
+
+    let mut decoder = Decoder::new(BufReader::new(fs::File::open(&args.file)?))?;
+    let header = decoder.header().clone();
+    if header.frame_count == 0 {
+        println!("{:?} has no frames", args.file);
+        return Ok(());
+    }
+    let fps = header.fps();
+    let frame_time = Duration::from_secs_f64(1.0 / fps);
+
+    let mut screen = Screen::new()?;
+    let mut frame = Plane::new(header.width, header.height, 0);
+    let mut colors = palette_colors(decoder.palette());
+    let mut paused = false;
+    // Decode the next frame even when paused
+    let mut advance = true;
+    let mut deadline = Instant::now();
+    // Color cycling keeps running from the time of the shown frame, even when paused
+    let mut shown_frame = 0;
+    let mut shown_at = Instant::now();
+    loop {
+        if advance || (!paused && Instant::now() >= deadline) {
+            match decoder.next_frame()? {
+                Some(decoded) => {
+                    frame.data.copy_from_slice(&decoded.data);
+                    colors = palette_colors(decoder.palette());
+                    // Only decode while playback is behind by more than a frame
+                    let late = !advance && Instant::now() > deadline + frame_time;
+                    if !late {
+                        screen.draw(&frame, &colors)?;
+                        shown_frame = decoder.position() - 1;
+                        shown_at = Instant::now();
+                    }
+                    deadline = if late && Instant::now() > deadline + Duration::from_secs(1) {
+                        Instant::now()
+                    } else {
+                        deadline + frame_time
+                    };
+                }
+                None if args.r#loop => {
+                    decoder.seek(0)?;
+                    continue;
+                }
+                None => paused = true,
+            }
+            advance = false;
+            show_status(&mut screen, decoder.position(), header.frame_count, fps, paused)?;
+        }
+
+        let cycling = !decoder.base_palette().cycles().is_empty();
+        if cycling {
+            // Milliseconds times the frame rate, so that frame times stay exact
+            let time_num = shown_frame as u64 * header.fps_den as u64 * 1000
+                + shown_at.elapsed().as_millis() as u64 * header.fps_num as u64;
+            let cycled = palette_colors(&decoder.base_palette().cycled(time_num, header.fps_num as u64 * 1000));
+            if cycled != colors {
+                colors = cycled;
+                screen.draw(&frame, &colors)?;
+            }
+        }
+
+        let mut timeout = if paused {
+            Duration::from_millis(250)
+        } else {
+            deadline.saturating_duration_since(Instant::now())
+        };
+        if cycling {
+            timeout = timeout.min(CYCLE_TICK);
+        }
+        if !event::poll(timeout)? {
+            continue;
+        }
+        let mut seek = None;
+        match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Char(' ') => {
+                    paused = !paused;
+                    advance = !paused && decoder.position() >= header.frame_count;
+                    if advance {
+                        decoder.seek(0)?;
+                    }
+                    deadline = Instant::now();
+                }
+                KeyCode::Char('.') => {
+                    paused = true;
+                    advance = true;
+                }
+                KeyCode::Left => seek = Some(-SHORT_SEEK),
+                KeyCode::Right => seek = Some(SHORT_SEEK),
+                KeyCode::Down => seek = Some(-LONG_SEEK),
+                KeyCode::Up => seek = Some(LONG_SEEK),
+                KeyCode::Home => seek = Some(-f64::INFINITY),
+                _ => {}
+            },
+            Event::Resize(width, height) => {
+                screen.resize(width, height)?;
+                screen.draw(&frame, &colors)?;
+            }
+            _ => {}
+        }
+        if let Some(seconds) = seek {
+            let shown = decoder.position().saturating_sub(1) as f64;
+            let target = (shown + seconds * fps).clamp(0.0, (header.frame_count - 1) as f64);
+            decoder.seek(target as u32)?;
+            advance = true;
+            deadline = Instant::now();
+        }
+        show_status(&mut screen, decoder.position(), header.frame_count, fps, paused)?;
+    }
+    Ok(())
+}
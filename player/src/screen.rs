@@ -0,0 +1,123 @@
+use anyhow::Result;
+use crossterm::{
+    cursor, execute, queue,
+    style::{self, Color},
+    terminal,
+};
+use rvc_shared::{colors::IntColor, palette::Palette, plane::Plane};
+use std::io::{Stdout, Write, stdout};
+
+/// Terminal colors of all palette entries.
+pub fn palette_colors(palette: &Palette) -> Vec<Color> {
+    (0..palette.len())
+        .map(|i| {
+            let color = IntColor::from(palette.get(i as i32));
+            Color::Rgb {
+                r: color.r as u8,
+                g: color.g as u8,
+                b: color.b as u8,
+            }
+        })
+        .collect()
+}
+
+/// Full-screen output that draws two pixels per character cell with upper half blocks,
+/// the top pixel as the foreground and the bottom one as the background color.
+/// The last terminal line is kept for the status.
+pub struct Screen {
+    out: Stdout,
+    buffer: Vec<u8>,
+    width: u16,
+    height: u16,
+}
+
+impl Screen {
+    pub fn new() -> Result<Screen> {
+        let (width, height) = terminal::size()?;
+        let mut out = stdout();
+        terminal::enable_raw_mode()?;
+        execute!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        Ok(Screen {
+            out,
+            buffer: vec![],
+            width,
+            height,
+        })
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) -> Result<()> {
+        self.width = width;
+        self.height = height;
+        execute!(self.out, style::ResetColor, terminal::Clear(terminal::ClearType::All))?;
+        Ok(())
+    }
+
+    /// Draws the frame scaled to fit the screen with its aspect ratio kept.
+    pub fn draw(&mut self, frame: &Plane<i32>, colors: &[Color]) -> Result<()> {
+        let rows = self.height.saturating_sub(1) as u32;
+        let scale = (self.width as f64 / frame.width as f64).min((rows * 2) as f64 / frame.height as f64);
+        let width = (frame.width as f64 * scale) as u32;
+        let height = ((frame.height as f64 * scale) as u32) & !1;
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let left = (self.width as u32 - width) / 2;
+        let top = (rows - height / 2) / 2;
+
+        self.buffer.clear();
+        let mut current = (Color::Reset, Color::Reset);
+        for row in 0..height / 2 {
+            queue!(self.buffer, cursor::MoveTo(left as u16, (top + row) as u16))?;
+            let upper = (row * 2 * frame.height / height).min(frame.height - 1);
+            let lower = ((row * 2 + 1) * frame.height / height).min(frame.height - 1);
+            for column in 0..width {
+                let x = column * frame.width / width;
+                let fg = colors[frame.get(x, upper) as usize];
+                let bg = colors[frame.get(x, lower) as usize];
+                if fg != current.0 {
+                    queue!(self.buffer, style::SetForegroundColor(fg))?;
+                }
+                if bg != current.1 {
+                    queue!(self.buffer, style::SetBackgroundColor(bg))?;
+                }
+                current = (fg, bg);
+                queue!(self.buffer, style::Print('▀'))?;
+            }
+        }
+        queue!(self.buffer, style::ResetColor)?;
+        self.out.write_all(&self.buffer)?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    pub fn status(&mut self, text: &str) -> Result<()> {
+        execute!(
+            self.out,
+            cursor::MoveTo(0, self.height.saturating_sub(1)),
+            style::ResetColor,
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::SetForegroundColor(Color::Grey),
+            style::Print(text.chars().take(self.width as usize).collect::<String>()),
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        // Errors can't be reported from here, and panicking while unwinding would abort with the
+        // terminal left in raw mode
+        let _ = execute!(
+            self.out,
+            style::ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -1,12 +1,13 @@
 use anyhow::Result;
 use clap::Parser;
-use image::{ImageBuffer, ImageFormat, ImageReader};
-use indexing::{convert_fs, convert_matrix, convert_posterize};
-use rvc_shared::{colors::IntColor, dmatrix::DitherMatrix, palette::Palette, plane::Plane};
+use image::ImageReader;
+use rvc::preprocess::ScaleArgs;
+use rvc_shared::{
+    colors::IntColor, dmatrix::DitherMatrix, indexing::convert_matrix, palette::Palette, picture::save_image,
+    plane::Plane,
+};
 use std::{path::PathBuf, time::Instant};
 
-mod indexing;
-
 fn load_image(filename: &PathBuf, image: &mut Plane<IntColor>) -> Result<()> {
     let file = ImageReader::open(filename)?.decode()?.to_rgb8();
     for (file_pixel, img_pixel) in file.pixels().zip(image.data.iter_mut()) {
@@ -17,16 +18,6 @@ fn load_image(filename: &PathBuf, image: &mut Plane<IntColor>) -> Result<()> {
     Ok(())
 }
 
-fn save_image(filename: &PathBuf, image: &Plane<i32>, palette: &Palette) -> Result<()> {
-    let mut file = ImageBuffer::new(image.width, image.height);
-    for (file_pixel, img_pixel) in file.pixels_mut().zip(image.data.iter()) {
-        let c = IntColor::from(palette.get(*img_pixel));
-        *file_pixel = image::Rgb([c.r as u8, c.g as u8, c.b as u8]);
-    }
-    file.save_with_format(filename, ImageFormat::Png)?;
-    Ok(())
-}
-
 #[derive(Parser, Debug)]
 struct Args {
     #[arg(required = true)]
@@ -35,6 +26,8 @@ struct Args {
     output: PathBuf,
     #[arg(short, long)]
     palette: PathBuf,
+    #[command(flatten)]
+    scale: ScaleArgs,
 }
 
 fn main() -> Result<()> {
@@ -43,6 +36,8 @@ fn main() -> Result<()> {
     let pal = Palette::from_file(args.palette)?;
     let pat = DitherMatrix::from_file(String::from("testdata\\noise480x270x16.ptrn"))?;
 
+    let mut scaler = args.scale.scaler()?;
+
     let now = Instant::now();
     for file in args.files {
         println!("{:?}", file);
@@ -50,7 +45,10 @@ fn main() -> Result<()> {
 
         let mut img = Plane::new(width, height, IntColor::BLACK);
         load_image(&file, &mut img)?;
-        let mut out = Plane::new(width, height, 0i32);
+        if let Some(scaler) = &mut scaler {
+            img = scaler.scale(&img)?;
+        }
+        let mut out = Plane::new(img.width, img.height, 0i32);
         convert_matrix(&img, &mut out, &pal, &pat);
 
         let mut outfile = file.clone();
@@ -5,13 +5,19 @@ use image::RgbImage;
 use rand::Rng;
 use rayon::prelude::*;
 
-use crate::interface::StatusCalculating;
 use rvc_shared::colors::{FloatColor, IntColor};
-use rvc_shared::interface::Tui;
 use rvc_shared::palette::Palette;
+use rvc_shared::plane::Plane;
 
+/// Histogram of all colors of the source frames.
 pub struct ColorData(Vec<Vec<Vec<u64>>>);
 
+impl Default for ColorData {
+    fn default() -> Self {
+        ColorData::new()
+    }
+}
+
 impl ColorData {
     pub fn new() -> ColorData {
         ColorData(vec![vec![vec![0u64; 256]; 256]; 256])
@@ -23,6 +29,12 @@ impl ColorData {
             self.0[color[0] as usize][color[1] as usize][color[2] as usize] += 1;
         }
     }
+
+    pub fn add_plane(&mut self, image: &Plane<IntColor>) {
+        for color in image.data.iter() {
+            self.0[color.r as usize][color.g as usize][color.b as usize] += 1;
+        }
+    }
 }
 
 struct ColorPoint {
@@ -43,6 +55,23 @@ impl ColorPoint {
     }
 }
 
+/// State of the palette calculation, reported after every k-means step.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub attempt: u32,
+    pub step: u32,
+    /// Number of colors that moved to another cluster.
+    pub moved: u64,
+    /// How far the cluster centers moved.
+    pub distance: f64,
+    /// Steps done so far and the estimated total over all attempts.
+    pub current: u32,
+    pub total: u32,
+    /// Whether the attempt ends with this step.
+    pub last: bool,
+}
+
+/// Calculates a palette for the collected colors with k-means clustering.
 pub struct ColorCalc {
     points: Vec<ColorPoint>,
     centroids: Vec<FloatColor>,
@@ -53,7 +82,6 @@ pub struct ColorCalc {
     total_distance: f64,
     points_changed: u64,
 
-    status: StatusCalculating,
     max_attempts: u32,
     max_steps: u32,
 
@@ -62,13 +90,7 @@ pub struct ColorCalc {
 }
 
 impl ColorCalc {
-    pub fn new(
-        color_count: u32,
-        colors: ColorData,
-        tui: &mut Tui,
-        max_attempts: u32,
-        max_steps: u32,
-    ) -> Result<ColorCalc> {
+    pub fn new(color_count: u32, colors: ColorData, max_attempts: u32, max_steps: u32) -> ColorCalc {
         let mut total_colors = {
             if color_count > 256 {
                 256u64
@@ -101,8 +123,8 @@ impl ColorCalc {
             total_colors = unique_colors;
         }
 
-        Ok(ColorCalc {
-            colors: color_count,
+        ColorCalc {
+            colors: total_colors as u32,
             points,
             centroids: vec![FloatColor::BLACK; total_colors as usize],
             point_count: unique_colors,
@@ -110,10 +132,14 @@ impl ColorCalc {
             points_changed: 0,
             max_attempts,
             max_steps,
-            status: StatusCalculating::new(tui, max_attempts, max_steps, total_colors as u32)?,
             best_error: 0.0,
             best_palette: Palette::new(),
-        })
+        }
+    }
+
+    /// Number of palette colors, limited by the number of distinct source colors.
+    pub fn total_colors(&self) -> u32 {
+        self.colors
     }
 
     fn init_centroids(&mut self) {
@@ -194,27 +220,20 @@ impl ColorCalc {
         self.points_changed = *points_changed.lock().unwrap();
     }
 
-    fn update_stats(&mut self, tui: &mut Tui, attempt: u32, step: u32, passed: u32) -> Result<()> {
-        let step_current;
-        let steps_total;
-        if attempt == 0 {
-            step_current = step;
-            steps_total = self.max_attempts * self.max_steps;
-        } else {
-            step_current = passed + step;
-            steps_total = passed + passed / attempt * (self.max_attempts - attempt);
-        }
-
-        self.status.update(
-            tui,
+    fn progress(&self, attempt: u32, step: u32, passed: u32, last: bool) -> Progress {
+        let (current, total) = match passed.checked_div(attempt) {
+            Some(per_attempt) => (passed + step, passed + per_attempt * (self.max_attempts - attempt)),
+            None => (step, self.max_attempts * self.max_steps),
+        };
+        Progress {
             attempt,
             step,
-            self.points_changed,
-            self.total_distance * 100.0,
-            step_current,
-            steps_total,
-        )?;
-        Ok(())
+            moved: self.points_changed,
+            distance: self.total_distance,
+            current,
+            total,
+            last,
+        }
     }
 
     fn calc_error(&self) -> f64 {
@@ -233,21 +252,20 @@ impl ColorCalc {
         result
     }
 
-    pub fn run(&mut self, tui: &mut Tui) -> Result<Palette> {
+    /// Runs all attempts and returns the best palette. `progress` is called after every step.
+    pub fn run(&mut self, mut progress: impl FnMut(&Progress) -> Result<()>) -> Result<Palette> {
         let mut steps_passed = 0;
         for a in 0..self.max_attempts {
             self.init_centroids();
             for s in 0..self.max_steps {
                 self.calc_segments();
                 if self.points_changed == 0 {
-                    self.update_stats(tui, a, s, steps_passed)?;
+                    progress(&self.progress(a, s, steps_passed, true))?;
                     steps_passed += s;
                     break;
                 }
                 self.calc_centroids();
-                if self.status.timer.needs_update() || s == self.max_steps - 1 {
-                    self.update_stats(tui, a, s, steps_passed)?;
-                }
+                progress(&self.progress(a, s, steps_passed, s == self.max_steps - 1))?;
                 if s == self.max_steps - 1 {
                     steps_passed += s;
                 }
@@ -264,3 +282,22 @@ impl ColorCalc {
         Ok(self.best_palette.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fewer_colors_than_requested() {
+        let mut image = Plane::new(4, 4, IntColor::new(10, 20, 30));
+        image.set(0, 0, IntColor::new(200, 0, 0));
+        image.set(3, 3, IntColor::new(0, 0, 200));
+        let mut colors = ColorData::new();
+        colors.add_plane(&image);
+        let mut calc = ColorCalc::new(16, colors, 2, 10);
+        assert_eq!(calc.total_colors(), 3);
+        let palette = calc.run(|_| Ok(())).unwrap();
+        assert_eq!(palette.len(), 3);
+        assert_eq!(IntColor::from(palette.get(0)), IntColor::new(10, 20, 30));
+    }
+}
@@ -0,0 +1,14 @@
+//! Library interface of the RVC tools. Encoding is assembled from pipeline stages: a source of
+//! RGB frames, optional preprocessors, a quantizer that maps frames to palette indices and a frame
+//! coder that hands the stream over to a muxer. Palettes and dither matrices are generated here
+//! as well.
+
+pub mod colorcalc;
+pub mod pattern;
+pub mod pipeline;
+pub mod preprocess;
+pub mod quantize;
+pub mod source;
+
+pub use rvc_codec as codec;
+pub use rvc_shared as shared;
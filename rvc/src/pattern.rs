@@ -0,0 +1,60 @@
+use anyhow::{Result, bail};
+use rvc_shared::dmatrix::DitherMatrix;
+
+mod bluenoise;
+
+const BAYER2: [u32; 4] = [0, 2, 3, 1];
+
+/// Bayer matrix of `size` x `size` with as many levels as cells. The size must be a power of two.
+pub fn bayer(size: u32) -> Result<DitherMatrix> {
+    if size < 2 {
+        bail!("Size must be at least 2");
+    }
+    if !size.is_power_of_two() {
+        bail!("Size must be power of two.");
+    }
+
+    let mut pattern = DitherMatrix::new(size, size, size * size);
+
+    let mut block_size = 2;
+    loop {
+        let step = size / block_size;
+
+        for by in 0..step {
+            for bx in 0..step {
+                for py in 0..2 {
+                    for px in 0..2 {
+                        let color = BAYER2[(px + 2 * py) as usize];
+                        for iy in 0..block_size / 2 {
+                            for ix in 0..block_size / 2 {
+                                let fx = bx * block_size + px * block_size / 2 + ix;
+                                let fy = by * block_size + py * block_size / 2 + iy;
+                                let prev_color = pattern.get(fx, fy);
+                                pattern.set(fx, fy, prev_color * 4 + color);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        block_size *= 2;
+        if block_size > size {
+            break;
+        }
+    }
+    Ok(pattern)
+}
+
+/// Blue noise matrix of `width` x `height` with pixels spread evenly over `levels`.
+pub fn blue_noise(width: u32, height: u32, levels: u32) -> DitherMatrix {
+    let points = bluenoise::generate_points(width, height);
+
+    let divisor = points.len() as f64 / levels as f64;
+    let mut pattern = DitherMatrix::new(width, height, levels);
+
+    for (i, point) in points.iter().enumerate() {
+        let level = (i as f64 / divisor).floor() as u32;
+        pattern.set(point.x, point.y, level);
+    }
+    pattern
+}
@@ -31,9 +31,6 @@ fn apply_point(x: u32, y: u32, add: bool, image: &mut Plane<bool>, mask: &mut Pl
         let ylut = if yp < y { mask.height + yp - y } else { yp - y };
         for xp in 0..mask.width {
             let xlut = if xp < x { mask.width + xp - x } else { xp - x };
-            if xlut >= mask.width || ylut >= mask.height {
-                println!("error");
-            }
             mask.set(xp, yp, mask.get(xp, yp) + lut.get(xlut, ylut) * sign);
         }
     }
@@ -89,6 +86,7 @@ fn find_cluster(image: &Plane<bool>, mask: &Plane<f64>) -> Point {
     cluster_point
 }
 
+/// Orders all pixels of a `width` x `height` tile with the void-and-cluster method.
 pub fn generate_points(width: u32, height: u32) -> Vec<Point> {
     let mut res = Plane::new(width, height, false);
     let mut energy_mask = Plane::new(width, height, 0.0);
@@ -97,7 +95,6 @@ pub fn generate_points(width: u32, height: u32) -> Vec<Point> {
     let first_points_count = start_fill(&mut res, &mut energy_mask, &lut, 0.1);
     let mut points = vec![Point { x: 0, y: 0 }; first_points_count as usize];
 
-    println!("Step 1");
     loop {
         let cluster = find_cluster(&res, &energy_mask);
         apply_point(cluster.x, cluster.y, false, &mut res, &mut energy_mask, &lut);
@@ -108,7 +105,6 @@ pub fn generate_points(width: u32, height: u32) -> Vec<Point> {
         }
     }
 
-    println!("Step 2");
     let mut step2temp = res.clone();
     let mut step2mask = energy_mask.clone();
 
@@ -118,14 +114,12 @@ pub fn generate_points(width: u32, height: u32) -> Vec<Point> {
         apply_point(cluster.x, cluster.y, false, &mut step2temp, &mut step2mask, &lut);
     }
 
-    println!("Step 3");
     for _ in first_points_count..width * height / 2 {
         let void = find_void(&res, &energy_mask);
         points.push(void);
         apply_point(void.x, void.y, true, &mut res, &mut energy_mask, &lut);
     }
 
-    println!("Step 4");
     let mut negative = Plane::new(width, height, false);
     let mut neg_energy = Plane::new(width, height, 0.0);
     for y in 0..negative.height {
@@ -0,0 +1,398 @@
+use anyhow::Result;
+use rvc_codec::encoder::{Encoder, FrameStats};
+use rvc_shared::{colors::IntColor, container::Muxer, palette::Palette, plane::Plane};
+
+/// Size and rate of the frames of a source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VideoFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fps_num: u32,
+    pub fps_den: u32,
+}
+
+/// Supplies RGB frames.
+pub trait Source {
+    fn format(&self) -> VideoFormat;
+    /// Reads the next frame into `frame`, which has the size of the format. Returns false at the end.
+    fn read_frame(&mut self, frame: &mut Plane<IntColor>) -> Result<bool>;
+}
+
+/// Supplies interleaved samples in the sample rate and channel count of the stream.
+pub trait AudioSource {
+    /// Appends the next piece of samples and returns their number, 0 at the end.
+    fn read(&mut self, samples: &mut Vec<i16>) -> Result<usize>;
+}
+
+/// Transforms RGB frames before quantization, possibly changing their size.
+pub trait Preprocessor {
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32);
+    fn process(&mut self, input: &Plane<IntColor>, output: &mut Plane<IntColor>) -> Result<()>;
+}
+
+/// Maps RGB frames to palette indices.
+pub trait Quantizer {
+    /// Palette of the last quantized frame, or of the first frame before any.
+    fn palette(&self) -> &Palette;
+    /// Returns true when the palette differs from the one of the previous frame.
+    fn quantize(&mut self, input: &Plane<IntColor>, output: &mut Plane<i32>) -> Result<bool>;
+}
+
+/// Compresses indexed frames into a stream.
+pub trait FrameCoder {
+    /// Switches to another palette from the next frame on.
+    fn set_palette(&mut self, palette: &Palette) -> Result<()>;
+    /// Returns the statistics of the frames completed by this call, which may be held back.
+    fn encode(&mut self, frame: &Plane<i32>) -> Result<Vec<FrameStats>>;
+    /// Completes the frames held back.
+    fn flush(&mut self) -> Result<Vec<FrameStats>>;
+    /// Number of interleaved samples that accompany the next frame.
+    fn audio_samples(&self) -> usize;
+    /// Number of interleaved samples waiting for their frames.
+    fn queued_audio(&self) -> usize;
+    fn push_audio(&mut self, samples: &[i16]);
+}
+
+impl<M: Muxer> FrameCoder for Encoder<M> {
+    fn set_palette(&mut self, palette: &Palette) -> Result<()> {
+        Encoder::set_palette(self, palette)
+    }
+
+    fn encode(&mut self, frame: &Plane<i32>) -> Result<Vec<FrameStats>> {
+        Encoder::encode(self, frame)
+    }
+
+    fn flush(&mut self) -> Result<Vec<FrameStats>> {
+        Encoder::flush(self)
+    }
+
+    fn audio_samples(&self) -> usize {
+        Encoder::audio_samples(self)
+    }
+
+    fn queued_audio(&self) -> usize {
+        Encoder::queued_audio(self)
+    }
+
+    fn push_audio(&mut self, samples: &[i16]) {
+        Encoder::push_audio(self, samples)
+    }
+}
+
+/// Size of the frames that come out of a chain of preprocessors.
+pub fn output_size(format: &VideoFormat, preprocessors: &[Box<dyn Preprocessor>]) -> (u32, u32) {
+    preprocessors
+        .iter()
+        .fold((format.width, format.height), |(width, height), stage| {
+            stage.output_size(width, height)
+        })
+}
+
+/// Encodes a source through all stages. The coder has to be set up for the palette of the
+/// quantizer and the frame size of `output_size`.
+pub struct Pipeline<C: FrameCoder> {
+    source: Box<dyn Source>,
+    audio: Option<Box<dyn AudioSource>>,
+    preprocessors: Vec<Box<dyn Preprocessor>>,
+    quantizer: Box<dyn Quantizer>,
+    coder: C,
+}
+
+impl<C: FrameCoder> Pipeline<C> {
+    pub fn new(
+        source: Box<dyn Source>,
+        preprocessors: Vec<Box<dyn Preprocessor>>,
+        quantizer: Box<dyn Quantizer>,
+        coder: C,
+    ) -> Pipeline<C> {
+        Pipeline {
+            source,
+            audio: None,
+            preprocessors,
+            quantizer,
+            coder,
+        }
+    }
+
+    pub fn set_audio(&mut self, audio: Box<dyn AudioSource>) {
+        self.audio = Some(audio);
+    }
+
+    /// Encodes all frames of the source and returns their number. `progress` is called with
+    /// the number and statistics of every frame once the coder completes it.
+    pub fn run(&mut self, mut progress: impl FnMut(u32, &FrameStats) -> Result<()>) -> Result<u32> {
+        let format = self.source.format();
+        let mut frames = vec![Plane::new(format.width, format.height, IntColor::BLACK)];
+        for stage in self.preprocessors.iter() {
+            let last = &frames[frames.len() - 1];
+            let (width, height) = stage.output_size(last.width, last.height);
+            frames.push(Plane::new(width, height, IntColor::BLACK));
+        }
+        let last = &frames[frames.len() - 1];
+        let mut indices = Plane::new(last.width, last.height, 0i32);
+        let mut samples = vec![];
+
+        let mut number = 0;
+        let mut completed = 0;
+        while self.source.read_frame(&mut frames[0])? {
+            if let Some(audio) = &mut self.audio {
+                while self.coder.queued_audio() < self.coder.audio_samples() {
+                    samples.clear();
+                    if audio.read(&mut samples)? == 0 {
+                        break;
+                    }
+                    self.coder.push_audio(&samples);
+                }
+            }
+            for (i, stage) in self.preprocessors.iter_mut().enumerate() {
+                let (input, output) = frames.split_at_mut(i + 1);
+                stage.process(&input[i], &mut output[0])?;
+            }
+            if self.quantizer.quantize(&frames[frames.len() - 1], &mut indices)? {
+                self.coder.set_palette(self.quantizer.palette())?;
+            }
+            for stats in self.coder.encode(&indices)? {
+                progress(completed, &stats)?;
+                completed += 1;
+            }
+            number += 1;
+        }
+        for stats in self.coder.flush()? {
+            progress(completed, &stats)?;
+            completed += 1;
+        }
+        Ok(number)
+    }
+
+    pub fn into_coder(self) -> C {
+        self.coder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        colorcalc::{ColorCalc, ColorData},
+        preprocess::{Fit, ScaleConfig, Scaler},
+        quantize::{MatrixDither, Posterize},
+    };
+    use rvc_codec::{
+        decoder::Decoder,
+        encoder::{EncoderConfig, SkipError},
+    };
+    use rvc_shared::{colors::FloatColor, container::Header, dmatrix::DitherMatrix};
+    use std::io::Cursor;
+
+    const FORMAT: VideoFormat = VideoFormat {
+        width: 4,
+        height: 2,
+        fps_num: 25,
+        fps_den: 1,
+    };
+
+    /// Frames of a single grey level that grows with the frame number.
+    struct Frames(i32);
+
+    impl Source for Frames {
+        fn format(&self) -> VideoFormat {
+            FORMAT
+        }
+
+        fn read_frame(&mut self, frame: &mut Plane<IntColor>) -> Result<bool> {
+            if self.0 == 5 {
+                return Ok(false);
+            }
+            frame.data.fill(IntColor::new(self.0 * 60, self.0 * 60, self.0 * 60));
+            self.0 += 1;
+            Ok(true)
+        }
+    }
+
+    /// Twelve samples in pieces of three.
+    struct Samples(i16);
+
+    impl AudioSource for Samples {
+        fn read(&mut self, samples: &mut Vec<i16>) -> Result<usize> {
+            if self.0 == 12 {
+                return Ok(0);
+            }
+            samples.extend(self.0..self.0 + 3);
+            self.0 += 3;
+            Ok(3)
+        }
+    }
+
+    /// Doubles the width by repeating every pixel.
+    struct Stretch;
+
+    impl Preprocessor for Stretch {
+        fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+            (width * 2, height)
+        }
+
+        fn process(&mut self, input: &Plane<IntColor>, output: &mut Plane<IntColor>) -> Result<()> {
+            for (i, color) in output.data.iter_mut().enumerate() {
+                *color = input.data[i / 2];
+            }
+            Ok(())
+        }
+    }
+
+    /// Records what reaches the coder, taking five samples per frame. Frames are completed in pairs.
+    #[derive(Default)]
+    struct Recorder {
+        palettes: Vec<(usize, usize)>,
+        frames: Vec<(u32, u32, i32, usize)>,
+        audio: Vec<i16>,
+        used: usize,
+        held: usize,
+    }
+
+    impl Recorder {
+        fn complete(&mut self) -> Vec<FrameStats> {
+            let stats = FrameStats {
+                keyframe: false,
+                size: 0,
+                audio_size: 0,
+                palette_size: 0,
+                level: 0,
+                budget: f64::INFINITY,
+                skip_error: SkipError::default(),
+                scene_cut: false,
+                changed_blocks: 0,
+                cycles: 0,
+            };
+            vec![stats; std::mem::take(&mut self.held)]
+        }
+    }
+
+    impl FrameCoder for Recorder {
+        fn set_palette(&mut self, palette: &Palette) -> Result<()> {
+            self.palettes.push((self.frames.len(), palette.len()));
+            Ok(())
+        }
+
+        fn encode(&mut self, frame: &Plane<i32>) -> Result<Vec<FrameStats>> {
+            self.frames.push((frame.width, frame.height, frame.data[0], self.queued_audio()));
+            self.used = (self.used + self.audio_samples()).min(self.audio.len());
+            self.held += 1;
+            Ok(if self.held == 2 { self.complete() } else { vec![] })
+        }
+
+        fn flush(&mut self) -> Result<Vec<FrameStats>> {
+            Ok(self.complete())
+        }
+
+        fn audio_samples(&self) -> usize {
+            5
+        }
+
+        fn queued_audio(&self) -> usize {
+            self.audio.len() - self.used
+        }
+
+        fn push_audio(&mut self, samples: &[i16]) {
+            self.audio.extend_from_slice(samples);
+        }
+    }
+
+    fn palette(levels: &[i32]) -> Palette {
+        let mut palette = Palette::new();
+        for level in levels {
+            palette.add(FloatColor::new(*level, *level, *level));
+        }
+        palette
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        let preprocessors: Vec<Box<dyn Preprocessor>> = vec![Box::new(Stretch), Box::new(Stretch)];
+        assert_eq!(output_size(&FORMAT, &preprocessors), (16, 2));
+        let mut quantizer = MatrixDither::new(palette(&[0, 60, 120, 180, 240]), DitherMatrix::new(1, 1, 1));
+        quantizer.switch_palette(3, palette(&[240, 0]));
+        let mut pipeline = Pipeline::new(
+            Box::new(Frames(0)),
+            preprocessors,
+            Box::new(quantizer),
+            Recorder::default(),
+        );
+        pipeline.set_audio(Box::new(Samples(0)));
+        let mut numbers = vec![];
+        let count = pipeline
+            .run(|number, _| {
+                numbers.push(number);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(count, 5);
+        assert_eq!(numbers, [0, 1, 2, 3, 4]);
+
+        let recorder = pipeline.into_coder();
+        assert_eq!(recorder.palettes, [(3, 2)]);
+        // Audio is read ahead until a frame's worth is queued or the source runs dry
+        assert_eq!(
+            recorder.frames,
+            [(16, 2, 0, 6), (16, 2, 1, 7), (16, 2, 2, 2), (16, 2, 0, 0), (16, 2, 0, 0)]
+        );
+        assert_eq!(recorder.audio, (0..12).collect::<Vec<i16>>());
+    }
+
+    /// Two red frames twice as wide as high.
+    struct Wide(u32);
+
+    impl Source for Wide {
+        fn format(&self) -> VideoFormat {
+            VideoFormat {
+                width: 16,
+                height: 8,
+                ..FORMAT
+            }
+        }
+
+        fn read_frame(&mut self, frame: &mut Plane<IntColor>) -> Result<bool> {
+            frame.data.fill(IntColor::new(255, 0, 0));
+            self.0 += 1;
+            Ok(self.0 <= 2)
+        }
+    }
+
+    #[test]
+    fn letterbox_border_stays_black() {
+        let config = ScaleConfig {
+            fit: Fit::Letterbox,
+            ..ScaleConfig::new(16, 16)
+        };
+        // Colors are collected from the scaled frames as palcalc does
+        let mut source = Wide(0);
+        let mut frame = Plane::new(16, 8, IntColor::BLACK);
+        let mut scaler = Scaler::new(config).unwrap();
+        let mut colors = ColorData::new();
+        while source.read_frame(&mut frame).unwrap() {
+            colors.add_plane(&scaler.scale(&frame).unwrap());
+        }
+        let palette = ColorCalc::new(16, colors, 2, 10).run(|_| Ok(())).unwrap();
+        assert_eq!(palette.len(), 2);
+
+        let header = Header::new(16, 16, FORMAT.fps_num, FORMAT.fps_den);
+        let encoder = Encoder::new(Cursor::new(vec![]), header, &palette, EncoderConfig::default()).unwrap();
+        let preprocessors: Vec<Box<dyn Preprocessor>> = vec![Box::new(Scaler::new(config).unwrap())];
+        let quantizer = Box::new(Posterize::new(palette));
+        let mut pipeline = Pipeline::new(Box::new(Wide(0)), preprocessors, quantizer, encoder);
+        assert_eq!(pipeline.run(|_, _| Ok(())).unwrap(), 2);
+        let stream = pipeline.into_coder().finish().unwrap().into_inner();
+
+        let mut decoder = Decoder::new(stream.as_slice()).unwrap();
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            let frame = frame.clone();
+            let color = |x, y| IntColor::from(decoder.palette().get(frame.get(x, y)));
+            for x in 0..16 {
+                for y in [0, 3, 12, 15] {
+                    assert_eq!(color(x, y), IntColor::BLACK, "{}, {}", x, y);
+                }
+                assert_eq!(color(x, 8), IntColor::new(255, 0, 0));
+            }
+        }
+        assert_eq!(decoder.position(), 2);
+    }
+}
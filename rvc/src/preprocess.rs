@@ -0,0 +1,302 @@
+use anyhow::{Result, bail};
+use image::{RgbImage, imageops};
+use rvc_shared::{colors::IntColor, plane::Plane};
+use std::str::FromStr;
+
+use crate::pipeline::Preprocessor;
+
+/// Resampling filter of the scaler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos,
+}
+
+impl Filter {
+    fn filter_type(&self) -> imageops::FilterType {
+        match self {
+            Filter::Nearest => imageops::FilterType::Nearest,
+            Filter::Bilinear => imageops::FilterType::Triangle,
+            Filter::Bicubic => imageops::FilterType::CatmullRom,
+            Filter::Lanczos => imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Filter, String> {
+        Ok(match value {
+            "nearest" => Filter::Nearest,
+            "bilinear" => Filter::Bilinear,
+            "bicubic" => Filter::Bicubic,
+            "lanczos" => Filter::Lanczos,
+            _ => {
+                return Err(format!(
+                    "Unknown filter {:?}, expected nearest, bilinear, bicubic or lanczos",
+                    value
+                ));
+            }
+        })
+    }
+}
+
+/// How a source with another aspect ratio than the target fills it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fit {
+    /// Distorts the source to cover the whole target.
+    Stretch,
+    /// Cuts off the edges of the source that stick out.
+    Crop,
+    /// Shows the whole source with borders on two sides.
+    Letterbox,
+}
+
+impl FromStr for Fit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Fit, String> {
+        Ok(match value {
+            "stretch" => Fit::Stretch,
+            "crop" => Fit::Crop,
+            "letterbox" => Fit::Letterbox,
+            _ => return Err(format!("Unknown fit {:?}, expected stretch, crop or letterbox", value)),
+        })
+    }
+}
+
+/// Parses `WIDTHxHEIGHT` of a target resolution.
+pub fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    match value.split_once(['x', 'X']) {
+        Some((width, height)) => match (width.parse(), height.parse()) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+            _ => Err(format!("Invalid size {:?}", value)),
+        },
+        None => Err(String::from("Expected WIDTHxHEIGHT")),
+    }
+}
+
+/// Target resolution and how the source is brought to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaleConfig {
+    pub width: u32,
+    pub height: u32,
+    pub filter: Filter,
+    pub fit: Fit,
+    /// Displayed height of a target pixel relative to its width, 1.2 for 320x200 on a 4:3 screen.
+    pub pixel_aspect: f64,
+    pub border: IntColor,
+}
+
+impl ScaleConfig {
+    pub fn new(width: u32, height: u32) -> ScaleConfig {
+        ScaleConfig {
+            width,
+            height,
+            filter: Filter::Lanczos,
+            fit: Fit::Letterbox,
+            pixel_aspect: 1.0,
+            border: IntColor::BLACK,
+        }
+    }
+}
+
+/// Command line options of the scaler, shared by all tools that read source frames so that they
+/// see the same pixels.
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub struct ScaleArgs {
+    /// Scale frames to WIDTHxHEIGHT, the source size by default
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+    /// Resampling filter: nearest, bilinear, bicubic or lanczos
+    #[arg(long, default_value = "lanczos", requires = "size")]
+    pub filter: Filter,
+    /// How a source of another aspect ratio fills the frame: stretch, crop or letterbox
+    #[arg(long, default_value = "letterbox", requires = "size")]
+    pub fit: Fit,
+    /// Displayed height of a pixel relative to its width, 1.2 for 320x200 on a 4:3 screen
+    #[arg(long, default_value_t = 1.0, requires = "size")]
+    pub pixel_aspect: f64,
+}
+
+impl ScaleArgs {
+    /// Configuration of the options, `None` when frames keep the source size.
+    pub fn config(&self) -> Option<ScaleConfig> {
+        self.size.map(|(width, height)| ScaleConfig {
+            filter: self.filter,
+            fit: self.fit,
+            pixel_aspect: self.pixel_aspect,
+            ..ScaleConfig::new(width, height)
+        })
+    }
+
+    pub fn scaler(&self) -> Result<Option<Scaler>> {
+        self.config().map(Scaler::new).transpose()
+    }
+}
+
+/// Area of a frame as x, y, width and height.
+type Rect = (u32, u32, u32, u32);
+
+/// Centers `length` within `total`.
+fn center(total: u32, length: u32) -> (u32, u32) {
+    let length = length.clamp(1, total);
+    ((total - length) / 2, length)
+}
+
+/// Resamples frames to the target resolution of a `ScaleConfig`.
+pub struct Scaler {
+    config: ScaleConfig,
+}
+
+impl Scaler {
+    pub fn new(config: ScaleConfig) -> Result<Scaler> {
+        if config.width == 0 || config.height == 0 {
+            bail!("Target size must not be 0");
+        }
+        if !(config.pixel_aspect.is_finite() && config.pixel_aspect > 0.0) {
+            bail!("Invalid pixel aspect ratio {}", config.pixel_aspect);
+        }
+        Ok(Scaler { config })
+    }
+
+    /// Scales `input` into a new frame of the target size.
+    pub fn scale(&mut self, input: &Plane<IntColor>) -> Result<Plane<IntColor>> {
+        let mut output = Plane::new(self.config.width, self.config.height, self.config.border);
+        self.process(input, &mut output)?;
+        Ok(output)
+    }
+
+    /// Part of the source that is shown and the part of the target it lands in.
+    fn layout(&self, width: u32, height: u32) -> (Rect, Rect) {
+        let config = &self.config;
+        let full_source = (0, 0, width, height);
+        let full_target = (0, 0, config.width, config.height);
+        // Aspect ratios as displayed, the source having square pixels
+        let source_aspect = width as f64 / height as f64;
+        let target_aspect = config.width as f64 / (config.height as f64 * config.pixel_aspect);
+        match config.fit {
+            Fit::Stretch => (full_source, full_target),
+            Fit::Crop if source_aspect > target_aspect => {
+                let (x, width) = center(width, (height as f64 * target_aspect).round() as u32);
+                ((x, 0, width, height), full_target)
+            }
+            Fit::Crop => {
+                let (y, height) = center(height, (width as f64 / target_aspect).round() as u32);
+                ((0, y, width, height), full_target)
+            }
+            Fit::Letterbox if source_aspect > target_aspect => {
+                let scaled = config.width as f64 / (source_aspect * config.pixel_aspect);
+                let (y, height) = center(config.height, scaled.round() as u32);
+                (full_source, (0, y, config.width, height))
+            }
+            Fit::Letterbox => {
+                let scaled = config.height as f64 * config.pixel_aspect * source_aspect;
+                let (x, width) = center(config.width, scaled.round() as u32);
+                (full_source, (x, 0, width, config.height))
+            }
+        }
+    }
+}
+
+impl Preprocessor for Scaler {
+    fn output_size(&self, _width: u32, _height: u32) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    fn process(&mut self, input: &Plane<IntColor>, output: &mut Plane<IntColor>) -> Result<()> {
+        let ((sx, sy, sw, sh), (tx, ty, tw, th)) = self.layout(input.width, input.height);
+        let source = RgbImage::from_fn(sw, sh, |x, y| {
+            let color = input.get(sx + x, sy + y);
+            image::Rgb([color.r as u8, color.g as u8, color.b as u8])
+        });
+        let scaled = imageops::resize(&source, tw, th, self.config.filter.filter_type());
+        output.data.fill(self.config.border);
+        for (x, y, pixel) in scaled.enumerate_pixels() {
+            output.set(
+                tx + x,
+                ty + y,
+                IntColor::new(pixel.0[0] as i32, pixel.0[1] as i32, pixel.0[2] as i32),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(source: (u32, u32), target: (u32, u32), fit: Fit, pixel_aspect: f64) -> (Rect, Rect) {
+        let config = ScaleConfig {
+            fit,
+            pixel_aspect,
+            ..ScaleConfig::new(target.0, target.1)
+        };
+        Scaler::new(config).unwrap().layout(source.0, source.1)
+    }
+
+    #[test]
+    fn fits() {
+        let full = ((0, 0, 160, 90), (0, 0, 80, 80));
+        assert_eq!(layout((160, 90), (80, 80), Fit::Stretch, 1.0), full);
+        assert_eq!(layout((160, 90), (80, 80), Fit::Letterbox, 1.0), ((0, 0, 160, 90), (0, 17, 80, 45)));
+        assert_eq!(layout((90, 160), (80, 80), Fit::Letterbox, 1.0), ((0, 0, 90, 160), (17, 0, 45, 80)));
+        assert_eq!(layout((160, 90), (80, 80), Fit::Crop, 1.0), ((35, 0, 90, 90), (0, 0, 80, 80)));
+        assert_eq!(layout((90, 160), (80, 80), Fit::Crop, 1.0), ((0, 35, 90, 90), (0, 0, 80, 80)));
+    }
+
+    #[test]
+    fn pixel_aspect() {
+        // 320x200 with tall pixels shows 4:3 sources in full
+        for fit in [Fit::Letterbox, Fit::Crop] {
+            assert_eq!(layout((640, 480), (320, 200), fit, 1.2), ((0, 0, 640, 480), (0, 0, 320, 200)));
+        }
+        assert_eq!(layout((100, 100), (320, 200), Fit::Letterbox, 1.2), ((0, 0, 100, 100), (40, 0, 240, 200)));
+        assert_eq!(layout((100, 100), (320, 200), Fit::Crop, 1.2), ((0, 12, 100, 75), (0, 0, 320, 200)));
+        assert!(Scaler::new(ScaleConfig::new(0, 200)).is_err());
+        for pixel_aspect in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let config = ScaleConfig {
+                pixel_aspect,
+                ..ScaleConfig::new(320, 200)
+            };
+            assert!(Scaler::new(config).is_err());
+        }
+    }
+
+    #[test]
+    fn letterbox_border() {
+        let red = IntColor::new(255, 0, 0);
+        let blue = IntColor::new(0, 0, 255);
+        let mut scaler = Scaler::new(ScaleConfig {
+            filter: Filter::Nearest,
+            border: blue,
+            ..ScaleConfig::new(4, 4)
+        })
+        .unwrap();
+        assert_eq!(scaler.output_size(4, 2), (4, 4));
+        let mut output = Plane::new(4, 4, IntColor::BLACK);
+        scaler.process(&Plane::new(4, 2, red), &mut output).unwrap();
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(output.get(x, y), if y == 0 || y == 3 { blue } else { red }, "{}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(parse_size("320x200"), Ok((320, 200)));
+        assert_eq!(parse_size("64X48"), Ok((64, 48)));
+        for bad in ["320", "0x200", "320x", "ax2"] {
+            assert!(parse_size(bad).is_err(), "{}", bad);
+        }
+        assert_eq!("bicubic".parse(), Ok(Filter::Bicubic));
+        assert!("cubic".parse::<Filter>().is_err());
+        assert_eq!("crop".parse(), Ok(Fit::Crop));
+        assert!("fill".parse::<Fit>().is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use rvc_shared::{
+    colors::IntColor,
+    dmatrix::DitherMatrix,
+    indexing::{convert_fs, convert_matrix, convert_posterize},
+    palette::Palette,
+    plane::Plane,
+};
+
+use crate::pipeline::Quantizer;
+
+/// Replaces every pixel with the nearest palette color.
+pub struct Posterize {
+    palette: Palette,
+}
+
+impl Posterize {
+    pub fn new(palette: Palette) -> Posterize {
+        Posterize { palette }
+    }
+}
+
+impl Quantizer for Posterize {
+    fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    fn quantize(&mut self, input: &Plane<IntColor>, output: &mut Plane<i32>) -> Result<bool> {
+        convert_posterize(input, output, &self.palette);
+        Ok(false)
+    }
+}
+
+/// Floyd-Steinberg error diffusion.
+pub struct ErrorDiffusion {
+    palette: Palette,
+}
+
+impl ErrorDiffusion {
+    pub fn new(palette: Palette) -> ErrorDiffusion {
+        ErrorDiffusion { palette }
+    }
+}
+
+impl Quantizer for ErrorDiffusion {
+    fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    fn quantize(&mut self, input: &Plane<IntColor>, output: &mut Plane<i32>) -> Result<bool> {
+        convert_fs(input, output, &self.palette);
+        Ok(false)
+    }
+}
+
+/// Ordered dithering with a dither matrix. It keeps the dither pattern still between frames,
+/// which suits delta coding. The palette can be switched at given frames.
+pub struct MatrixDither {
+    palette: Palette,
+    matrix: DitherMatrix,
+    /// Palettes that take over at a frame number, sorted by the number.
+    switches: Vec<(u32, Palette)>,
+    frame: u32,
+}
+
+impl MatrixDither {
+    pub fn new(palette: Palette, matrix: DitherMatrix) -> MatrixDither {
+        MatrixDither {
+            palette,
+            matrix,
+            switches: vec![],
+            frame: 0,
+        }
+    }
+
+    /// Uses `palette` from frame number `frame` on.
+    pub fn switch_palette(&mut self, frame: u32, palette: Palette) {
+        let position = self.switches.partition_point(|(other, _)| *other <= frame);
+        self.switches.insert(position, (frame, palette));
+    }
+}
+
+impl Quantizer for MatrixDither {
+    fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    fn quantize(&mut self, input: &Plane<IntColor>, output: &mut Plane<i32>) -> Result<bool> {
+        let switches = self.switches.partition_point(|(frame, _)| *frame <= self.frame);
+        let changed = switches > 0;
+        if let Some((_, palette)) = self.switches.drain(..switches).next_back() {
+            self.palette = palette;
+        }
+        convert_matrix(input, output, &self.palette, &self.matrix);
+        self.frame += 1;
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rvc_shared::colors::FloatColor;
+
+    fn palette(levels: &[i32]) -> Palette {
+        let mut palette = Palette::new();
+        for level in levels {
+            palette.add(FloatColor::new(*level, *level, *level));
+        }
+        palette
+    }
+
+    fn grey(width: u32, height: u32, level: i32) -> Plane<IntColor> {
+        Plane::new(width, height, IntColor::new(level, level, level))
+    }
+
+    #[test]
+    fn posterize_picks_nearest() {
+        let mut quantizer = Posterize::new(palette(&[0, 85, 170, 255]));
+        let mut input = grey(4, 1, 0);
+        for (color, level) in input.data.iter_mut().zip([250, 20, 90, 160]) {
+            *color = IntColor::new(level, level, level);
+        }
+        let mut output = Plane::new(4, 1, -1);
+        assert!(!quantizer.quantize(&input, &mut output).unwrap());
+        assert_eq!(output.data, [3, 0, 1, 2]);
+    }
+
+    #[test]
+    fn dithering_keeps_average() {
+        let input = grey(4, 4, 128);
+        let mut output = Plane::new(4, 4, -1);
+        let mut matrix = DitherMatrix::new(2, 2, 4);
+        for (i, level) in [0, 2, 3, 1].into_iter().enumerate() {
+            matrix.set(i as u32 % 2, i as u32 / 2, level);
+        }
+        let mut quantizer = MatrixDither::new(palette(&[0, 255]), matrix);
+        quantizer.quantize(&input, &mut output).unwrap();
+        // Every 2x2 tile gets two white pixels at the same place
+        assert_eq!(output.data, [0, 1, 0, 1, 1, 0, 1, 0, 0, 1, 0, 1, 1, 0, 1, 0]);
+
+        let mut quantizer = ErrorDiffusion::new(palette(&[0, 255]));
+        quantizer.quantize(&input, &mut output).unwrap();
+        let white = output.data.iter().filter(|index| **index == 1).count();
+        assert!((6..=10).contains(&white), "{} white pixels", white);
+    }
+
+    #[test]
+    fn matrix_dither_switches_palettes() {
+        let mut quantizer = MatrixDither::new(palette(&[0, 255]), DitherMatrix::new(1, 1, 1));
+        // Switches may be added in any order
+        quantizer.switch_palette(4, palette(&[255, 0]));
+        quantizer.switch_palette(2, palette(&[255, 128, 0]));
+        let input = grey(2, 2, 255);
+        let mut output = Plane::new(2, 2, -1);
+        let mut results = vec![];
+        for _ in 0..6 {
+            let changed = quantizer.quantize(&input, &mut output).unwrap();
+            results.push((changed, quantizer.palette().len(), output.data[0]));
+        }
+        assert_eq!(
+            results,
+            [(false, 2, 1), (false, 2, 1), (true, 3, 0), (false, 3, 0), (true, 2, 0), (false, 2, 0)]
+        );
+    }
+}
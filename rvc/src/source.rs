@@ -0,0 +1,150 @@
+use anyhow::{Result, bail};
+use image::ImageReader;
+use rvc_codec::audio::resample::{Resampler, downmix};
+use rvc_shared::{colors::IntColor, plane::Plane, wav::WavReader, y4m::Y4mReader};
+use std::{
+    fs,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::pipeline::{AudioSource, Source, VideoFormat};
+
+/// Frame rate of image sequences.
+pub const IMAGE_FPS: u32 = 25;
+
+/// Number of samples per channel read from a WAV file at a time.
+const WAV_PIECE: usize = 1024;
+
+fn is_y4m(filename: &Path) -> bool {
+    filename.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("y4m"))
+}
+
+fn open_y4m(filename: &Path) -> Result<Y4mReader<BufReader<fs::File>>> {
+    Y4mReader::new(BufReader::new(fs::File::open(filename)?))
+}
+
+fn load_image(filename: &Path, image: &mut Plane<IntColor>) -> Result<()> {
+    let file = ImageReader::open(filename)?.decode()?.to_rgb8();
+    if file.width() != image.width || file.height() != image.height {
+        bail!("{:?} has different size than the first frame", filename);
+    }
+    for (file_pixel, img_pixel) in file.pixels().zip(image.data.iter_mut()) {
+        img_pixel.r = file_pixel.0[0] as i32;
+        img_pixel.g = file_pixel.0[1] as i32;
+        img_pixel.b = file_pixel.0[2] as i32;
+    }
+    Ok(())
+}
+
+/// Reads frames from a list of Y4M videos and still images, one frame per image. The format
+/// comes from the first file, images run at `IMAGE_FPS`.
+pub struct FileSource {
+    files: Vec<PathBuf>,
+    next: usize,
+    video: Option<Y4mReader<BufReader<fs::File>>>,
+    format: VideoFormat,
+}
+
+impl FileSource {
+    pub fn new(files: Vec<PathBuf>) -> Result<FileSource> {
+        let format = match files.first() {
+            Some(filename) if is_y4m(filename) => {
+                let video = open_y4m(filename)?;
+                VideoFormat {
+                    width: video.width,
+                    height: video.height,
+                    fps_num: video.fps_num,
+                    fps_den: video.fps_den,
+                }
+            }
+            Some(filename) => {
+                let (width, height) = ImageReader::open(filename)?.into_dimensions()?;
+                VideoFormat {
+                    width,
+                    height,
+                    fps_num: IMAGE_FPS,
+                    fps_den: 1,
+                }
+            }
+            None => bail!("No source files"),
+        };
+        Ok(FileSource {
+            files,
+            next: 0,
+            video: None,
+            format,
+        })
+    }
+}
+
+impl Source for FileSource {
+    fn format(&self) -> VideoFormat {
+        self.format
+    }
+
+    fn read_frame(&mut self, frame: &mut Plane<IntColor>) -> Result<bool> {
+        loop {
+            if let Some(video) = &mut self.video {
+                if video.read_frame(frame)? {
+                    return Ok(true);
+                }
+                self.video = None;
+            }
+            let filename = match self.files.get(self.next) {
+                Some(filename) => filename,
+                None => return Ok(false),
+            };
+            self.next += 1;
+            if is_y4m(filename) {
+                self.video = Some(open_y4m(filename)?);
+            } else {
+                load_image(filename, frame)?;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Sound of a WAV file, converted to the sample rate and channel count of the stream.
+pub struct WavSource<R: Read> {
+    wav: WavReader<R>,
+    resampler: Resampler,
+    mono: bool,
+    samples: Vec<i16>,
+}
+
+impl<R: Read> WavSource<R> {
+    /// Converts to `sample_rate`, and to a single channel if `mono` is set.
+    pub fn new(wav: WavReader<R>, sample_rate: u32, mono: bool) -> Result<WavSource<R>> {
+        let channels = if mono { 1 } else { wav.channels };
+        Ok(WavSource {
+            resampler: Resampler::new(channels, wav.sample_rate, sample_rate)?,
+            mono,
+            samples: vec![0; WAV_PIECE * wav.channels as usize],
+            wav,
+        })
+    }
+}
+
+impl<R: Read> AudioSource for WavSource<R> {
+    fn read(&mut self, samples: &mut Vec<i16>) -> Result<usize> {
+        let start = samples.len();
+        loop {
+            let count = self.wav.read_samples(&mut self.samples)?;
+            if count == 0 {
+                return Ok(0);
+            }
+            if self.mono {
+                self.resampler
+                    .process(&downmix(&self.samples[..count], self.wav.channels), samples);
+            } else {
+                self.resampler.process(&self.samples[..count], samples);
+            }
+            // Downsampling a short piece may not produce any output yet
+            if samples.len() > start {
+                return Ok(samples.len() - start);
+            }
+        }
+    }
+}
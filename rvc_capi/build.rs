@@ -0,0 +1,20 @@
+//! Generates the C header into `OUT_DIR`. Set `RVC_UPDATE_HEADER` to also update the copy in
+//! `include/` that is shipped with the library.
+
+use std::{env, fs, path::Path};
+
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let header = Path::new(&env::var("OUT_DIR").unwrap()).join("rvc.h");
+    cbindgen::generate(&crate_dir)
+        .expect("Unable to generate C header")
+        .write_to_file(&header);
+    if env::var_os("RVC_UPDATE_HEADER").is_some() {
+        fs::copy(&header, Path::new(&crate_dir).join("include/rvc.h")).expect("Unable to update C header");
+    }
+    // The C example test builds for the same target
+    println!("cargo:rustc-env=TARGET={}", env::var("TARGET").unwrap());
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=RVC_UPDATE_HEADER");
+}
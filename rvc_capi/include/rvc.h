@@ -0,0 +1,131 @@
+#ifndef RVC_H
+#define RVC_H
+
+/* Generated with cbindgen from rvc_capi, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define RVC_OK 0
+
+/**
+ * The stream has no more frames.
+ */
+#define RVC_END 1
+
+/**
+ * Decoding failed, `rvc_error` describes why.
+ */
+#define RVC_ERROR -1
+
+/**
+ * A pointer is null or a buffer is too small.
+ */
+#define RVC_INVALID_ARGUMENT -2
+
+/**
+ * Opened stream.
+ */
+typedef struct RvcDecoder RvcDecoder;
+
+typedef struct RvcInfo {
+  uint32_t width;
+  uint32_t height;
+  uint32_t fps_num;
+  uint32_t fps_den;
+  uint32_t frame_count;
+  /**
+   * Zero when the stream has no sound.
+   */
+  uint32_t audio_rate;
+  uint32_t audio_channels;
+} RvcInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens a stream stored in `data`. Returns null if it can't be read. The decoder reads the
+ * stream from `data` on every call, it is not copied.
+ *
+ * # Safety
+ * `data` must point to `size` readable bytes that stay valid and unchanged until `rvc_close`.
+ */
+struct RvcDecoder *rvc_open(const uint8_t *data, size_t size);
+
+/**
+ * Frees a decoder returned by `rvc_open`. The stream data may be released afterwards.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open` and must not be used afterwards.
+ */
+void rvc_close(struct RvcDecoder *decoder);
+
+/**
+ * Fills `info` with the stream properties.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open` and `info` must point to writable memory.
+ */
+int32_t rvc_info(const struct RvcDecoder *decoder, struct RvcInfo *info);
+
+/**
+ * Decodes the next frame into `frame`, one palette index per pixel in rows of `width` bytes.
+ * Returns `RVC_END` after the last frame.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open` and `frame` must point to `size` writable bytes.
+ */
+int32_t rvc_next_frame(struct RvcDecoder *decoder, uint8_t *frame, size_t size);
+
+/**
+ * Copies the current palette to `rgb` as 8-bit RGB triplets and returns the number of colors.
+ * Only as many colors as fit in `size` bytes are copied. The palette may change between frames.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open` and `rgb` must point to `size` writable bytes.
+ */
+int32_t rvc_palette(const struct RvcDecoder *decoder, uint8_t *rgb, size_t size);
+
+/**
+ * Copies the interleaved 16-bit samples of the last decoded frame to `samples` and returns
+ * their number. Only as many samples as fit in `count` are copied.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open` and `samples` must point to `count` writable samples.
+ */
+int32_t rvc_audio(const struct RvcDecoder *decoder, int16_t *samples, size_t count);
+
+/**
+ * Positions the decoder so that the next decoded frame is `frame`.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open`.
+ */
+int32_t rvc_seek(struct RvcDecoder *decoder, uint32_t frame);
+
+/**
+ * Number of the frame that the next call to `rvc_next_frame` will return.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open`.
+ */
+uint32_t rvc_position(const struct RvcDecoder *decoder);
+
+/**
+ * Message of the last error, valid until the next call with the same decoder.
+ *
+ * # Safety
+ * `decoder` must come from `rvc_open`.
+ */
+const char *rvc_error(const struct RvcDecoder *decoder);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RVC_H */
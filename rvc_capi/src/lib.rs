@@ -0,0 +1,276 @@
+//! C interface of the decoder, built on the `no_std` one. Streams are decoded from a memory
+//! buffer owned by the caller, which has to stay valid and unchanged until the decoder is closed.
+//! Panics don't unwind into the caller, the entry points report them as failures.
+
+use rvc_nostd::{
+    Decoder, Frame, audio,
+    container::{Header, MAX_COLORS},
+};
+use std::{
+    ffi::{CString, c_char},
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+pub const RVC_OK: i32 = 0;
+/// The stream has no more frames.
+pub const RVC_END: i32 = 1;
+/// Decoding failed, `rvc_error` describes why.
+pub const RVC_ERROR: i32 = -1;
+/// A pointer is null or a buffer is too small.
+pub const RVC_INVALID_ARGUMENT: i32 = -2;
+
+/// Opened stream.
+pub struct RvcDecoder {
+    /// Borrows the stream data of the caller and `memory` for as long as the decoder is open.
+    decoder: Decoder<'static>,
+    /// Framebuffer followed by the scratch buffer of `decoder`.
+    memory: *mut [u8],
+    /// Interleaved samples that accompany the last decoded frame.
+    audio: Vec<i16>,
+    error: CString,
+}
+
+#[repr(C)]
+pub struct RvcInfo {
+    pub width: u32,
+    pub height: u32,
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub frame_count: u32,
+    /// Zero when the stream has no sound.
+    pub audio_rate: u32,
+    pub audio_channels: u32,
+}
+
+impl RvcDecoder {
+    fn fail(&mut self, error: impl fmt::Display) -> i32 {
+        self.error = CString::new(error.to_string()).unwrap_or_default();
+        RVC_ERROR
+    }
+
+    /// Runs a call that may fail on the decoder, a panic fails it as well.
+    fn run(&mut self, body: impl FnOnce(&mut RvcDecoder) -> i32) -> i32 {
+        match panic::catch_unwind(AssertUnwindSafe(|| body(self))) {
+            Ok(result) => result,
+            Err(_) => self.fail("Decoder panicked"),
+        }
+    }
+
+    /// Decodes the sound that came with `frame`.
+    fn decode_audio(&mut self, frame: &Frame) -> rvc_nostd::Result<()> {
+        self.audio.clear();
+        let header = self.decoder.header();
+        if let (Some(format), Some(data)) = (header.audio, frame.audio) {
+            self.audio
+                .resize(header.audio_samples(frame.number) * format.channels as usize, 0);
+            audio::decode(format.codec, format.channels, data, &mut self.audio)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RvcDecoder {
+    fn drop(&mut self) {
+        // The decoder isn't used anymore, its borrow of the memory ends here
+        drop(unsafe { Box::from_raw(self.memory) });
+    }
+}
+
+/// Runs the body of an entry point and returns `failure` if it panics.
+fn guard<T>(failure: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(failure)
+}
+
+/// Opens a stream stored in `data`. Returns null if it can't be read. The decoder reads the
+/// stream from `data` on every call, it is not copied.
+///
+/// # Safety
+/// `data` must point to `size` readable bytes that stay valid and unchanged until `rvc_close`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_open(data: *const u8, size: usize) -> *mut RvcDecoder {
+    if data.is_null() {
+        return ptr::null_mut();
+    }
+    guard(ptr::null_mut(), || {
+        // The caller keeps the data valid for as long as the decoder is open
+        let data = unsafe { slice::from_raw_parts(data, size) };
+        let header = match Header::parse(data) {
+            Ok(header) => header,
+            Err(_) => return ptr::null_mut(),
+        };
+        let memory = Box::into_raw(vec![0; header.frame_size() + header.scratch_size()].into_boxed_slice());
+        let (frame, scratch) = unsafe { &mut *memory }.split_at_mut(header.frame_size());
+        match Decoder::new(data, frame, scratch) {
+            Ok(decoder) => Box::into_raw(Box::new(RvcDecoder {
+                decoder,
+                memory,
+                audio: vec![],
+                error: CString::default(),
+            })),
+            Err(_) => {
+                drop(unsafe { Box::from_raw(memory) });
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Frees a decoder returned by `rvc_open`. The stream data may be released afterwards.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open` and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_close(decoder: *mut RvcDecoder) {
+    if !decoder.is_null() {
+        guard((), || drop(unsafe { Box::from_raw(decoder) }));
+    }
+}
+
+/// Fills `info` with the stream properties.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open` and `info` must point to writable memory.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_info(decoder: *const RvcDecoder, info: *mut RvcInfo) -> i32 {
+    let (decoder, info) = match (unsafe { decoder.as_ref() }, unsafe { info.as_mut() }) {
+        (Some(decoder), Some(info)) => (decoder, info),
+        _ => return RVC_INVALID_ARGUMENT,
+    };
+    guard(RVC_ERROR, || {
+        let header = decoder.decoder.header();
+        *info = RvcInfo {
+            width: header.width,
+            height: header.height,
+            fps_num: header.fps_num,
+            fps_den: header.fps_den,
+            frame_count: header.frame_count,
+            audio_rate: header.audio.map_or(0, |audio| audio.sample_rate),
+            audio_channels: header.audio.map_or(0, |audio| audio.channels),
+        };
+        RVC_OK
+    })
+}
+
+/// Decodes the next frame into `frame`, one palette index per pixel in rows of `width` bytes.
+/// Returns `RVC_END` after the last frame.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open` and `frame` must point to `size` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_next_frame(decoder: *mut RvcDecoder, frame: *mut u8, size: usize) -> i32 {
+    let decoder = match unsafe { decoder.as_mut() } {
+        Some(decoder) => decoder,
+        None => return RVC_INVALID_ARGUMENT,
+    };
+    if frame.is_null() || size < decoder.decoder.header().frame_size() {
+        return RVC_INVALID_ARGUMENT;
+    }
+    let frame = unsafe { slice::from_raw_parts_mut(frame, size) };
+    decoder.run(|decoder| match decoder.decoder.next_frame() {
+        Ok(Some(decoded)) => {
+            let indices = decoder.decoder.frame();
+            frame[..indices.len()].copy_from_slice(indices);
+            match decoder.decode_audio(&decoded) {
+                Ok(()) => RVC_OK,
+                Err(error) => decoder.fail(error),
+            }
+        }
+        Ok(None) => RVC_END,
+        Err(error) => decoder.fail(error),
+    })
+}
+
+/// Copies the current palette to `rgb` as 8-bit RGB triplets and returns the number of colors.
+/// Only as many colors as fit in `size` bytes are copied. The palette may change between frames.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open` and `rgb` must point to `size` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_palette(decoder: *const RvcDecoder, rgb: *mut u8, size: usize) -> i32 {
+    let decoder = match unsafe { decoder.as_ref() } {
+        Some(decoder) => decoder,
+        None => return RVC_INVALID_ARGUMENT,
+    };
+    if rgb.is_null() {
+        return RVC_INVALID_ARGUMENT;
+    }
+    let rgb = unsafe { slice::from_raw_parts_mut(rgb, size) };
+    guard(RVC_ERROR, || {
+        let decoder = &decoder.decoder;
+        // Colors as of the last decoded frame
+        let mut cycled = [0; MAX_COLORS * 3];
+        if decoder
+            .cycled_palette(decoder.position().saturating_sub(1), &mut cycled)
+            .is_err()
+        {
+            return RVC_ERROR;
+        }
+        let copied = (decoder.colors() * 3).min(size / 3 * 3);
+        rgb[..copied].copy_from_slice(&cycled[..copied]);
+        decoder.colors() as i32
+    })
+}
+
+/// Copies the interleaved 16-bit samples of the last decoded frame to `samples` and returns
+/// their number. Only as many samples as fit in `count` are copied.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open` and `samples` must point to `count` writable samples.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_audio(decoder: *const RvcDecoder, samples: *mut i16, count: usize) -> i32 {
+    let decoder = match unsafe { decoder.as_ref() } {
+        Some(decoder) => decoder,
+        None => return RVC_INVALID_ARGUMENT,
+    };
+    if samples.is_null() {
+        return RVC_INVALID_ARGUMENT;
+    }
+    let samples = unsafe { slice::from_raw_parts_mut(samples, count) };
+    guard(RVC_ERROR, || {
+        let audio = &decoder.audio;
+        let copied = audio.len().min(count);
+        samples[..copied].copy_from_slice(&audio[..copied]);
+        audio.len() as i32
+    })
+}
+
+/// Positions the decoder so that the next decoded frame is `frame`.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_seek(decoder: *mut RvcDecoder, frame: u32) -> i32 {
+    let decoder = match unsafe { decoder.as_mut() } {
+        Some(decoder) => decoder,
+        None => return RVC_INVALID_ARGUMENT,
+    };
+    decoder.run(|decoder| match decoder.decoder.seek(frame) {
+        Ok(()) => RVC_OK,
+        Err(error) => decoder.fail(error),
+    })
+}
+
+/// Number of the frame that the next call to `rvc_next_frame` will return.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_position(decoder: *const RvcDecoder) -> u32 {
+    let decoder = unsafe { decoder.as_ref() };
+    guard(0, || decoder.map_or(0, |decoder| decoder.decoder.position()))
+}
+
+/// Message of the last error, valid until the next call with the same decoder.
+///
+/// # Safety
+/// `decoder` must come from `rvc_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rvc_error(decoder: *const RvcDecoder) -> *const c_char {
+    let decoder = unsafe { decoder.as_ref() };
+    guard(ptr::null(), || match decoder {
+        Some(decoder) => decoder.error.as_ptr(),
+        None => ptr::null(),
+    })
+}
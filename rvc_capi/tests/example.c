@@ -0,0 +1,127 @@
+/*
+ * Decodes a whole stream through the C interface, checks seeking and saves a frame as PPM.
+ *
+ *   cargo build -p rvc_capi --release
+ *   cc rvc_capi/tests/example.c -Irvc_capi/include target/release/librvc_capi.a -lm -lpthread -ldl -o example
+ *   ./example video.rvc [frame.ppm]
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "rvc.h"
+
+static uint8_t *read_file(const char *filename, size_t *size) {
+    FILE *file = fopen(filename, "rb");
+    if (!file) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    *size = (size_t)ftell(file);
+    fseek(file, 0, SEEK_SET);
+    uint8_t *data = malloc(*size);
+    if (data && fread(data, 1, *size, file) != *size) {
+        free(data);
+        data = NULL;
+    }
+    fclose(file);
+    return data;
+}
+
+static uint32_t checksum(const uint8_t *frame, size_t size) {
+    uint32_t sum = 2166136261u;
+    for (size_t i = 0; i < size; i++) {
+        sum = (sum ^ frame[i]) * 16777619u;
+    }
+    return sum;
+}
+
+static int save_ppm(const char *filename, const RvcInfo *info, const uint8_t *frame, const uint8_t *rgb) {
+    FILE *file = fopen(filename, "wb");
+    if (!file) {
+        return 0;
+    }
+    fprintf(file, "P6\n%u %u\n255\n", info->width, info->height);
+    for (size_t i = 0; i < (size_t)info->width * info->height; i++) {
+        fwrite(&rgb[frame[i] * 3], 1, 3, file);
+    }
+    fclose(file);
+    return 1;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "Usage: %s <file.rvc> [frame.ppm]\n", argv[0]);
+        return 1;
+    }
+    size_t size;
+    uint8_t *data = read_file(argv[1], &size);
+    if (!data) {
+        fprintf(stderr, "Can't read %s\n", argv[1]);
+        return 1;
+    }
+    RvcDecoder *decoder = rvc_open(data, size);
+    if (!decoder) {
+        fprintf(stderr, "%s is not a valid RVC stream\n", argv[1]);
+        free(data);
+        return 1;
+    }
+
+    RvcInfo info;
+    rvc_info(decoder, &info);
+    printf("%ux%u, %u/%u fps, %u frames", info.width, info.height, info.fps_num, info.fps_den, info.frame_count);
+    if (info.audio_rate) {
+        printf(", %u Hz x %u", info.audio_rate, info.audio_channels);
+    }
+    printf("\n");
+
+    size_t frame_size = (size_t)info.width * info.height;
+    uint8_t *frame = malloc(frame_size);
+    uint32_t *sums = calloc(info.frame_count ? info.frame_count : 1, sizeof(uint32_t));
+    int16_t samples[8192];
+    uint8_t rgb[256 * 3];
+    int result;
+    long audio = 0;
+    uint32_t count = 0;
+    while ((result = rvc_next_frame(decoder, frame, frame_size)) == RVC_OK) {
+        if (count < info.frame_count) {
+            sums[count] = checksum(frame, frame_size);
+        }
+        audio += rvc_audio(decoder, samples, sizeof(samples) / sizeof(samples[0]));
+        count++;
+    }
+    if (result != RVC_END) {
+        fprintf(stderr, "Decoding failed: %s\n", rvc_error(decoder));
+        return 1;
+    }
+    printf("Decoded %u frames, %ld audio samples\n", count, audio);
+
+    // Frames decoded after a seek must match the sequential pass
+    int status = 0;
+    uint32_t targets[] = {info.frame_count / 2, info.frame_count - 1, 0};
+    for (size_t i = 0; i < sizeof(targets) / sizeof(targets[0]) && info.frame_count > 0; i++) {
+        if (rvc_seek(decoder, targets[i]) != RVC_OK || rvc_next_frame(decoder, frame, frame_size) != RVC_OK ||
+            checksum(frame, frame_size) != sums[targets[i]]) {
+            fprintf(stderr, "Seeking to frame %u failed\n", targets[i]);
+            status = 1;
+        }
+    }
+    if (rvc_seek(decoder, info.frame_count) != RVC_ERROR) {
+        fprintf(stderr, "Seeking past the end succeeded\n");
+        status = 1;
+    }
+
+    int colors = rvc_palette(decoder, rgb, sizeof(rgb));
+    printf("Palette has %d colors\n", colors);
+    if (argc > 2 && !save_ppm(argv[2], &info, frame, rgb)) {
+        fprintf(stderr, "Can't write %s\n", argv[2]);
+        status = 1;
+    }
+
+    rvc_close(decoder);
+    free(sums);
+    free(frame);
+    free(data);
+    return status;
+}
@@ -0,0 +1,117 @@
+//! Builds `example.c` against the static library and runs it on a generated stream.
+
+use rvc_codec::{
+    audio::Codec,
+    encoder::{Encoder, EncoderConfig},
+};
+use rvc_shared::{
+    colors::FloatColor,
+    container::{AudioFormat, Header},
+    palette::Palette,
+    plane::Plane,
+};
+use std::{
+    env, fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Builds the static library with the profile of this test and returns its directory, the parent
+/// of the test's own `deps`. Tests link the crate as a Rust library, which it doesn't provide.
+fn build_library() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let profile_dir = exe.parent().and_then(Path::parent).unwrap();
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo
+        .args(["build", "-p", "rvc_capi", "--target-dir"])
+        .arg(profile_dir.parent().unwrap());
+    if profile_dir.ends_with("release") {
+        cargo.arg("--release");
+    }
+    assert!(cargo.status().unwrap().success(), "Building the library failed");
+    profile_dir.to_path_buf()
+}
+
+/// Encodes a square moving over stripes with an ADPCM tone, with keyframes every 10 frames.
+fn write_stream(filename: &Path) {
+    let mut palette = Palette::new();
+    for i in 0..8 {
+        palette.add(FloatColor::new(i * 32, 255 - i * 32, 128));
+    }
+    let config = EncoderConfig {
+        block_size: 4,
+        keyframe_interval: 10,
+        ..Default::default()
+    };
+    let mut header = Header::new(32, 24, 25, 1);
+    header.audio = Some(AudioFormat {
+        sample_rate: 8000,
+        channels: 1,
+        codec: Codec::Adpcm.id(),
+    });
+    let mut encoder = Encoder::new(Cursor::new(vec![]), header, &palette, config).unwrap();
+    for number in 0..30 {
+        let mut frame = Plane::new(32, 24, 0);
+        for y in 0..24 {
+            for x in 0..32 {
+                let square = (number..number + 6).contains(&x) && (8..14).contains(&y);
+                frame.set(x, y, if square { 7 } else { ((x + y) / 4 % 4) as i32 });
+            }
+        }
+        let samples: Vec<i16> = (0..encoder.audio_samples())
+            .map(|i| (((number as usize * 320 + i) as f64 * 0.1).sin() * 8000.0) as i16)
+            .collect();
+        encoder.push_audio(&samples);
+        encoder.encode(&frame).unwrap();
+    }
+    fs::write(filename, encoder.finish().unwrap().into_inner()).unwrap();
+}
+
+#[test]
+fn header_is_up_to_date() {
+    let generated = fs::read_to_string(Path::new(env!("OUT_DIR")).join("rvc.h")).unwrap();
+    let shipped = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("include/rvc.h")).unwrap();
+    assert!(
+        generated == shipped,
+        "include/rvc.h is outdated, build with RVC_UPDATE_HEADER set"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn c_example() {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let (stream, image, example) = (tmp.join("example.rvc"), tmp.join("example.ppm"), tmp.join("example"));
+    write_stream(&stream);
+
+    let compiler = cc::Build::new()
+        .cargo_metadata(false)
+        .opt_level(0)
+        .host(env!("TARGET"))
+        .target(env!("TARGET"))
+        .get_compiler();
+    let status = compiler
+        .to_command()
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/example.c"))
+        .arg("-I")
+        .arg(env!("OUT_DIR"))
+        .arg(build_library().join("librvc_capi.a"))
+        .args(["-lm", "-lpthread", "-ldl", "-o"])
+        .arg(&example)
+        .status()
+        .unwrap();
+    assert!(status.success(), "Compiling example.c failed");
+
+    let output = Command::new(&example).arg(&stream).arg(&image).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains("32x24, 25/1 fps, 30 frames, 8000 Hz x 1"), "{}", stdout);
+    assert!(stdout.contains("Decoded 30 frames, 9600 audio samples"), "{}", stdout);
+    assert!(fs::read(&image).unwrap().starts_with(b"P6\n32 24\n255\n"));
+}
@@ -0,0 +1,112 @@
+use anyhow::{Result, bail};
+
+pub mod adpcm;
+pub mod resample;
+
+/// Encoder or decoder of the sound of a single frame. Samples are interleaved by channel.
+pub trait AudioCodec {
+    fn encode(&mut self, samples: &[i16]) -> Vec<u8>;
+    /// Decodes a chunk into `samples`, which must have the exact size of the chunk's contents.
+    fn decode(&mut self, data: &[u8], samples: &mut [i16]) -> Result<()>;
+}
+
+/// Audio codec of a stream, stored in the stream header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Pcm16 = 0,
+    Pcm8 = 1,
+    Adpcm = 2,
+}
+
+impl Codec {
+    pub fn from_id(id: u8) -> Result<Codec> {
+        Ok(match id {
+            0 => Codec::Pcm16,
+            1 => Codec::Pcm8,
+            2 => Codec::Adpcm,
+            _ => bail!("Unknown audio codec {}", id),
+        })
+    }
+
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn coder(&self, channels: u32) -> Box<dyn AudioCodec + Send + Sync> {
+        match self {
+            Codec::Pcm16 => Box::new(Pcm16),
+            Codec::Pcm8 => Box::new(Pcm8),
+            Codec::Adpcm => Box::new(adpcm::ImaAdpcm::new(channels)),
+        }
+    }
+}
+
+fn check_size(data: &[u8], expected: usize) -> Result<()> {
+    if data.len() != expected {
+        bail!("Audio chunk has {} bytes instead of {}", data.len(), expected);
+    }
+    Ok(())
+}
+
+/// Signed 16-bit little endian samples.
+pub struct Pcm16;
+
+impl AudioCodec for Pcm16 {
+    fn encode(&mut self, samples: &[i16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    fn decode(&mut self, data: &[u8], samples: &mut [i16]) -> Result<()> {
+        check_size(data, samples.len() * 2)?;
+        for (sample, bytes) in samples.iter_mut().zip(data.chunks_exact(2)) {
+            *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(())
+    }
+}
+
+/// Unsigned 8-bit samples centered at 128, as played by Sound Blaster and Covox.
+pub struct Pcm8;
+
+impl AudioCodec for Pcm8 {
+    fn encode(&mut self, samples: &[i16]) -> Vec<u8> {
+        samples
+            .iter()
+            .map(|s| ((*s as i32 + 128).min(i16::MAX as i32) >> 8) as u8 ^ 0x80)
+            .collect()
+    }
+
+    fn decode(&mut self, data: &[u8], samples: &mut [i16]) -> Result<()> {
+        check_size(data, samples.len())?;
+        for (sample, byte) in samples.iter_mut().zip(data) {
+            *sample = ((*byte ^ 0x80) as i8 as i16) << 8;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcm16_roundtrip() {
+        let samples = [i16::MIN, -1, 0, 1, 12345, i16::MAX];
+        let data = Pcm16.encode(&samples);
+        let mut result = [0; 6];
+        Pcm16.decode(&data, &mut result).unwrap();
+        assert_eq!(result, samples);
+        assert!(Pcm16.decode(&data[1..], &mut result).is_err());
+    }
+
+    #[test]
+    fn pcm8_rounds_to_nearest() {
+        let samples = [i16::MIN, -129, -128, 0, 127, 128, 0x1280, i16::MAX];
+        let data = Pcm8.encode(&samples);
+        assert_eq!(data, [0x00, 0x7f, 0x80, 0x80, 0x80, 0x81, 0x93, 0xff]);
+        let mut result = [0; 8];
+        Pcm8.decode(&data, &mut result).unwrap();
+        assert_eq!(result, [i16::MIN, -256, 0, 0, 0, 256, 0x1300, 0x7f00]);
+        assert!(Pcm8.decode(&data, &mut [0; 7]).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{Result, bail};
+
+use super::{AudioCodec, check_size};
+
+const INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107,
+    118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963,
+    1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894,
+    6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794,
+    32767,
+];
+
+/// Bytes of the per-channel state stored at the start of every chunk.
+const CHANNEL_HEADER_SIZE: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Channel {
+    predictor: i32,
+    index: i32,
+}
+
+impl Channel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = STEP_TABLE[self.index as usize];
+        let mut diff = step >> 3;
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        if nibble & 8 != 0 {
+            diff = -diff;
+        }
+        self.predictor = (self.predictor + diff).clamp(i16::MIN as i32, i16::MAX as i32);
+        self.index = (self.index + INDEX_TABLE[nibble as usize]).clamp(0, STEP_TABLE.len() as i32 - 1);
+        self.predictor as i16
+    }
+
+    fn encode(&mut self, sample: i16) -> u8 {
+        let mut diff = sample as i32 - self.predictor;
+        let mut nibble = 0;
+        if diff < 0 {
+            nibble = 8;
+            diff = -diff;
+        }
+        let mut step = STEP_TABLE[self.index as usize];
+        for bit in [4, 2, 1] {
+            if diff >= step {
+                nibble |= bit;
+                diff -= step;
+            }
+            step >>= 1;
+        }
+        // Track the decoder's state rather than the exact signal
+        self.decode(nibble);
+        nibble
+    }
+}
+
+/// 4-bit IMA ADPCM. Every chunk starts with the predictor (i16) and step index (u8, followed by
+/// a padding byte) of each channel, so chunks can be decoded independently of each other.
+/// Samples follow as nibbles in interleaved order, low nibble first.
+pub struct ImaAdpcm {
+    channels: Vec<Channel>,
+}
+
+impl ImaAdpcm {
+    pub fn new(channels: u32) -> ImaAdpcm {
+        ImaAdpcm {
+            channels: vec![Channel::default(); channels as usize],
+        }
+    }
+}
+
+impl AudioCodec for ImaAdpcm {
+    fn encode(&mut self, samples: &[i16]) -> Vec<u8> {
+        let mut result = Vec::with_capacity(self.channels.len() * CHANNEL_HEADER_SIZE + samples.len().div_ceil(2));
+        for channel in self.channels.iter() {
+            result.extend_from_slice(&(channel.predictor as i16).to_le_bytes());
+            result.extend_from_slice(&[channel.index as u8, 0]);
+        }
+        let count = self.channels.len();
+        let mut nibbles = samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| self.channels[i % count].encode(*sample));
+        while let Some(low) = nibbles.next() {
+            result.push(low | nibbles.next().unwrap_or(0) << 4);
+        }
+        result
+    }
+
+    fn decode(&mut self, data: &[u8], samples: &mut [i16]) -> Result<()> {
+        let count = self.channels.len();
+        check_size(data, count * CHANNEL_HEADER_SIZE + samples.len().div_ceil(2))?;
+        let (header, nibbles) = data.split_at(count * CHANNEL_HEADER_SIZE);
+        for (channel, state) in self.channels.iter_mut().zip(header.chunks_exact(CHANNEL_HEADER_SIZE)) {
+            channel.predictor = i16::from_le_bytes([state[0], state[1]]) as i32;
+            channel.index = state[2] as i32;
+            if channel.index >= STEP_TABLE.len() as i32 {
+                bail!("Invalid ADPCM step index {}", channel.index);
+            }
+        }
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let nibble = (nibbles[i / 2] >> (i % 2 * 4)) & 0xf;
+            *sample = self.channels[i % count].decode(nibble);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stereo tone whose channels differ in pitch and loudness.
+    fn tone(frames: usize) -> Vec<i16> {
+        (0..frames * 2)
+            .map(|i| {
+                let (time, channel) = ((i / 2) as f64, (i % 2) as f64);
+                ((time * (0.05 + channel * 0.03)).sin() * (12000.0 - channel * 8000.0)) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip_error() {
+        let samples = tone(4000);
+        let mut encoder = ImaAdpcm::new(2);
+        let mut decoded = vec![0; samples.len()];
+        for (input, output) in samples.chunks(2 * 441).zip(decoded.chunks_mut(2 * 441)) {
+            let data = encoder.encode(input);
+            assert_eq!(data.len(), 2 * CHANNEL_HEADER_SIZE + input.len().div_ceil(2));
+            // Every chunk decodes on its own
+            ImaAdpcm::new(2).decode(&data, output).unwrap();
+        }
+        // The step size needs a few samples to adapt to the signal
+        let errors: Vec<f64> = samples
+            .iter()
+            .zip(decoded.iter())
+            .skip(100)
+            .map(|(a, b)| (*a as f64 - *b as f64).abs())
+            .collect();
+        let rms = (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt();
+        assert!(rms < 60.0, "RMS error {}", rms);
+        assert!(errors.iter().all(|e| *e < 250.0));
+    }
+
+    #[test]
+    fn rejects_bad_chunks() {
+        // Odd sample counts leave the high nibble of the last byte empty
+        let data = ImaAdpcm::new(1).encode(&[100; 11]);
+        assert_eq!(data.len(), CHANNEL_HEADER_SIZE + 6);
+        ImaAdpcm::new(1).decode(&data, &mut [0; 11]).unwrap();
+        assert!(ImaAdpcm::new(1).decode(&data, &mut [0; 13]).is_err());
+        let mut bad = data.clone();
+        bad[2] = STEP_TABLE.len() as u8;
+        assert!(ImaAdpcm::new(1).decode(&bad, &mut [0; 11]).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Result, bail};
+
+/// Averages the channels of interleaved samples into a single one.
+pub fn downmix(samples: &[i16], channels: u32) -> Vec<i16> {
+    samples
+        .chunks_exact(channels as usize)
+        .map(|frame| (frame.iter().map(|s| *s as i32).sum::<i32>() / channels as i32) as i16)
+        .collect()
+}
+
+/// Converts the sample rate of interleaved samples with linear interpolation. When downsampling,
+/// a box filter as wide as the rate ratio first removes most of the frequencies that the lower
+/// rate can't hold, which would otherwise fold back as aliases. The input can be fed in pieces
+/// of any size, the output continues seamlessly.
+pub struct Resampler {
+    channels: usize,
+    from: u64,
+    to: u64,
+    /// Position of the next output sample in input samples, multiplied by `to`.
+    time: u64,
+    /// Number of input samples consumed so far.
+    offset: u64,
+    /// Last input sample of every channel from the previous piece.
+    last: Vec<i16>,
+    /// Number of input samples the prefilter averages, 1 when not downsampling.
+    width: usize,
+    /// Last `width - 1` input samples of every channel, interleaved.
+    history: Vec<i16>,
+}
+
+impl Resampler {
+    pub fn new(channels: u32, from: u32, to: u32) -> Result<Resampler> {
+        if channels == 0 {
+            bail!("Resampling needs at least one channel");
+        }
+        if from == 0 || to == 0 {
+            bail!("Invalid sample rates {} and {}", from, to);
+        }
+        let width = from.div_ceil(to) as usize;
+        Ok(Resampler {
+            channels: channels as usize,
+            from: from as u64,
+            to: to as u64,
+            time: 0,
+            offset: 0,
+            last: vec![0; channels as usize],
+            width,
+            history: vec![0; (width - 1) * channels as usize],
+        })
+    }
+
+    /// Averages every input sample with the `width - 1` samples of its channel before it.
+    fn prefilter(&mut self, input: &[i16]) -> Vec<i16> {
+        let span = (self.width - 1) * self.channels;
+        let mut window = std::mem::take(&mut self.history);
+        window.extend_from_slice(&input[..input.len() / self.channels * self.channels]);
+        let filtered = (span..window.len())
+            .map(|i| {
+                let sum: i32 = (0..self.width).map(|k| window[i - k * self.channels] as i32).sum();
+                (sum / self.width as i32) as i16
+            })
+            .collect();
+        self.history = window.split_off(window.len() - span);
+        filtered
+    }
+
+    /// Resamples `input` and appends the result to `output`.
+    pub fn process(&mut self, input: &[i16], output: &mut Vec<i16>) {
+        let count = (input.len() / self.channels) as u64;
+        if count == 0 {
+            return;
+        }
+        let filtered;
+        let input = if self.width > 1 {
+            filtered = self.prefilter(input);
+            &filtered[..]
+        } else {
+            input
+        };
+        let sample = |i: u64, channel: usize| -> i32 {
+            if i < self.offset {
+                self.last[channel] as i32
+            } else {
+                input[(i - self.offset) as usize * self.channels + channel] as i32
+            }
+        };
+        // Interpolation needs the input sample after the output position
+        while self.time / self.to + 1 < self.offset + count {
+            let i = self.time / self.to;
+            let fraction = (self.time % self.to) as i32;
+            for channel in 0..self.channels {
+                let a = sample(i, channel);
+                let b = sample(i + 1, channel);
+                output.push((a + ((b - a) as i64 * fraction as i64 / self.to as i64) as i32) as i16);
+            }
+            self.time += self.from;
+        }
+        let last = (count - 1) as usize * self.channels;
+        self.last.copy_from_slice(&input[last..last + self.channels]);
+        self.offset += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stereo tone of `frequency` Hz, the right channel at half the volume.
+    fn tone(frequency: f64, rate: u32, count: usize) -> Vec<i16> {
+        (0..count)
+            .flat_map(|i| {
+                let value = 16000.0 * (i as f64 * frequency * std::f64::consts::TAU / rate as f64).sin();
+                [value as i16, (value / 2.0) as i16]
+            })
+            .collect()
+    }
+
+    fn peak(samples: &[i16]) -> i16 {
+        samples.iter().map(|sample| sample.abs()).max().unwrap()
+    }
+
+    #[test]
+    fn output_length_follows_rate() {
+        let input = tone(440.0, 44100, 44100);
+        for to in [8000, 22050, 44100, 48000] {
+            let mut resampler = Resampler::new(2, 44100, to).unwrap();
+            let mut whole = vec![];
+            resampler.process(&input, &mut whole);
+            // The last output sample waits for the input sample after it
+            assert!((whole.len() / 2).abs_diff(to as usize) <= 1, "{}: {}", to, whole.len());
+
+            let mut resampler = Resampler::new(2, 44100, to).unwrap();
+            let mut pieces = vec![];
+            for piece in input.chunks(2 * 997) {
+                resampler.process(piece, &mut pieces);
+            }
+            assert_eq!(pieces, whole, "{}", to);
+        }
+    }
+
+    #[test]
+    fn downsampling_filters_aliases() {
+        // 7 kHz doesn't fit 8 kHz sampling and would come out as a loud 1 kHz tone
+        let mut output = vec![];
+        Resampler::new(2, 48000, 8000).unwrap().process(&tone(7000.0, 48000, 4800), &mut output);
+        assert!(peak(&output[20..]) < 16000 / 4, "{}", peak(&output[20..]));
+        // Frequencies well below the limit pass
+        let mut output = vec![];
+        Resampler::new(2, 48000, 8000).unwrap().process(&tone(500.0, 48000, 4800), &mut output);
+        assert!(peak(&output[20..]) > 14400, "{}", peak(&output[20..]));
+    }
+
+    #[test]
+    fn rejects_empty_formats() {
+        assert!(Resampler::new(0, 44100, 8000).is_err());
+        assert!(Resampler::new(2, 0, 8000).is_err());
+        assert!(Resampler::new(2, 44100, 0).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{Result, bail};
+
+/// Packs values of arbitrary bit width into bytes, most significant bit first.
+pub struct BitWriter {
+    data: Vec<u8>,
+    acc: u32,
+    count: u32,
+    unpacked: bool,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter {
+            data: vec![],
+            acc: 0,
+            count: 0,
+            unpacked: false,
+        }
+    }
+
+    /// Writes every value of up to 8 bits to a byte of its own instead of packing them.
+    pub fn unpacked() -> BitWriter {
+        BitWriter {
+            unpacked: true,
+            ..BitWriter::new()
+        }
+    }
+
+    pub fn write(&mut self, value: u32, bits: u32) {
+        if self.unpacked {
+            debug_assert!(bits <= 8 && value >> bits == 0);
+            if bits > 0 {
+                self.data.push(value as u8);
+            }
+            return;
+        }
+        for i in (0..bits).rev() {
+            self.acc = (self.acc << 1) | ((value >> i) & 1);
+            self.count += 1;
+            if self.count == 8 {
+                self.data.push(self.acc as u8);
+                self.acc = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    pub fn write_bit(&mut self, value: bool) {
+        self.write(value as u32, 1);
+    }
+
+    /// Pads the current byte with zero bits.
+    pub fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.data
+    }
+}
+
+impl Default for BitWriter {
+    fn default() -> Self {
+        BitWriter::new()
+    }
+}
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+    unpacked: bool,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            pos: 0,
+            bit: 0,
+            unpacked: false,
+        }
+    }
+
+    /// Reads data written by `BitWriter::unpacked`.
+    pub fn unpacked(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            unpacked: true,
+            ..BitReader::new(data)
+        }
+    }
+
+    pub fn read(&mut self, bits: u32) -> Result<u32> {
+        if self.unpacked && bits > 0 {
+            let value = match self.data.get(self.pos) {
+                Some(value) => *value as u32,
+                None => bail!("Unexpected end of bitstream"),
+            };
+            if bits < 8 && value >> bits != 0 {
+                bail!("Symbol {} doesn't fit in {} bits", value, bits);
+            }
+            self.pos += 1;
+            return Ok(value);
+        }
+        let mut value = 0;
+        for _ in 0..bits {
+            if self.pos >= self.data.len() {
+                bail!("Unexpected end of bitstream");
+            }
+            value = (value << 1) | ((self.data[self.pos] >> (7 - self.bit)) & 1) as u32;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool> {
+        Ok(self.read(1)? == 1)
+    }
+
+    /// Skips the rest of the current byte.
+    pub fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+
+    /// Number of whole bytes consumed so far, counting a partially read byte.
+    pub fn position(&self) -> usize {
+        self.pos + (self.bit > 0) as usize
+    }
+}
+
+/// Number of bits needed to store any index of a palette with `colors` entries.
+pub fn index_bits(colors: usize) -> u32 {
+    let mut bits = 1;
+    while (1usize << bits) < colors {
+        bits += 1;
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpacked_symbols() {
+        let values = [(5, 3), (0, 1), (255, 8), (1, 2), (0, 0), (3, 2)];
+        for unpacked in [false, true] {
+            let mut writer = if unpacked { BitWriter::unpacked() } else { BitWriter::new() };
+            for (value, bits) in values {
+                writer.write(value, bits);
+            }
+            let data = writer.finish();
+            assert_eq!(data.len(), if unpacked { 5 } else { 2 });
+            let mut reader = if unpacked {
+                BitReader::unpacked(&data)
+            } else {
+                BitReader::new(&data)
+            };
+            for (value, bits) in values {
+                assert_eq!(reader.read(bits).unwrap(), value);
+            }
+            assert_eq!(reader.position(), data.len());
+        }
+        assert_eq!(BitReader::unpacked(&[8]).read(3).ok(), None);
+        assert_eq!(BitReader::unpacked(&[]).read(3).ok(), None);
+    }
+}
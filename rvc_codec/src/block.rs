@@ -0,0 +1,74 @@
+use rvc_shared::{palette::Palette, plane::Plane};
+
+/// Rectangular area of a frame. Blocks on the right and bottom edges may be smaller than the block size.
+#[derive(Clone, Copy, Debug)]
+pub struct Block {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Block {
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let block = *self;
+        (block.y..block.y + block.height).flat_map(move |y| (block.x..block.x + block.width).map(move |x| (x, y)))
+    }
+
+    pub fn count_changed(&self, a: &Plane<i32>, b: &Plane<i32>) -> u32 {
+        self.pixels().filter(|(x, y)| a.get(*x, *y) != b.get(*x, *y)).count() as u32
+    }
+
+    /// Most frequent index of the block, the lowest one on ties.
+    pub fn dominant(&self, plane: &Plane<i32>) -> i32 {
+        let mut counts = [0u32; 256];
+        for (x, y) in self.pixels() {
+            counts[plane.get(x, y) as usize] += 1;
+        }
+        let mut best = 0;
+        for (i, count) in counts.iter().enumerate() {
+            if *count > counts[best] {
+                best = i;
+            }
+        }
+        best as i32
+    }
+
+    pub fn is_changed(&self, a: &Plane<i32>, b: &Plane<i32>) -> bool {
+        self.pixels().any(|(x, y)| a.get(x, y) != b.get(x, y))
+    }
+
+    /// Largest perceptual difference between the pixels of the block in two planes shown with
+    /// `palette`, see `FloatColor::difference`.
+    pub fn max_difference(&self, a: &Plane<i32>, b: &Plane<i32>, palette: &Palette) -> f64 {
+        self.pixels()
+            .map(|(x, y)| palette.get(a.get(x, y)).difference(&palette.get(b.get(x, y))))
+            .fold(0.0, f64::max)
+    }
+
+    pub fn copy(&self, from: &Plane<i32>, to: &mut Plane<i32>) {
+        for (x, y) in self.pixels() {
+            to.set(x, y, from.get(x, y));
+        }
+    }
+}
+
+pub fn block_count(width: u32, height: u32, size: u32) -> usize {
+    (width.div_ceil(size) * height.div_ceil(size)) as usize
+}
+
+/// Splits a frame into blocks in raster order.
+pub fn blocks(width: u32, height: u32, size: u32) -> impl Iterator<Item = Block> {
+    (0..height.div_ceil(size)).flat_map(move |by| {
+        (0..width.div_ceil(size)).map(move |bx| {
+            let x = bx * size;
+            let y = by * size;
+            Block {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            }
+        })
+    })
+}
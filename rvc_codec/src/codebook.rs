@@ -0,0 +1,198 @@
+use anyhow::{Result, bail};
+use rayon::prelude::*;
+use rvc_shared::{colors::FloatColor, palette::Palette, plane::Plane};
+use std::collections::HashMap;
+
+use crate::{
+    bits::{BitReader, BitWriter, index_bits},
+    block::{Block, blocks},
+};
+
+pub const MAX_ENTRIES: usize = 256;
+
+/// Set of index tiles that blocks refer to in codebook mode.
+#[derive(Clone, Debug, Default)]
+pub struct Codebook {
+    pub tile_size: u32,
+    pub tiles: Vec<Vec<i32>>,
+}
+
+struct TilePoint {
+    tile: Vec<i32>,
+    colors: Vec<FloatColor>,
+    count: u64,
+    segment: usize,
+}
+
+fn tile_distance(colors: &[FloatColor], centroid: &[FloatColor]) -> f64 {
+    colors
+        .iter()
+        .zip(centroid.iter())
+        .map(|(a, b)| a.distance_squared(*b))
+        .sum()
+}
+
+impl Codebook {
+    /// Clusters the full-size tiles of `frame` into at most `size` entries with k-means in RGB space.
+    /// Every entry is the source tile closest to its cluster centre, so dithering patterns survive.
+    pub fn build(frame: &Plane<i32>, palette: &Palette, tile_size: u32, size: usize, max_steps: u32) -> Codebook {
+        let mut counts: HashMap<Vec<i32>, u64> = HashMap::new();
+        for block in blocks(frame.width, frame.height, tile_size) {
+            if block.width == tile_size && block.height == tile_size {
+                let tile: Vec<i32> = block.pixels().map(|(x, y)| frame.get(x, y)).collect();
+                *counts.entry(tile).or_insert(0) += 1;
+            }
+        }
+
+        let mut points: Vec<TilePoint> = counts
+            .into_iter()
+            .map(|(tile, count)| TilePoint {
+                colors: tile.iter().map(|i| palette.get(*i)).collect(),
+                tile,
+                count,
+                segment: 0,
+            })
+            .collect();
+        // Most frequent tiles seed the clusters, ties are broken by content to keep the result deterministic
+        points.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tile.cmp(&b.tile)));
+
+        if points.is_empty() {
+            // Frame is smaller than a single tile
+            return Codebook {
+                tile_size,
+                tiles: vec![vec![frame.get(0, 0); (tile_size * tile_size) as usize]],
+            };
+        }
+        if points.len() <= size {
+            return Codebook {
+                tile_size,
+                tiles: points.into_iter().map(|p| p.tile).collect(),
+            };
+        }
+
+        let mut centroids: Vec<Vec<FloatColor>> = points[..size].iter().map(|p| p.colors.clone()).collect();
+        for _ in 0..max_steps {
+            let changed: usize = points
+                .par_iter_mut()
+                .map(|point| {
+                    let mut best = point.segment;
+                    let mut best_distance = tile_distance(&point.colors, &centroids[best]);
+                    for (i, centroid) in centroids.iter().enumerate() {
+                        let distance = tile_distance(&point.colors, centroid);
+                        if distance < best_distance {
+                            best_distance = distance;
+                            best = i;
+                        }
+                    }
+                    let changed = (best != point.segment) as usize;
+                    point.segment = best;
+                    changed
+                })
+                .sum();
+            if changed == 0 {
+                break;
+            }
+
+            let pixels = (tile_size * tile_size) as usize;
+            let mut sums = vec![vec![FloatColor::BLACK; pixels]; size];
+            let mut weights = vec![0u64; size];
+            for point in points.iter() {
+                weights[point.segment] += point.count;
+                for (sum, color) in sums[point.segment].iter_mut().zip(point.colors.iter()) {
+                    *sum += *color * point.count as f64;
+                }
+            }
+            for ((centroid, sum), weight) in centroids.iter_mut().zip(sums).zip(weights) {
+                if weight > 0 {
+                    *centroid = sum.into_iter().map(|c| c * (1.0 / weight as f64)).collect();
+                }
+            }
+        }
+
+        let mut best: Vec<Option<(f64, usize)>> = vec![None; size];
+        for (i, point) in points.iter().enumerate() {
+            let distance = tile_distance(&point.colors, &centroids[point.segment]);
+            match best[point.segment] {
+                Some((best_distance, _)) if best_distance <= distance => {}
+                _ => best[point.segment] = Some((distance, i)),
+            }
+        }
+        Codebook {
+            tile_size,
+            tiles: best
+                .into_iter()
+                .flatten()
+                .map(|(_, i)| points[i].tile.clone())
+                .collect(),
+        }
+    }
+
+    /// Entry that reproduces the block of `frame` with the smallest colour error.
+    pub fn nearest(&self, frame: &Plane<i32>, block: &Block, palette: &Palette) -> usize {
+        let mut best = 0;
+        let mut best_distance = f64::MAX;
+        for (i, tile) in self.tiles.iter().enumerate() {
+            let mut distance = 0.0;
+            for (x, y) in block.pixels() {
+                let index = tile[((x - block.x) + (y - block.y) * self.tile_size) as usize];
+                distance += palette.get(frame.get(x, y)).distance_squared(palette.get(index));
+                if distance >= best_distance {
+                    break;
+                }
+            }
+            if distance < best_distance {
+                best_distance = distance;
+                best = i;
+                if distance == 0.0 {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /// Whether the block of `frame` already holds the tile of `entry`.
+    pub fn matches(&self, entry: usize, block: &Block, frame: &Plane<i32>) -> bool {
+        let tile = &self.tiles[entry];
+        block
+            .pixels()
+            .all(|(x, y)| frame.get(x, y) == tile[((x - block.x) + (y - block.y) * self.tile_size) as usize])
+    }
+
+    pub fn apply(&self, entry: usize, block: &Block, frame: &mut Plane<i32>) {
+        let tile = &self.tiles[entry];
+        for (x, y) in block.pixels() {
+            frame.set(x, y, tile[((x - block.x) + (y - block.y) * self.tile_size) as usize]);
+        }
+    }
+
+    pub fn entry_bits(&self) -> u32 {
+        index_bits(self.tiles.len())
+    }
+
+    pub fn write(&self, writer: &mut BitWriter, bits: u32) {
+        writer.write(self.tiles.len() as u32 - 1, 8);
+        for tile in self.tiles.iter() {
+            for index in tile.iter() {
+                writer.write(*index as u32, bits);
+            }
+        }
+    }
+
+    pub fn read(reader: &mut BitReader, tile_size: u32, bits: u32, colors: usize) -> Result<Codebook> {
+        let count = reader.read(8)? as usize + 1;
+        let mut tiles = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut tile = Vec::with_capacity((tile_size * tile_size) as usize);
+            for _ in 0..tile_size * tile_size {
+                let index = reader.read(bits)?;
+                if index as usize >= colors {
+                    bail!("Color index {} is out of palette range", index);
+                }
+                tile.push(index as i32);
+            }
+            tiles.push(tile);
+        }
+        Ok(Codebook { tile_size, tiles })
+    }
+}
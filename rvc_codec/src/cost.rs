@@ -0,0 +1,163 @@
+use anyhow::{Result, bail};
+
+use crate::{block::blocks, entropy::EntropyItems, frame::BlockOp};
+
+/// Estimated decoder cycles of the stream operations. The defaults are rough figures for a 386
+/// class machine and are meant to be tuned for the actual player.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CostModel {
+    /// Fixed cost of every frame, for parsing the chunk and presenting the picture.
+    pub frame: u32,
+    pub skip: u32,
+    /// Setup of every block that is not skipped, its pixels are counted separately.
+    pub block: u32,
+    /// Copying a pixel of a motion block from the previous frame.
+    pub motion_pixel: u32,
+    /// Reading a pixel of a raw block or a keyframe from the bitstream.
+    pub raw_pixel: u32,
+    pub fill_pixel: u32,
+    /// Copying a pixel of a codebook entry, loading the codebook counts as raw pixels.
+    pub entry_pixel: u32,
+    /// Entropy decoding of a literal byte, stored payloads count every byte.
+    pub literal: u32,
+    /// An LZ77 match or RLE run, the bytes it copies are counted separately.
+    pub lz_match: u32,
+    pub match_byte: u32,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            frame: 2000,
+            skip: 20,
+            block: 60,
+            motion_pixel: 8,
+            raw_pixel: 12,
+            fill_pixel: 3,
+            entry_pixel: 6,
+            literal: 10,
+            lz_match: 40,
+            match_byte: 6,
+        }
+    }
+}
+
+impl CostModel {
+    pub const NAMES: [&str; 10] = [
+        "frame",
+        "skip",
+        "block",
+        "motion_pixel",
+        "raw_pixel",
+        "fill_pixel",
+        "entry_pixel",
+        "literal",
+        "lz_match",
+        "match_byte",
+    ];
+
+    /// Sets the cost of an operation by its name in `NAMES`.
+    pub fn set(&mut self, name: &str, cycles: u32) -> Result<()> {
+        let cost = match name {
+            "frame" => &mut self.frame,
+            "skip" => &mut self.skip,
+            "block" => &mut self.block,
+            "motion_pixel" => &mut self.motion_pixel,
+            "raw_pixel" => &mut self.raw_pixel,
+            "fill_pixel" => &mut self.fill_pixel,
+            "entry_pixel" => &mut self.entry_pixel,
+            "literal" => &mut self.literal,
+            "lz_match" => &mut self.lz_match,
+            "match_byte" => &mut self.match_byte,
+            _ => bail!(
+                "Unknown operation {:?}, expected one of {}",
+                name,
+                CostModel::NAMES.join(", ")
+            ),
+        };
+        *cost = cycles;
+        Ok(())
+    }
+
+    /// Applies a `NAME=CYCLES` assignment.
+    pub fn parse_assignment(&mut self, assignment: &str) -> Result<()> {
+        match assignment.split_once('=') {
+            Some((name, cycles)) => match cycles.trim().parse() {
+                Ok(cycles) => self.set(name.trim(), cycles),
+                Err(_) => bail!("Invalid number of cycles {:?}", cycles),
+            },
+            None => bail!("Expected NAME=CYCLES instead of {:?}", assignment),
+        }
+    }
+
+    /// Estimated cycles to decode a frame from its block operations. `codebook_size` is the number
+    /// of codebook entries that come with a keyframe.
+    pub fn frame_cycles(
+        &self,
+        width: u32,
+        height: u32,
+        block_size: u32,
+        ops: &[BlockOp],
+        codebook_size: Option<usize>,
+        entropy: &EntropyItems,
+    ) -> u64 {
+        let mut cycles = self.frame as u64;
+        for (block, op) in blocks(width, height, block_size).zip(ops) {
+            let pixels = (block.width * block.height) as u64;
+            cycles += match op {
+                BlockOp::Skip => self.skip as u64,
+                BlockOp::Motion(_) => self.block as u64 + pixels * self.motion_pixel as u64,
+                BlockOp::Raw => self.block as u64 + pixels * self.raw_pixel as u64,
+                BlockOp::Fill(_) => self.block as u64 + pixels * self.fill_pixel as u64,
+                BlockOp::Entry(_) => self.block as u64 + pixels * self.entry_pixel as u64,
+            };
+        }
+        if let Some(entries) = codebook_size {
+            cycles += (entries as u64) * (block_size * block_size) as u64 * self.raw_pixel as u64;
+        }
+        cycles += entropy.literals as u64 * self.literal as u64;
+        cycles += entropy.matches as u64 * self.lz_match as u64;
+        cycles += entropy.match_bytes as u64 * self.match_byte as u64;
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::MotionVector;
+
+    #[test]
+    fn parse_assignments() {
+        let mut model = CostModel::default();
+        model.parse_assignment("raw_pixel=7").unwrap();
+        model.parse_assignment(" lz_match = 100 ").unwrap();
+        assert_eq!((model.raw_pixel, model.lz_match), (7, 100));
+        for bad in ["pixel=3", "skip=-1", "skip=", "skip"] {
+            assert!(model.parse_assignment(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(model.skip, CostModel::default().skip);
+        for name in CostModel::NAMES {
+            model.set(name, 1).unwrap();
+        }
+        assert_eq!(model.frame_cycles(4, 4, 4, &[BlockOp::Skip], None, &EntropyItems::default()), 2);
+    }
+
+    #[test]
+    fn frame_cycles() {
+        let model = CostModel::default();
+        // A full block and a partial 2x4 block on the right
+        let ops = [BlockOp::Motion(MotionVector { dx: 1, dy: 0 }), BlockOp::Fill(3)];
+        let items = EntropyItems {
+            literals: 10,
+            matches: 2,
+            match_bytes: 30,
+        };
+        let expected = 2000 + (60 + 16 * 8) + (60 + 8 * 3) + 10 * 10 + 2 * 40 + 30 * 6;
+        assert_eq!(model.frame_cycles(6, 4, 4, &ops, None, &items), expected);
+        // Every codebook entry is loaded like a raw block
+        let ops = [BlockOp::Entry(0), BlockOp::Skip];
+        let expected = 2000 + (60 + 16 * 6) + 20 + 5 * 16 * 12;
+        assert_eq!(model.frame_cycles(6, 4, 4, &ops, Some(5), &EntropyItems::default()), expected);
+    }
+}
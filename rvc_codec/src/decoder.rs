@@ -0,0 +1,352 @@
+use anyhow::{Result, bail};
+use rvc_shared::{
+    container::{CHUNK_HEADER_SIZE, Chunk, ContainerReader, FRAME_OVERHEAD, Header, apply_palette_delta},
+    palette::Palette,
+    plane::Plane,
+};
+use std::io::{Read, Seek};
+
+use crate::{
+    audio::{AudioCodec, Codec},
+    codebook::Codebook,
+    entropy::{Backend, EntropyCoder, EntropyItems},
+    frame::{BlockOp, FrameParams, decode_inter, decode_intra},
+};
+
+/// Details of the last decoded frame.
+#[derive(Clone, Debug, Default)]
+pub struct FrameInfo {
+    pub keyframe: bool,
+    /// Size of the frame chunk in bytes.
+    pub size: usize,
+    /// Size of the frame payload after decompression.
+    pub payload_size: usize,
+    /// Size of the audio chunk in bytes.
+    pub audio_size: usize,
+    /// Whether a new palette, a palette delta or new cycling ranges came with the frame.
+    pub palette_update: bool,
+    /// Number of entries of a codebook that came with the frame.
+    pub codebook_entries: Option<usize>,
+    /// Operations of all blocks in raster order.
+    pub ops: Vec<BlockOp>,
+    /// Literals and matches of the compressed frame.
+    pub entropy: EntropyItems,
+}
+
+/// Part of the stream, in stream order. Palette and audio events precede the frame they belong to.
+pub enum Event<'a> {
+    /// The palette or its cycling ranges changed.
+    Palette(&'a Palette),
+    /// Interleaved samples that accompany the next frame.
+    Audio(&'a [i16]),
+    Frame(&'a Plane<i32>),
+}
+
+/// Pull-based decoder that reads the stream chunk by chunk. Besides the current and previous
+/// frame it buffers a single chunk. Seeking is available when the input implements `Seek`.
+pub struct Decoder<R: Read> {
+    reader: ContainerReader<R>,
+    header: Header,
+    base_palette: Palette,
+    /// Colors of `base_palette` at the time of the last decoded frame, with cycling ranges applied.
+    palette: Palette,
+    /// Palette at the start of the stream, keyframes that follow palette changes carry their own.
+    initial_palette: Palette,
+    /// Number of colors of the palette the last frame was decoded with.
+    colors: usize,
+    current: u32,
+    frame: Plane<i32>,
+    previous: Plane<i32>,
+    codebook: Codebook,
+    coder: Box<dyn EntropyCoder + Send + Sync>,
+    audio: Vec<i16>,
+    audio_coder: Option<Box<dyn AudioCodec + Send + Sync>>,
+    info: FrameInfo,
+    /// Whether the last event was a frame, so the next one starts a new frame.
+    frame_done: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(input: R) -> Result<Decoder<R>> {
+        let mut reader = ContainerReader::new(input)?;
+        let palette = match reader.next_chunk()? {
+            Some(Chunk::Palette(palette)) if !palette.is_empty() => palette,
+            _ => bail!("Missing palette"),
+        };
+        let coder = Backend::from_id(reader.header().entropy)?.coder();
+        let audio_coder = match &reader.header().audio {
+            Some(audio) => Some(Codec::from_id(audio.codec)?.coder(audio.channels)),
+            None => None,
+        };
+        let frame = Plane::new(reader.header().width, reader.header().height, 0);
+        Ok(Decoder {
+            header: reader.header().clone(),
+            reader,
+            colors: palette.len(),
+            initial_palette: palette.clone(),
+            base_palette: palette.clone(),
+            palette,
+            current: 0,
+            previous: frame.clone(),
+            frame,
+            codebook: Codebook::default(),
+            coder,
+            audio: vec![],
+            audio_coder,
+            info: FrameInfo::default(),
+            frame_done: true,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Colors of the last decoded frame.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Palette before color cycling, along with its cycling ranges.
+    pub fn base_palette(&self) -> &Palette {
+        &self.base_palette
+    }
+
+    /// Time of `frame` in seconds.
+    pub fn frame_time(&self, frame: u32) -> f64 {
+        frame as f64 * self.header.fps_den as f64 / self.header.fps_num as f64
+    }
+
+    /// Number of the frame that the next call to `next_frame` will return.
+    pub fn position(&self) -> u32 {
+        self.current
+    }
+
+    /// Interleaved samples that accompany the last decoded frame.
+    pub fn audio(&self) -> &[i16] {
+        &self.audio
+    }
+
+    /// Details of the last decoded frame.
+    pub fn info(&self) -> &FrameInfo {
+        &self.info
+    }
+
+    /// Reads the stream up to the next event. Returns `None` at the end of the stream.
+    pub fn next_event(&mut self) -> Result<Option<Event<'_>>> {
+        if self.frame_done {
+            self.audio.clear();
+            self.info.ops.clear();
+            self.info.audio_size = 0;
+            self.info.palette_update = false;
+            self.frame_done = false;
+        }
+        let header = &self.header;
+        match self.reader.next_chunk()? {
+            Some(Chunk::Palette(palette)) => {
+                self.base_palette = palette;
+                self.info.palette_update = true;
+                Ok(Some(Event::Palette(&self.base_palette)))
+            }
+            Some(Chunk::PaletteDelta(entries)) => {
+                apply_palette_delta(&mut self.base_palette, &entries)?;
+                self.info.palette_update = true;
+                Ok(Some(Event::Palette(&self.base_palette)))
+            }
+            Some(Chunk::Cycles(ranges)) => {
+                self.base_palette.clear_cycles();
+                for range in ranges {
+                    self.base_palette.add_cycle(range)?;
+                }
+                self.info.palette_update = true;
+                Ok(Some(Event::Palette(&self.base_palette)))
+            }
+            Some(Chunk::Audio(data)) => {
+                let (audio, coder) = match (&header.audio, &mut self.audio_coder) {
+                    (Some(audio), Some(coder)) => (audio, coder),
+                    _ => bail!("Audio chunk in a stream without sound"),
+                };
+                self.audio
+                    .resize(header.audio_samples(self.current) * audio.channels as usize, 0);
+                coder.decode(data, &mut self.audio)?;
+                self.info.audio_size = data.len() + CHUNK_HEADER_SIZE;
+                Ok(Some(Event::Audio(&self.audio)))
+            }
+            Some(Chunk::Frame(frame)) => {
+                if !frame.keyframe && self.base_palette.len() != self.colors {
+                    bail!("Number of colors changes before a delta frame");
+                }
+                let params = FrameParams::new(header, &self.base_palette);
+                let payload = self
+                    .coder
+                    .decompress(frame.data, params.max_payload(header.width, header.height))?;
+                let mut reader = params.reader(&payload);
+                let ops = &mut self.info.ops;
+                if frame.keyframe {
+                    decode_intra(&mut reader, &mut self.frame, &params, &mut self.codebook, ops)?;
+                } else {
+                    self.previous.data.copy_from_slice(&self.frame.data);
+                    decode_inter(
+                        &mut reader,
+                        &mut self.frame,
+                        &self.previous,
+                        &params,
+                        &self.codebook,
+                        ops,
+                    )?;
+                }
+                self.info.keyframe = frame.keyframe;
+                self.info.size = frame.data.len() + FRAME_OVERHEAD;
+                self.info.payload_size = payload.len();
+                self.info.entropy = self.coder.items(frame.data, payload.len());
+                self.info.codebook_entries = if frame.keyframe && params.codebook {
+                    Some(self.codebook.tiles.len())
+                } else {
+                    None
+                };
+                self.colors = self.base_palette.len();
+                let time_num = self.current as u64 * header.fps_den as u64;
+                self.palette = self.base_palette.cycled(time_num, header.fps_num as u64);
+                self.current += 1;
+                self.frame_done = true;
+                Ok(Some(Event::Frame(&self.frame)))
+            }
+            Some(Chunk::Index(_)) | None => Ok(None),
+        }
+    }
+
+    /// Reads the stream up to the next frame. The palette, audio and details that came with the
+    /// frame are available after it.
+    pub fn next_frame(&mut self) -> Result<Option<&Plane<i32>>> {
+        loop {
+            match self.next_event()? {
+                Some(Event::Frame(_)) => break,
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(&self.frame))
+    }
+}
+
+impl<R: Read + Seek> Decoder<R> {
+    /// Positions the decoder so that the next decoded frame is `frame`.
+    pub fn seek(&mut self, frame: u32) -> Result<()> {
+        if frame >= self.header().frame_count {
+            bail!("Frame {} is out of range", frame);
+        }
+        self.current = self.reader.seek_keyframe(frame)?;
+        self.base_palette = self.initial_palette.clone();
+        self.frame_done = true;
+        while self.current < frame {
+            self.next_frame()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::Codec,
+        encoder::{Encoder, EncoderConfig},
+        testing,
+    };
+    use rvc_shared::{
+        colors::{FloatColor, IntColor},
+        container::AudioFormat,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn events_come_before_their_frame() {
+        let mut header = testing::header();
+        header.audio = Some(AudioFormat {
+            sample_rate: 8000,
+            channels: 1,
+            codec: Codec::Pcm16.id(),
+        });
+        let config = EncoderConfig::default();
+        let mut encoder = Encoder::new(Cursor::new(vec![]), header, &testing::palette(), config).unwrap();
+        let mut other = testing::palette();
+        other.set(0, FloatColor::new(1, 2, 3));
+        for (number, frame) in testing::clip(6, 6).iter().enumerate() {
+            if number == 3 {
+                encoder.set_palette(&other).unwrap();
+            }
+            encoder.push_audio(&vec![number as i16; encoder.audio_samples()]);
+            encoder.encode(frame).unwrap();
+        }
+        let stream = encoder.finish().unwrap().into_inner();
+
+        // Plain `Read` is enough without seeking
+        let mut decoder = Decoder::new(stream.as_slice()).unwrap();
+        let mut events = vec![];
+        while let Some(event) = decoder.next_event().unwrap() {
+            events.push(match event {
+                Event::Palette(palette) => format!("P{}", IntColor::from(palette.get(0)).r),
+                Event::Audio(samples) => format!("A{}x{}", samples[0], samples.len()),
+                Event::Frame(_) => "F".to_string(),
+            });
+        }
+        assert_eq!(
+            events.join(" "),
+            "A0x320 F A1x320 F A2x320 F A3x320 P1 F A4x320 F A5x320 F"
+        );
+        assert_eq!(decoder.position(), 6);
+    }
+
+    #[test]
+    fn seek_lands_on_frame() {
+        let config = EncoderConfig {
+            block_size: 4,
+            keyframe_interval: 8,
+            scene_threshold: 0.0,
+            ..Default::default()
+        };
+        let stream = testing::encode(config, &testing::clip(50, 50), usize::MAX);
+        let mut decoder = Decoder::new(Cursor::new(stream)).unwrap();
+        let mut frames = vec![];
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            frames.push(frame.clone());
+        }
+        assert_eq!(frames.len(), 50);
+        for target in [13, 0, 7, 8, 49, 16, 3] {
+            decoder.seek(target).unwrap();
+            assert_eq!(decoder.position(), target);
+            assert_eq!(
+                decoder.next_frame().unwrap().unwrap().data,
+                frames[target as usize].data
+            );
+            assert_eq!(decoder.info().keyframe, target % 8 == 0);
+        }
+        assert!(decoder.seek(50).is_err());
+    }
+
+    #[test]
+    fn rle_and_huffman_see_whole_symbols() {
+        let frames = testing::clip(2, 2);
+        let pixels = (testing::WIDTH * testing::HEIGHT) as usize;
+        // 16 colors take 4 bits packed and a byte each for the back-ends that code symbols
+        for (entropy, keyframe_size) in [
+            (Backend::None, pixels / 2),
+            (Backend::Rle, pixels),
+            (Backend::Lz77, pixels / 2),
+            (Backend::Huffman, pixels),
+        ] {
+            let config = EncoderConfig {
+                entropy,
+                ..Default::default()
+            };
+            let stream = testing::encode(config, &frames, usize::MAX);
+            let mut decoder = Decoder::new(Cursor::new(stream)).unwrap();
+            for frame in frames.iter() {
+                assert_eq!(decoder.next_frame().unwrap().unwrap().data, frame.data);
+                if decoder.info().keyframe {
+                    assert_eq!(decoder.info().payload_size, keyframe_size, "{:?}", entropy);
+                }
+            }
+        }
+    }
+}
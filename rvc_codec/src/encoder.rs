@@ -0,0 +1,1052 @@
+use anyhow::{Result, bail};
+use rayon::prelude::*;
+use rvc_shared::{
+    container::{
+        CHUNK_HEADER_SIZE, ContainerWriter, FRAME_OVERHEAD, Header, Muxer, PaletteEntry, encode_cycles, encode_palette,
+        palette_delta,
+    },
+    palette::{CycleRange, Palette},
+    plane::Plane,
+};
+use std::io::{Seek, Write};
+
+use crate::{
+    audio::{AudioCodec, Codec},
+    block::{Block, block_count, blocks},
+    codebook::{self, Codebook},
+    cost::CostModel,
+    entropy::{Backend, EntropyCoder},
+    frame::{BlockOp, FrameParams, encode_inter, encode_intra, encode_intra_codebook},
+    motion::{self, MotionVector},
+    rate::{Budget, LEVELS, PassFrame, PassStats, Quality, RateControl},
+    scene::{KeyframePlacer, SceneMetric},
+};
+
+pub const MAX_COLORS: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct EncoderConfig {
+    pub block_size: u32,
+    /// Maximum motion vector length in pixels, 0 disables motion search.
+    pub motion_radius: u32,
+    /// Number of codebook entries built for every keyframe, 0 stores blocks as raw indices.
+    /// In codebook mode the block size is also the tile size and must be 2 or 4.
+    pub codebook_size: u32,
+    /// Maximum number of k-means iterations when building a codebook.
+    pub codebook_steps: u32,
+    /// Maximum distance between keyframes, 0 means no limit.
+    pub keyframe_interval: u32,
+    /// Difference between consecutive frames that forces a keyframe, 0 disables scene-cut detection.
+    pub scene_threshold: f64,
+    pub scene_metric: SceneMetric,
+    pub budget: Budget,
+    pub entropy: Backend,
+    /// Changed blocks in which no pixel differs from the previous frame by more than this are
+    /// skipped, 0 keeps every change. The difference is perceptual, in steps of 8-bit brightness.
+    pub skip_threshold: f64,
+    /// Number of groups of frames, each from a keyframe up to the next one, that are held back and
+    /// coded in parallel. 1 codes every frame right away, and so does any limited budget since the
+    /// budget of a frame depends on the size of the frames before.
+    pub parallel_groups: usize,
+    /// Statistics of a first pass with the same settings and no budget. They spread a per-second
+    /// budget over the clip by frame complexity.
+    pub first_pass: Option<PassStats>,
+    pub cost_model: CostModel,
+    /// Estimated decoder cycles a delta frame may take, 0 means no limit. Frames over the limit
+    /// are degraded like frames over the byte budget, keyframes can't be.
+    pub cycle_budget: u64,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        EncoderConfig {
+            block_size: 8,
+            motion_radius: 8,
+            codebook_size: 0,
+            codebook_steps: 16,
+            keyframe_interval: 250,
+            scene_threshold: 0.4,
+            scene_metric: SceneMetric::Histogram,
+            budget: Budget::Unlimited,
+            entropy: Backend::None,
+            skip_threshold: 0.0,
+            parallel_groups: 1,
+            first_pass: None,
+            cost_model: CostModel::default(),
+            cycle_budget: 0,
+        }
+    }
+}
+
+/// Error accepted by skipping blocks below the skip threshold.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkipError {
+    pub blocks: u32,
+    /// Sum of the perceptual differences of the skipped blocks.
+    pub total: f64,
+    pub max: f64,
+}
+
+impl SkipError {
+    pub fn add(&mut self, other: &SkipError) {
+        self.blocks += other.blocks;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.blocks == 0 {
+            0.0
+        } else {
+            self.total / self.blocks as f64
+        }
+    }
+}
+
+/// Outcome of encoding a single frame.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    pub keyframe: bool,
+    /// Size of the frame chunk in bytes.
+    pub size: usize,
+    /// Size of the audio chunk in bytes, it counts against the budget as well.
+    pub audio_size: usize,
+    /// Size of the palette or palette delta chunk in bytes, also counted against the budget.
+    pub palette_size: usize,
+    /// Degradation level that was needed to fit the budget.
+    pub level: u32,
+    /// Bytes the rate control allowed for this frame.
+    pub budget: f64,
+    pub skip_error: SkipError,
+    /// Whether the frame starts a new scene, keyframes from the interval or palette changes don't.
+    pub scene_cut: bool,
+    /// Blocks that differ from the previous frame, all of them in a keyframe.
+    pub changed_blocks: u32,
+    /// Estimated decoding time, see `EncoderConfig::cost_model`.
+    pub cycles: u64,
+}
+
+impl FrameStats {
+    pub fn over_budget(&self) -> bool {
+        (self.size + self.audio_size + self.palette_size) as f64 > self.budget
+    }
+
+    /// Statistics of the frame for the second pass, taken from an encode without a budget.
+    pub fn pass_frame(&self) -> PassFrame {
+        PassFrame {
+            keyframe: self.keyframe,
+            scene_cut: self.scene_cut,
+            complexity: (self.size + self.audio_size + self.palette_size) as u32,
+            changed_blocks: self.changed_blocks,
+        }
+    }
+}
+
+fn check_palette(palette: &Palette) -> Result<()> {
+    if palette.is_empty() || palette.len() > MAX_COLORS {
+        bail!("Palette must have from 1 to {} colors", MAX_COLORS);
+    }
+    Ok(())
+}
+
+/// Chunk that goes ahead of a frame.
+enum FrameChunk {
+    Palette(Palette),
+    PaletteDelta(Vec<PaletteEntry>),
+    Cycles(Vec<CycleRange>),
+    Audio(Vec<u8>),
+}
+
+/// Frame with its chunks and coding parameters settled, waiting to be coded.
+struct PlannedFrame {
+    frame: Plane<i32>,
+    keyframe: bool,
+    scene_cut: bool,
+    chunks: Vec<FrameChunk>,
+    palette: Palette,
+    params: FrameParams,
+    budget: f64,
+    audio_size: usize,
+    palette_size: usize,
+}
+
+struct CodedFrame {
+    data: Vec<u8>,
+    level: u32,
+    skip_error: SkipError,
+    changed_blocks: u32,
+    cycles: u64,
+}
+
+/// State shared by the frames of a group, from its keyframe on.
+#[derive(Default)]
+struct Group {
+    codebook: Codebook,
+    reference: Option<Plane<i32>>,
+}
+
+/// Codes the frames of a group, shared by all groups.
+struct GroupCoder {
+    config: EncoderConfig,
+    candidates: Vec<MotionVector>,
+    coder: Box<dyn EntropyCoder + Send + Sync>,
+}
+
+pub struct Encoder<M: Muxer> {
+    writer: M,
+    group_coder: GroupCoder,
+    params: FrameParams,
+    palette: Palette,
+    /// Palette that takes effect with the next frame.
+    next_palette: Option<Palette>,
+    /// Encoded palette of the stream start, keyframes repeat the palette whenever it differs.
+    initial_palette: Vec<u8>,
+    placer: KeyframePlacer,
+    rate: RateControl,
+    /// Group of the frames coded right away.
+    group: Group,
+    /// Groups held back to be coded in parallel, the last one may still grow.
+    pending: Vec<Vec<PlannedFrame>>,
+    /// Number of frames passed to the encoder.
+    frames: u32,
+    /// Interleaved samples waiting for their frames.
+    audio: Vec<i16>,
+    audio_coder: Option<Box<dyn AudioCodec + Send + Sync>>,
+}
+
+impl EncoderConfig {
+    /// Stores the coding parameters in the stream header.
+    pub fn apply(&self, header: &mut Header) {
+        header.block_size = self.block_size;
+        header.motion_radius = self.motion_radius;
+        header.codebook = self.codebook_size > 0;
+        header.entropy = self.entropy.id();
+    }
+}
+
+impl<W: Write + Seek> Encoder<ContainerWriter<W>> {
+    /// Encodes into an RVC file.
+    pub fn new(output: W, mut header: Header, palette: &Palette, config: EncoderConfig) -> Result<Self> {
+        config.apply(&mut header);
+        Encoder::with_muxer(ContainerWriter::new(output, header)?, palette, config)
+    }
+}
+
+impl<M: Muxer> Encoder<M> {
+    /// Encodes into any muxer. Its header must carry the parameters of `config`, see
+    /// `EncoderConfig::apply`.
+    pub fn with_muxer(mut writer: M, palette: &Palette, config: EncoderConfig) -> Result<Encoder<M>> {
+        let header = writer.header().clone();
+        if header.width == 0 || header.height == 0 || header.width > u16::MAX as u32 || header.height > u16::MAX as u32
+        {
+            bail!("Unsupported frame size {}x{}", header.width, header.height);
+        }
+        if header.fps_num == 0 || header.fps_den == 0 {
+            bail!("Unsupported frame rate {}/{}", header.fps_num, header.fps_den);
+        }
+        check_palette(palette)?;
+        if !(2..=16).contains(&config.block_size) || !config.block_size.is_power_of_two() {
+            bail!("Block size must be a power of two from 2 to 16");
+        }
+        if config.motion_radius > 127 {
+            bail!("Motion radius must not exceed 127");
+        }
+        if config.motion_radius > header.width.max(header.height) {
+            bail!("Motion radius must not exceed the frame size");
+        }
+        if config.codebook_size as usize > codebook::MAX_ENTRIES {
+            bail!("Codebook size must not exceed {}", codebook::MAX_ENTRIES);
+        }
+        if config.codebook_size > 0 && config.block_size != 2 && config.block_size != 4 {
+            bail!("Codebook mode requires block size 2 or 4");
+        }
+        if config.skip_threshold.is_nan() || config.skip_threshold < 0.0 {
+            bail!("Skip threshold must not be negative");
+        }
+        if config.parallel_groups == 0 {
+            bail!("At least one group of frames must be coded at a time");
+        }
+        let mut expected = header.clone();
+        config.apply(&mut expected);
+        if (header.block_size, header.motion_radius, header.codebook, header.entropy)
+            != (
+                expected.block_size,
+                expected.motion_radius,
+                expected.codebook,
+                expected.entropy,
+            )
+        {
+            bail!("Stream header doesn't match the encoder configuration");
+        }
+        let audio_coder = match &header.audio {
+            Some(audio) if audio.sample_rate == 0 || audio.channels == 0 || audio.channels > u8::MAX as u32 => {
+                bail!("Unsupported audio format")
+            }
+            Some(audio) => Some(Codec::from_id(audio.codec)?.coder(audio.channels)),
+            None => None,
+        };
+        let params = FrameParams::new(&header, palette);
+        writer.write_palette(palette)?;
+        let mut initial = palette.clone();
+        initial.clear_cycles();
+        Ok(Encoder {
+            writer,
+            params,
+            palette: initial,
+            // The cycling ranges are written along with the first frame
+            next_palette: Some(palette.clone()),
+            initial_palette: encode_palette(palette),
+            placer: KeyframePlacer::new(config.keyframe_interval, config.scene_threshold, config.scene_metric),
+            rate: match &config.first_pass {
+                Some(stats) => RateControl::with_stats(config.budget, header.fps(), stats),
+                None => RateControl::new(config.budget, header.fps()),
+            },
+            group_coder: GroupCoder {
+                candidates: motion::candidates(config.motion_radius),
+                coder: config.entropy.coder(),
+                config,
+            },
+            group: Group::default(),
+            pending: vec![],
+            frames: 0,
+            audio: vec![],
+            audio_coder,
+        })
+    }
+
+    /// Queues interleaved samples of the audio track. They are written in pieces along with the frames.
+    pub fn push_audio(&mut self, samples: &[i16]) {
+        self.audio.extend_from_slice(samples);
+    }
+
+    /// Number of interleaved samples that accompany the next frame.
+    pub fn audio_samples(&self) -> usize {
+        let header = self.writer.header();
+        match &header.audio {
+            Some(audio) => header.audio_samples(self.frames) * audio.channels as usize,
+            None => 0,
+        }
+    }
+
+    /// Number of interleaved samples waiting in the queue.
+    pub fn queued_audio(&self) -> usize {
+        self.audio.len()
+    }
+
+    /// Switches to another palette from the next frame on. Only the changed colors are stored
+    /// when that is smaller, a different number of colors forces a keyframe. The cycling ranges
+    /// of the palette are stored as well.
+    pub fn set_palette(&mut self, palette: &Palette) -> Result<()> {
+        check_palette(palette)?;
+        self.next_palette = Some(palette.clone());
+        Ok(())
+    }
+
+    /// Settles the palette changes of the next frame. Keyframes repeat the whole palette once it
+    /// differs from the initial one, so that seeking restores it.
+    fn plan_palette(&mut self, keyframe: bool, chunks: &mut Vec<FrameChunk>) -> usize {
+        let palette = match self.next_palette.take() {
+            Some(palette) => palette,
+            None if keyframe => self.palette.clone(),
+            None => return 0,
+        };
+        let full = encode_palette(&palette);
+        let entries = palette_delta(&self.palette, &palette);
+        let mut size = 0;
+        let rewrite = palette.len() != self.palette.len()
+            || (keyframe && full != self.initial_palette)
+            || (!entries.is_empty() && 2 + entries.len() * 4 >= full.len());
+        if rewrite {
+            size += full.len() + CHUNK_HEADER_SIZE;
+            chunks.push(FrameChunk::Palette(palette.clone()));
+        } else if !entries.is_empty() {
+            size += 2 + entries.len() * 4 + CHUNK_HEADER_SIZE;
+            chunks.push(FrameChunk::PaletteDelta(entries));
+        }
+        // A full palette clears the cycling ranges and so does seeking to a keyframe
+        let kept = if rewrite { &[][..] } else { self.palette.cycles() };
+        let restored = if keyframe { &[][..] } else { kept };
+        if palette.cycles() != kept || palette.cycles() != restored {
+            size += encode_cycles(palette.cycles()).len() + CHUNK_HEADER_SIZE;
+            chunks.push(FrameChunk::Cycles(palette.cycles().to_vec()));
+        }
+        self.params = FrameParams::new(self.writer.header(), &palette);
+        self.palette = palette;
+        size
+    }
+
+    /// Encodes the queued sound of the next frame, padded with silence if the track ends early.
+    fn plan_audio(&mut self, chunks: &mut Vec<FrameChunk>) -> usize {
+        let count = self.audio_samples();
+        let coder = match &mut self.audio_coder {
+            Some(coder) => coder,
+            None => return 0,
+        };
+        self.audio.resize(self.audio.len().max(count), 0);
+        let data = coder.encode(&self.audio[..count]);
+        self.audio.drain(..count);
+        let size = data.len() + CHUNK_HEADER_SIZE;
+        chunks.push(FrameChunk::Audio(data));
+        size
+    }
+
+    fn is_parallel(&self) -> bool {
+        self.group_coder.config.parallel_groups > 1 && !self.rate.is_limited()
+    }
+
+    /// Encodes the next frame and returns the statistics of the frames written to the stream.
+    /// That is the frame itself unless groups are coded in parallel, then whole groups are written
+    /// at once after enough of them have been collected. `flush` writes the remaining ones.
+    pub fn encode(&mut self, frame: &Plane<i32>) -> Result<Vec<FrameStats>> {
+        let header = self.writer.header();
+        if frame.width != header.width || frame.height != header.height {
+            bail!(
+                "Frame size {}x{} doesn't match stream size {}x{}",
+                frame.width,
+                frame.height,
+                header.width,
+                header.height
+            );
+        }
+        let colors = match &self.next_palette {
+            Some(palette) => palette.len(),
+            None => self.palette.len(),
+        };
+        if let Some(index) = frame.data.iter().find(|i| **i < 0 || **i as usize >= colors) {
+            bail!("Color index {} is out of palette range", index);
+        }
+
+        let budget = self.rate.allowance();
+        let mut chunks = vec![];
+        let audio_size = self.plan_audio(&mut chunks);
+        let scene_cut = self.placer.is_scene_cut(frame);
+        let keyframe = self.placer.next(frame, colors != self.palette.len()) || self.frames == 0;
+        let palette_size = self.plan_palette(keyframe, &mut chunks);
+        self.frames += 1;
+        let planned = PlannedFrame {
+            frame: frame.clone(),
+            keyframe,
+            scene_cut,
+            chunks,
+            palette: self.palette.clone(),
+            params: self.params,
+            budget,
+            audio_size,
+            palette_size,
+        };
+
+        if !self.is_parallel() {
+            let coded = self.group_coder.code(&mut self.group, &planned);
+            return Ok(vec![self.write(planned, coded)?]);
+        }
+        let mut stats = vec![];
+        if keyframe && self.pending.len() >= self.group_coder.config.parallel_groups {
+            stats = self.flush()?;
+        }
+        // A delta frame right after a flush continues the group written last
+        if keyframe || self.pending.is_empty() {
+            self.pending.push(vec![]);
+        }
+        match self.pending.last_mut() {
+            Some(group) => group.push(planned),
+            None => bail!("No group to add frame {} to", self.frames - 1),
+        }
+        Ok(stats)
+    }
+
+    /// Codes the groups held back in parallel, writes them and returns the statistics of their frames.
+    /// The state of the last group is kept, so the frames that follow may continue it.
+    pub fn flush(&mut self) -> Result<Vec<FrameStats>> {
+        let mut states: Vec<Group> = self.pending.iter().map(|_| Group::default()).collect();
+        if let (Some(frames), Some(state)) = (self.pending.first(), states.first_mut())
+            && frames.first().is_some_and(|planned| !planned.keyframe)
+        {
+            *state = std::mem::take(&mut self.group);
+        }
+        let group_coder = &self.group_coder;
+        let coded: Vec<Vec<CodedFrame>> = self
+            .pending
+            .par_iter()
+            .zip(states.par_iter_mut())
+            .map(|(frames, group)| frames.iter().map(|planned| group_coder.code(group, planned)).collect())
+            .collect();
+        if let Some(group) = states.pop() {
+            self.group = group;
+        }
+        let mut stats = vec![];
+        for (frames, coded) in std::mem::take(&mut self.pending).into_iter().zip(coded) {
+            for (planned, coded) in frames.into_iter().zip(coded) {
+                stats.push(self.write(planned, coded)?);
+            }
+        }
+        Ok(stats)
+    }
+
+    fn write(&mut self, planned: PlannedFrame, coded: CodedFrame) -> Result<FrameStats> {
+        for chunk in planned.chunks.iter() {
+            match chunk {
+                FrameChunk::Palette(palette) => self.writer.write_palette(palette)?,
+                FrameChunk::PaletteDelta(entries) => self.writer.write_palette_delta(entries)?,
+                FrameChunk::Cycles(ranges) => self.writer.write_cycles(ranges)?,
+                FrameChunk::Audio(data) => self.writer.write_audio(data)?,
+            }
+        }
+        let size = coded.data.len() + FRAME_OVERHEAD;
+        self.rate.spend(size + planned.audio_size + planned.palette_size);
+        self.writer.write_frame(planned.keyframe, &coded.data)?;
+        Ok(FrameStats {
+            keyframe: planned.keyframe,
+            size,
+            audio_size: planned.audio_size,
+            palette_size: planned.palette_size,
+            level: coded.level,
+            budget: planned.budget,
+            skip_error: coded.skip_error,
+            scene_cut: planned.scene_cut,
+            changed_blocks: coded.changed_blocks,
+            cycles: coded.cycles,
+        })
+    }
+
+    /// Writes the groups held back and completes the stream, call `flush` first for their statistics.
+    pub fn finish(mut self) -> Result<M::Output> {
+        self.flush()?;
+        self.writer.finish()
+    }
+}
+
+impl GroupCoder {
+    fn code(&self, group: &mut Group, planned: &PlannedFrame) -> CodedFrame {
+        let (coded, reconstructed) = match &group.reference {
+            Some(reference) if !planned.keyframe => {
+                let extra_size = (planned.audio_size + planned.palette_size) as f64;
+                self.encode_delta(planned, &group.codebook, reference, planned.budget - extra_size)
+            }
+            _ => self.encode_keyframe(planned, &mut group.codebook),
+        };
+        group.reference = Some(reconstructed);
+        coded
+    }
+
+    /// Estimated decoding cycles of a coded frame.
+    fn cycles(
+        &self,
+        planned: &PlannedFrame,
+        ops: &[BlockOp],
+        codebook_size: Option<usize>,
+        payload: &[u8],
+        data: &[u8],
+    ) -> u64 {
+        let frame = &planned.frame;
+        self.config.cost_model.frame_cycles(
+            frame.width,
+            frame.height,
+            planned.params.block_size,
+            ops,
+            codebook_size,
+            &self.coder.items(data, payload.len()),
+        )
+    }
+
+    /// Codes a delta frame at increasing degradation levels until it fits the budget and the
+    /// cycle budget.
+    fn encode_delta(
+        &self,
+        planned: &PlannedFrame,
+        codebook: &Codebook,
+        reference: &Plane<i32>,
+        budget: f64,
+    ) -> (CodedFrame, Plane<i32>) {
+        let frame = &planned.frame;
+        let changed_blocks = blocks(frame.width, frame.height, planned.params.block_size)
+            .filter(|block| block.is_changed(frame, reference))
+            .count();
+        // Nothing but skipped blocks can fit once the budget is overdrawn
+        let mut level = if budget <= FRAME_OVERHEAD as f64 { LEVELS } else { 0 };
+        loop {
+            let quality = Quality::level(level, planned.params.block_size);
+            let (ops, skip_error) = self.choose_ops(planned, codebook, reference, &quality);
+            let mut bits = planned.params.writer();
+            encode_inter(&mut bits, frame, &ops, &planned.params, codebook);
+            let payload = bits.finish();
+            let data = self.coder.compress(&payload);
+            let cycles = self.cycles(planned, &ops, None, &payload, &data);
+            let fits = (data.len() + FRAME_OVERHEAD) as f64 <= budget
+                && (self.config.cycle_budget == 0 || cycles <= self.config.cycle_budget);
+            if level == LEVELS || fits {
+                let coded = CodedFrame {
+                    data,
+                    level,
+                    skip_error,
+                    changed_blocks: changed_blocks as u32,
+                    cycles,
+                };
+                return (coded, self.reconstruct(planned, codebook, reference, &ops));
+            }
+            level += 1;
+        }
+    }
+
+    fn encode_keyframe(&self, planned: &PlannedFrame, codebook: &mut Codebook) -> (CodedFrame, Plane<i32>) {
+        let (frame, params) = (&planned.frame, &planned.params);
+        let mut bits = params.writer();
+        let mut reconstructed = frame.clone();
+        let (ops, codebook_size) = if params.codebook {
+            *codebook = Codebook::build(
+                frame,
+                &planned.palette,
+                params.block_size,
+                self.config.codebook_size as usize,
+                self.config.codebook_steps,
+            );
+            let mut entries = vec![];
+            for block in blocks(frame.width, frame.height, params.block_size) {
+                let entry = codebook.nearest(frame, &block, &planned.palette);
+                codebook.apply(entry, &block, &mut reconstructed);
+                entries.push(entry);
+            }
+            encode_intra_codebook(&mut bits, codebook, &entries, params);
+            let ops: Vec<BlockOp> = entries.into_iter().map(BlockOp::Entry).collect();
+            (ops, Some(codebook.tiles.len()))
+        } else {
+            encode_intra(&mut bits, frame, params);
+            (
+                vec![BlockOp::Raw; block_count(frame.width, frame.height, params.block_size)],
+                None,
+            )
+        };
+        let payload = bits.finish();
+        let data = self.coder.compress(&payload);
+        let coded = CodedFrame {
+            cycles: self.cycles(planned, &ops, codebook_size, &payload, &data),
+            data,
+            level: 0,
+            skip_error: SkipError::default(),
+            changed_blocks: ops.len() as u32,
+        };
+        (coded, reconstructed)
+    }
+
+    /// Perceptual difference between the block of two frames, see `EncoderConfig::skip_threshold`.
+    fn block_error(&self, planned: &PlannedFrame, reference: &Plane<i32>, block: &Block) -> f64 {
+        block.max_difference(&planned.frame, reference, &planned.palette).sqrt() * 255.0
+    }
+
+    fn choose_op(
+        &self,
+        planned: &PlannedFrame,
+        codebook: &Codebook,
+        reference: &Plane<i32>,
+        block: &Block,
+        quality: &Quality,
+        skip_error: &mut SkipError,
+    ) -> BlockOp {
+        let frame = &planned.frame;
+        if block.count_changed(frame, reference) <= quality.stale_pixels {
+            return BlockOp::Skip;
+        }
+        if self.config.skip_threshold > 0.0 {
+            let error = self.block_error(planned, reference, block);
+            if error <= self.config.skip_threshold {
+                skip_error.add(&SkipError {
+                    blocks: 1,
+                    total: error,
+                    max: error,
+                });
+                return BlockOp::Skip;
+            }
+        }
+        if let Some(vector) = motion::search(frame, reference, block, &self.candidates, quality.motion_error) {
+            return BlockOp::Motion(vector);
+        }
+        if !planned.params.codebook {
+            return if quality.fill {
+                BlockOp::Fill(block.dominant(frame))
+            } else {
+                BlockOp::Raw
+            };
+        }
+        let entry = codebook.nearest(frame, block, &planned.palette);
+        if codebook.matches(entry, block, reference) {
+            BlockOp::Skip
+        } else {
+            BlockOp::Entry(entry)
+        }
+    }
+
+    fn choose_ops(
+        &self,
+        planned: &PlannedFrame,
+        codebook: &Codebook,
+        reference: &Plane<i32>,
+        quality: &Quality,
+    ) -> (Vec<BlockOp>, SkipError) {
+        let frame = &planned.frame;
+        let mut skip_error = SkipError::default();
+        let ops = blocks(frame.width, frame.height, planned.params.block_size)
+            .map(|block| self.choose_op(planned, codebook, reference, &block, quality, &mut skip_error))
+            .collect();
+        (ops, skip_error)
+    }
+
+    /// Applies the chosen operations the same way the decoder will.
+    fn reconstruct(
+        &self,
+        planned: &PlannedFrame,
+        codebook: &Codebook,
+        reference: &Plane<i32>,
+        ops: &[BlockOp],
+    ) -> Plane<i32> {
+        let frame = &planned.frame;
+        let mut result = reference.clone();
+        for (block, op) in blocks(frame.width, frame.height, planned.params.block_size).zip(ops) {
+            match op {
+                BlockOp::Skip => {}
+                BlockOp::Raw => block.copy(frame, &mut result),
+                BlockOp::Motion(vector) => {
+                    if let Some(source) = vector.source(&block, frame.width, frame.height) {
+                        for ((x, y), (sx, sy)) in block.pixels().zip(source.pixels()) {
+                            result.set(x, y, reference.get(sx, sy));
+                        }
+                    }
+                }
+                BlockOp::Entry(entry) => codebook.apply(*entry, &block, &mut result),
+                BlockOp::Fill(index) => {
+                    for (x, y) in block.pixels() {
+                        result.set(x, y, *index);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decoder::Decoder, testing};
+    use rvc_shared::{
+        colors::FloatColor,
+        container::{Chunk, ContainerReader},
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn decoder_matches_input() {
+        let frames = testing::clip(40, 20);
+        for (block_size, motion_radius) in [(2, 8), (4, 0), (16, 3)] {
+            let config = EncoderConfig {
+                block_size,
+                motion_radius,
+                ..Default::default()
+            };
+            let stream = testing::encode(config, &frames, usize::MAX);
+            let mut decoder = Decoder::new(Cursor::new(stream)).unwrap();
+            assert_eq!(decoder.header().frame_count, frames.len() as u32);
+            for frame in frames.iter() {
+                assert_eq!(decoder.next_frame().unwrap().unwrap().data, frame.data);
+            }
+            assert!(decoder.next_frame().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn decoder_matches_reconstruction() {
+        let frames = testing::clip(40, 20);
+        let configs = [
+            EncoderConfig {
+                block_size: 2,
+                codebook_size: 256,
+                keyframe_interval: 10,
+                entropy: Backend::Rle,
+                ..Default::default()
+            },
+            EncoderConfig {
+                block_size: 4,
+                codebook_size: 16,
+                keyframe_interval: 10,
+                entropy: Backend::Huffman,
+                ..Default::default()
+            },
+            EncoderConfig {
+                block_size: 4,
+                budget: Budget::BytesPerFrame(120),
+                skip_threshold: 2.0,
+                entropy: Backend::Lz77,
+                ..Default::default()
+            },
+        ];
+        // The byte budget has to push some frames to the levels that fill blocks
+        let mut degraded = false;
+        // Every kind of block operation has to come up in one of the streams
+        let mut kinds = [false; 5];
+        for config in configs {
+            let mut encoder =
+                Encoder::new(Cursor::new(vec![]), testing::header(), &testing::palette(), config).unwrap();
+            let mut references = vec![];
+            for frame in frames.iter() {
+                degraded |= encoder.encode(frame).unwrap()[0].level >= LEVELS / 2;
+                references.push(encoder.group.reference.clone().unwrap());
+            }
+            let stream = encoder.finish().unwrap().into_inner();
+            let mut decoder = Decoder::new(Cursor::new(stream)).unwrap();
+            let mut keyframes = 0;
+            for reference in references.iter() {
+                assert_eq!(decoder.next_frame().unwrap().unwrap().data, reference.data);
+                keyframes += decoder.info().keyframe as usize;
+                for op in decoder.info().ops.iter() {
+                    kinds[match op {
+                        BlockOp::Skip => 0,
+                        BlockOp::Raw => 1,
+                        BlockOp::Motion(_) => 2,
+                        BlockOp::Entry(_) => 3,
+                        BlockOp::Fill(_) => 4,
+                    }] = true;
+                }
+            }
+            assert!(keyframes > 0 && keyframes < references.len());
+            assert!(decoder.next_frame().unwrap().is_none());
+        }
+        assert!(degraded);
+        assert_eq!(kinds, [true; 5]);
+    }
+
+    #[test]
+    fn skip_threshold_keeps_large_changes() {
+        let mut palette = Palette::new();
+        for color in [(0, 0, 0), (3, 3, 3), (40, 40, 40), (255, 255, 255)] {
+            palette.add(FloatColor::new(color.0, color.1, color.2));
+        }
+        let first = Plane::new(testing::WIDTH, testing::HEIGHT, 0);
+        let mut second = first.clone();
+        // A barely visible block, a dim one and a single bright pixel that averages out to dim
+        for y in 0..4 {
+            for x in 0..4 {
+                second.set(x, y, 1);
+                second.set(x + 8, y, 2);
+            }
+        }
+        second.set(17, 2, 3);
+        let mut results = vec![];
+        for skip_threshold in [0.0, 10.0, 100.0] {
+            let config = EncoderConfig {
+                block_size: 4,
+                motion_radius: 0,
+                skip_threshold,
+                ..Default::default()
+            };
+            let mut encoder = Encoder::new(Cursor::new(vec![]), testing::header(), &palette, config).unwrap();
+            encoder.encode(&first).unwrap();
+            let stats = encoder.encode(&second).unwrap()[0];
+            let stream = encoder.finish().unwrap().into_inner();
+            let mut decoder = Decoder::new(Cursor::new(stream)).unwrap();
+            decoder.next_frame().unwrap();
+            let frame = decoder.next_frame().unwrap().unwrap();
+            results.push((frame.get(0, 0), frame.get(8, 0), frame.get(17, 2), stats.skip_error.blocks));
+        }
+        assert_eq!(results, [(1, 2, 3, 0), (0, 2, 3, 1), (0, 0, 3, 2)]);
+    }
+
+    #[test]
+    fn cycle_budget_degrades_delta_frames() {
+        let frames = testing::clip(30, 30);
+        let config = EncoderConfig {
+            block_size: 4,
+            keyframe_interval: 10,
+            ..Default::default()
+        };
+        let mut encoder =
+            Encoder::new(Cursor::new(vec![]), testing::header(), &testing::palette(), config.clone()).unwrap();
+        let unlimited: Vec<FrameStats> = frames.iter().map(|frame| encoder.encode(frame).unwrap()[0]).collect();
+        let most = unlimited.iter().filter(|stats| !stats.keyframe).map(|stats| stats.cycles).max().unwrap();
+
+        let cycle_budget = most * 3 / 4;
+        let config = EncoderConfig { cycle_budget, ..config };
+        let mut encoder = Encoder::new(Cursor::new(vec![]), testing::header(), &testing::palette(), config).unwrap();
+        let mut degraded = 0;
+        for (frame, unlimited) in frames.iter().zip(unlimited.iter()) {
+            let stats = encoder.encode(frame).unwrap()[0];
+            assert_eq!(stats.keyframe, unlimited.keyframe);
+            if stats.keyframe {
+                // Keyframes are coded in full whatever they cost
+                assert_eq!((stats.level, stats.cycles), (0, unlimited.cycles));
+            } else {
+                assert!(stats.cycles <= cycle_budget || stats.level == LEVELS);
+                degraded += (stats.level > 0) as usize;
+            }
+        }
+        assert!(degraded > 0);
+    }
+
+    #[test]
+    fn byte_rate_stays_within_budget() {
+        // Still frames leave bytes unused before the motion starts
+        let mut frames = testing::clip(100, 100);
+        let still = frames[0].clone();
+        frames[..30].fill(still);
+        let rate = 2000;
+        let config = EncoderConfig {
+            block_size: 4,
+            scene_threshold: 0.0,
+            budget: Budget::BytesPerSecond(rate),
+            ..Default::default()
+        };
+        let mut encoder = Encoder::new(Cursor::new(vec![]), testing::header(), &testing::palette(), config).unwrap();
+        let stats: Vec<FrameStats> = frames.iter().map(|frame| encoder.encode(frame).unwrap()[0]).collect();
+        let sizes: Vec<usize> = stats.iter().map(|stats| stats.size + stats.audio_size + stats.palette_size).collect();
+        // The keyframe overdraws the budget and the frames after it skip everything until it is paid off
+        let settled = stats.iter().position(|stats| !stats.over_budget()).unwrap();
+        assert!(settled < 25 && stats[settled..].iter().all(|stats| !stats.over_budget()));
+        assert!(stats[settled..].iter().any(|stats| stats.level > 0));
+        // Every run of frames fits its share plus a second of slack
+        let per_frame = rate as f64 / 25.0;
+        for start in settled..sizes.len() {
+            let mut total = 0;
+            for (length, size) in sizes[start..].iter().enumerate() {
+                total += size;
+                assert!(total as f64 <= per_frame * (length + 1) as f64 + rate as f64);
+            }
+        }
+        // The motion after the still frames uses the bytes saved up
+        let burst: usize = sizes[30..55].iter().sum();
+        assert!(burst > rate as usize && burst <= 2 * rate as usize, "{}", burst);
+    }
+
+    #[test]
+    fn palette_switches() {
+        let frames: Vec<Plane<i32>> = testing::clip(24, 24)
+            .into_iter()
+            .map(|mut frame| {
+                frame.data.iter_mut().for_each(|index| *index %= 12);
+                frame
+            })
+            .collect();
+        let mut single = testing::palette();
+        single.set(3, FloatColor::new(1, 2, 3));
+        let mut all = Palette::new();
+        for i in 0..16 {
+            all.add(FloatColor::new(255 - i * 16, i * 9, 128));
+        }
+        let mut fewer = Palette::new();
+        for i in 0..12 {
+            fewer.add(all.get(i));
+        }
+        // Palette in effect from each frame on
+        let mut palettes = vec![testing::palette(); 5];
+        palettes.extend(vec![single.clone(); 5]);
+        palettes.extend(vec![all.clone(); 5]);
+        palettes.extend(vec![fewer.clone(); 9]);
+
+        let config = EncoderConfig {
+            block_size: 4,
+            scene_threshold: 0.0,
+            ..Default::default()
+        };
+        let mut encoder = Encoder::new(Cursor::new(vec![]), testing::header(), &testing::palette(), config).unwrap();
+        let mut keyframes = vec![];
+        for (number, frame) in frames.iter().enumerate() {
+            match number {
+                5 => encoder.set_palette(&single).unwrap(),
+                10 => encoder.set_palette(&all).unwrap(),
+                15 => encoder.set_palette(&fewer).unwrap(),
+                _ => {}
+            }
+            if encoder.encode(frame).unwrap()[0].keyframe {
+                keyframes.push(number);
+            }
+        }
+        // A different number of colors needs a keyframe
+        assert_eq!(keyframes, [0, 15]);
+        let stream = encoder.finish().unwrap().into_inner();
+
+        // Single changes are stored as a delta, the others as whole palettes
+        let mut reader = ContainerReader::new(Cursor::new(stream.clone())).unwrap();
+        let mut chunks = vec![];
+        let mut number = 0;
+        while let Some(chunk) = reader.next_chunk().unwrap() {
+            match chunk {
+                Chunk::Palette(palette) => chunks.push((number, palette.len(), false)),
+                Chunk::PaletteDelta(entries) => chunks.push((number, entries.len(), true)),
+                Chunk::Frame(_) => number += 1,
+                _ => {}
+            }
+        }
+        assert_eq!(chunks, [(0, 16, false), (5, 1, true), (10, 16, false), (15, 12, false)]);
+
+        let mut decoder = Decoder::new(Cursor::new(stream)).unwrap();
+        for (number, palette) in palettes.iter().enumerate() {
+            decoder.next_frame().unwrap().unwrap();
+            assert_eq!(encode_palette(decoder.palette()), encode_palette(palette), "frame {}", number);
+            assert_eq!(decoder.info().palette_update, [5, 10, 15].contains(&number));
+        }
+        for target in [12, 7, 3, 20] {
+            decoder.seek(target).unwrap();
+            decoder.next_frame().unwrap().unwrap();
+            assert_eq!(
+                encode_palette(decoder.palette()),
+                encode_palette(&palettes[target as usize]),
+                "frame {}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let mut encoder = Encoder::new(
+            Cursor::new(vec![]),
+            testing::header(),
+            &testing::palette(),
+            EncoderConfig::default(),
+        )
+        .unwrap();
+        let mut frame = Plane::new(testing::WIDTH, testing::HEIGHT, 0);
+        frame.set(3, 4, 16);
+        assert!(encoder.encode(&frame).is_err());
+        assert!(encoder.encode(&Plane::new(testing::WIDTH + 1, testing::HEIGHT, 0)).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_block_size() {
+        for block_size in [0, 1, 3, 32] {
+            let config = EncoderConfig {
+                block_size,
+                ..Default::default()
+            };
+            assert!(Encoder::new(Cursor::new(vec![]), testing::header(), &testing::palette(), config).is_err());
+        }
+    }
+
+    #[test]
+    fn parallel_groups_match_serial_encode() {
+        let frames = testing::clip(60, 25);
+        for codebook_size in [0, 16] {
+            let config = EncoderConfig {
+                block_size: 4,
+                keyframe_interval: 8,
+                codebook_size,
+                ..Default::default()
+            };
+            // Flushing in the middle of a group leaves a delta frame to open the next batch
+            let serial = testing::encode(config.clone(), &frames, 21);
+            let parallel = testing::encode(
+                EncoderConfig {
+                    parallel_groups: 4,
+                    ..config
+                },
+                &frames,
+                21,
+            );
+            assert!(parallel == serial, "codebook size {}", codebook_size);
+
+            let mut decoder = Decoder::new(Cursor::new(parallel)).unwrap();
+            let mut keyframes = 0;
+            while decoder.next_frame().unwrap().is_some() {
+                keyframes += decoder.info().keyframe as usize;
+            }
+            assert_eq!(decoder.position(), 60);
+            assert!(keyframes > 4);
+        }
+    }
+}
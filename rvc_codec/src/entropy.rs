@@ -0,0 +1,173 @@
+use anyhow::{Result, bail};
+
+pub mod huffman;
+pub mod lz77;
+pub mod rle;
+
+/// Parts of compressed data that the decoding time depends on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EntropyItems {
+    /// Bytes decoded one at a time.
+    pub literals: usize,
+    /// Matches or runs that repeat earlier bytes.
+    pub matches: usize,
+    /// Bytes produced by the matches.
+    pub match_bytes: usize,
+}
+
+/// Lossless compressor for frame payloads.
+pub trait EntropyCoder {
+    fn compress(&self, data: &[u8]) -> Vec<u8>;
+    /// Restores the payload, failing if it would grow beyond `max_size` bytes.
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>>;
+    /// Counts the items of compressed data that restores to `size` bytes.
+    fn items(&self, _data: &[u8], size: usize) -> EntropyItems {
+        EntropyItems {
+            literals: size,
+            ..Default::default()
+        }
+    }
+}
+
+/// Entropy back-end of a stream, stored in the stream header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    None = 0,
+    Rle = 1,
+    Lz77 = 2,
+    Huffman = 3,
+}
+
+impl Backend {
+    pub fn from_id(id: u8) -> Result<Backend> {
+        Ok(match id {
+            0 => Backend::None,
+            1 => Backend::Rle,
+            2 => Backend::Lz77,
+            3 => Backend::Huffman,
+            _ => bail!("Unknown entropy back-end {}", id),
+        })
+    }
+
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Whether frame payloads store every index, opcode, skip flag and vector component in a byte
+    /// of its own rather than bit-packed. Runs and symbol frequencies are only visible to RLE and
+    /// Huffman coding that way, while LZ77 matches the repeats of the denser packed layout.
+    pub fn unpacked(&self) -> bool {
+        matches!(self, Backend::Rle | Backend::Huffman)
+    }
+
+    pub fn coder(&self) -> Box<dyn EntropyCoder + Send + Sync> {
+        match self {
+            Backend::None => Box::new(Store),
+            Backend::Rle => Box::new(rle::PackBits),
+            Backend::Lz77 => Box::new(lz77::Lz77),
+            Backend::Huffman => Box::new(huffman::Huffman),
+        }
+    }
+}
+
+/// Keeps the payload as is.
+pub struct Store;
+
+impl EntropyCoder for Store {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        data.to_vec()
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        if data.len() > max_size {
+            bail!("Frame payload is too large");
+        }
+        Ok(data.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Payloads with runs, repeated sequences and noise.
+    fn payloads() -> Vec<Vec<u8>> {
+        let mut seed = 1u32;
+        let noise = (0..5000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let repeats = (0..6000).map(|i| (i % 37) as u8 ^ (i / 500) as u8).collect();
+        let runs = (0..300u32)
+            .flat_map(|i| std::iter::repeat_n(i as u8, (i * 40 % 131 + 1) as usize))
+            .collect();
+        vec![vec![], vec![42], vec![1, 2], vec![7; 1000], noise, repeats, runs]
+    }
+
+    const BACKENDS: [Backend; 4] = [Backend::None, Backend::Rle, Backend::Lz77, Backend::Huffman];
+
+    #[test]
+    fn roundtrip() {
+        for backend in BACKENDS {
+            let coder = backend.coder();
+            for data in payloads() {
+                let compressed = coder.compress(&data);
+                assert_eq!(
+                    coder.decompress(&compressed, data.len()).unwrap(),
+                    data,
+                    "{:?}",
+                    backend
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn payload_limit() {
+        for backend in BACKENDS {
+            let coder = backend.coder();
+            let data = vec![7; 1000];
+            assert!(coder.decompress(&coder.compress(&data), 999).is_err(), "{:?}", backend);
+        }
+    }
+
+    /// Decompresses truncated compressed payloads and returns how many of them decoded. Those
+    /// must give the start of the payload.
+    fn truncated(backend: Backend) -> usize {
+        let coder = backend.coder();
+        let mut decoded = 0;
+        for data in payloads() {
+            let compressed = coder.compress(&data);
+            for length in (0..compressed.len()).step_by(compressed.len() / 100 + 1) {
+                if let Ok(result) = coder.decompress(&compressed[..length], data.len()) {
+                    assert!(result.len() < data.len() && data.starts_with(&result), "{:?}", backend);
+                    decoded += 1;
+                }
+            }
+        }
+        decoded
+    }
+
+    #[test]
+    fn truncated_packbits() {
+        // Cutting the data between runs leaves a valid shorter stream
+        truncated(Backend::Rle);
+        assert!(rle::PackBits.decompress(&[5, 1, 2], 100).is_err());
+        assert!(rle::PackBits.decompress(&[200], 100).is_err());
+    }
+
+    #[test]
+    fn truncated_lzss() {
+        truncated(Backend::Lz77);
+        let compressed = lz77::Lz77.compress(&[7; 100]);
+        assert!(lz77::Lz77.decompress(&compressed[..compressed.len() - 1], 100).is_err());
+    }
+
+    #[test]
+    fn truncated_huffman() {
+        // The payload size comes first, so any cut is noticed
+        assert_eq!(truncated(Backend::Huffman), 0);
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{Result, bail};
+
+use super::EntropyCoder;
+use crate::bits::{BitReader, BitWriter};
+
+/// Canonical Huffman coding of payload bytes. The data starts with the decoded size (u32),
+/// followed by 4-bit code lengths of all 256 byte values and the codes, most significant bit first.
+pub struct Huffman;
+
+pub const MAX_LENGTH: u32 = 15;
+const SYMBOLS: usize = 256;
+
+/// Code lengths for the given symbol frequencies, limited to `MAX_LENGTH` bits.
+pub fn code_lengths(frequencies: &[u64; SYMBOLS]) -> [u8; SYMBOLS] {
+    let mut frequencies = *frequencies;
+    loop {
+        let lengths = build_lengths(&frequencies);
+        if lengths.iter().all(|l| *l as u32 <= MAX_LENGTH) {
+            return lengths;
+        }
+        // Flatten the distribution until the tree is shallow enough
+        for f in frequencies.iter_mut() {
+            if *f > 0 {
+                *f = (*f >> 1).max(1);
+            }
+        }
+    }
+}
+
+fn build_lengths(frequencies: &[u64; SYMBOLS]) -> [u8; SYMBOLS] {
+    let mut lengths = [0u8; SYMBOLS];
+    // Nodes are (weight, children or symbol), leaves first
+    let mut weights: Vec<u64> = vec![];
+    let mut parents: Vec<usize> = vec![];
+    let mut leaves: Vec<usize> = vec![];
+    for (symbol, frequency) in frequencies.iter().enumerate() {
+        if *frequency > 0 {
+            leaves.push(symbol);
+            weights.push(*frequency);
+            parents.push(usize::MAX);
+        }
+    }
+    match leaves.len() {
+        0 => return lengths,
+        1 => {
+            lengths[leaves[0]] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    let mut active: Vec<usize> = (0..leaves.len()).collect();
+    while active.len() > 1 {
+        active.sort_by(|a, b| weights[*b].cmp(&weights[*a]).then(b.cmp(a)));
+        let first = active.pop().unwrap();
+        let second = active.pop().unwrap();
+        let node = weights.len();
+        weights.push(weights[first] + weights[second]);
+        parents.push(usize::MAX);
+        parents[first] = node;
+        parents[second] = node;
+        active.push(node);
+    }
+
+    for (leaf, symbol) in leaves.iter().enumerate() {
+        let mut depth = 0;
+        let mut node = leaf;
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            depth += 1;
+        }
+        lengths[*symbol] = depth;
+    }
+    lengths
+}
+
+/// Assigns canonical codes: shorter codes first, equal lengths in symbol order.
+pub fn canonical_codes(lengths: &[u8; SYMBOLS]) -> [u32; SYMBOLS] {
+    let mut codes = [0u32; SYMBOLS];
+    let mut code = 0;
+    for length in 1..=MAX_LENGTH as u8 {
+        for (symbol, symbol_length) in lengths.iter().enumerate() {
+            if *symbol_length == length {
+                codes[symbol] = code;
+                code += 1;
+            }
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+impl EntropyCoder for Huffman {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut frequencies = [0u64; SYMBOLS];
+        for byte in data {
+            frequencies[*byte as usize] += 1;
+        }
+        let lengths = code_lengths(&frequencies);
+        let codes = canonical_codes(&lengths);
+
+        let mut writer = BitWriter::new();
+        writer.write(data.len() as u32, 32);
+        for length in lengths.iter() {
+            writer.write(*length as u32, 4);
+        }
+        for byte in data {
+            writer.write(codes[*byte as usize], lengths[*byte as usize] as u32);
+        }
+        writer.finish()
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let mut reader = BitReader::new(data);
+        let size = reader.read(32)? as usize;
+        if size > max_size {
+            bail!("Frame payload is too large");
+        }
+        let mut lengths = [0u8; SYMBOLS];
+        for length in lengths.iter_mut() {
+            *length = reader.read(4)? as u8;
+        }
+
+        // Symbols sorted by code, and the number of codes of every length
+        let mut counts = [0u32; MAX_LENGTH as usize + 1];
+        let mut symbols = vec![];
+        for length in 1..=MAX_LENGTH as u8 {
+            for (symbol, symbol_length) in lengths.iter().enumerate() {
+                if *symbol_length == length {
+                    symbols.push(symbol as u8);
+                    counts[length as usize] += 1;
+                }
+            }
+        }
+
+        let mut result = Vec::with_capacity(size);
+        while result.len() < size {
+            let mut code = 0;
+            let mut first = 0;
+            let mut offset = 0;
+            let mut found = false;
+            for count in counts.iter().skip(1) {
+                code |= reader.read(1)?;
+                if code < first + count {
+                    result.push(symbols[(offset + code - first) as usize]);
+                    found = true;
+                    break;
+                }
+                offset += count;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+            if !found {
+                bail!("Invalid Huffman code");
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Result, bail};
+
+use super::{EntropyCoder, EntropyItems};
+
+/// LZSS with a 4 KiB window. Every group of 8 items starts with a flag byte, least significant bit first:
+/// a clear bit is a literal byte, a set bit is a 2-byte match with a 12-bit distance and a 4-bit length.
+pub struct Lz77;
+
+const WINDOW: usize = 4096;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = MIN_MATCH + 15;
+const HASH_SIZE: usize = 1 << 12;
+const MAX_CHAIN: usize = 64;
+
+fn hash(data: &[u8]) -> usize {
+    ((data[0] as usize) << 4 ^ (data[1] as usize) << 2 ^ data[2] as usize) & (HASH_SIZE - 1)
+}
+
+impl EntropyCoder for Lz77 {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut result = vec![];
+        let mut head = vec![usize::MAX; HASH_SIZE];
+        let mut chain = vec![usize::MAX; data.len()];
+        let mut flag_pos = 0;
+        let mut item = 8;
+        let mut pos = 0;
+
+        let insert = |head: &mut Vec<usize>, chain: &mut Vec<usize>, at: usize| {
+            if at + MIN_MATCH <= data.len() {
+                let h = hash(&data[at..]);
+                chain[at] = head[h];
+                head[h] = at;
+            }
+        };
+
+        while pos < data.len() {
+            if item == 8 {
+                flag_pos = result.len();
+                result.push(0);
+                item = 0;
+            }
+
+            let mut best_length = 0;
+            let mut best_distance = 0;
+            if pos + MIN_MATCH <= data.len() {
+                let mut candidate = head[hash(&data[pos..])];
+                let mut steps = 0;
+                while candidate != usize::MAX && pos - candidate <= WINDOW && steps < MAX_CHAIN {
+                    let limit = MAX_MATCH.min(data.len() - pos);
+                    let mut length = 0;
+                    while length < limit && data[candidate + length] == data[pos + length] {
+                        length += 1;
+                    }
+                    if length > best_length {
+                        best_length = length;
+                        best_distance = pos - candidate;
+                        if length == limit {
+                            break;
+                        }
+                    }
+                    candidate = chain[candidate];
+                    steps += 1;
+                }
+            }
+
+            if best_length >= MIN_MATCH {
+                result[flag_pos] |= 1 << item;
+                let token = ((best_distance - 1) << 4) | (best_length - MIN_MATCH);
+                result.push((token >> 8) as u8);
+                result.push(token as u8);
+                for at in pos..pos + best_length {
+                    insert(&mut head, &mut chain, at);
+                }
+                pos += best_length;
+            } else {
+                result.push(data[pos]);
+                insert(&mut head, &mut chain, pos);
+                pos += 1;
+            }
+            item += 1;
+        }
+        result
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let mut result: Vec<u8> = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            let flags = data[pos];
+            pos += 1;
+            for item in 0..8 {
+                if pos >= data.len() {
+                    break;
+                }
+                if flags & (1 << item) == 0 {
+                    result.push(data[pos]);
+                    pos += 1;
+                } else {
+                    if pos + 2 > data.len() {
+                        bail!("Unexpected end of LZ77 data");
+                    }
+                    let token = (data[pos] as usize) << 8 | data[pos + 1] as usize;
+                    pos += 2;
+                    let distance = (token >> 4) + 1;
+                    let length = (token & 15) + MIN_MATCH;
+                    if distance > result.len() {
+                        bail!("LZ77 match points before the start of data");
+                    }
+                    let start = result.len() - distance;
+                    for i in 0..length {
+                        result.push(result[start + i]);
+                    }
+                }
+                if result.len() > max_size {
+                    bail!("Frame payload is too large");
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn items(&self, data: &[u8], _size: usize) -> EntropyItems {
+        let mut items = EntropyItems::default();
+        let mut pos = 0;
+        while pos < data.len() {
+            let flags = data[pos];
+            pos += 1;
+            for item in 0..8 {
+                if pos >= data.len() {
+                    break;
+                }
+                if flags & (1 << item) == 0 {
+                    items.literals += 1;
+                    pos += 1;
+                } else if pos + 2 <= data.len() {
+                    items.matches += 1;
+                    items.match_bytes += (data[pos + 1] as usize & 15) + MIN_MATCH;
+                    pos += 2;
+                } else {
+                    break;
+                }
+            }
+        }
+        items
+    }
+}
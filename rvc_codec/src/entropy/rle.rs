@@ -0,0 +1,89 @@
+use anyhow::{Result, bail};
+
+use super::{EntropyCoder, EntropyItems};
+
+/// PackBits run-length coding. A control byte `n` below 128 is followed by `n + 1` literal bytes,
+/// a control byte above 128 is followed by one byte repeated `257 - n` times.
+pub struct PackBits;
+
+const MAX_RUN: usize = 128;
+
+impl EntropyCoder for PackBits {
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut result = vec![];
+        let mut literals: Vec<u8> = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            let mut run = 1;
+            while pos + run < data.len() && run < MAX_RUN && data[pos + run] == data[pos] {
+                run += 1;
+            }
+            if run >= 3 || (run == 2 && literals.is_empty()) {
+                flush_literals(&mut result, &mut literals);
+                result.push((257 - run) as u8);
+                result.push(data[pos]);
+            } else {
+                literals.extend_from_slice(&data[pos..pos + run]);
+                if literals.len() >= MAX_RUN {
+                    flush_literals(&mut result, &mut literals);
+                }
+            }
+            pos += run;
+        }
+        flush_literals(&mut result, &mut literals);
+        result
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let mut result = vec![];
+        let mut pos = 0;
+        while pos < data.len() {
+            let control = data[pos] as usize;
+            pos += 1;
+            if control < 128 {
+                let count = control + 1;
+                if pos + count > data.len() {
+                    bail!("Unexpected end of RLE data");
+                }
+                result.extend_from_slice(&data[pos..pos + count]);
+                pos += count;
+            } else if control > 128 {
+                if pos >= data.len() {
+                    bail!("Unexpected end of RLE data");
+                }
+                result.resize(result.len() + 257 - control, data[pos]);
+                pos += 1;
+            }
+            if result.len() > max_size {
+                bail!("Frame payload is too large");
+            }
+        }
+        Ok(result)
+    }
+
+    fn items(&self, data: &[u8], _size: usize) -> EntropyItems {
+        let mut items = EntropyItems::default();
+        let mut pos = 0;
+        while pos < data.len() {
+            let control = data[pos] as usize;
+            pos += 1;
+            if control < 128 {
+                items.literals += control + 1;
+                pos += control + 1;
+            } else if control > 128 {
+                items.matches += 1;
+                items.match_bytes += 257 - control;
+                pos += 1;
+            }
+        }
+        items
+    }
+}
+
+fn flush_literals(result: &mut Vec<u8>, literals: &mut Vec<u8>) {
+    for chunk in literals.chunks(MAX_RUN) {
+        result.push((chunk.len() - 1) as u8);
+        result.extend_from_slice(chunk);
+    }
+    literals.clear();
+}
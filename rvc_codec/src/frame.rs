@@ -0,0 +1,244 @@
+use anyhow::{Result, bail};
+use rvc_shared::{container::Header, palette::Palette, plane::Plane};
+
+use crate::{
+    bits::{BitReader, BitWriter, index_bits},
+    block::{Block, blocks},
+    codebook::{Codebook, MAX_ENTRIES},
+    entropy::Backend,
+    motion::MotionVector,
+};
+
+pub const OP_BITS: u32 = 2;
+pub const OP_DIRECT: u32 = 0;
+pub const OP_MOTION: u32 = 1;
+pub const OP_FILL: u32 = 2;
+
+/// Stream parameters shared by the frame encoder and decoder.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameParams {
+    pub block_size: u32,
+    pub index_bits: u32,
+    pub colors: usize,
+    pub motion_radius: u32,
+    pub codebook: bool,
+    /// Every field of the payload takes a byte of its own, see `Backend::unpacked`.
+    pub unpacked: bool,
+}
+
+impl FrameParams {
+    pub fn new(header: &Header, palette: &Palette) -> FrameParams {
+        FrameParams {
+            block_size: header.block_size,
+            index_bits: index_bits(palette.len()),
+            colors: palette.len(),
+            motion_radius: header.motion_radius,
+            codebook: header.codebook,
+            unpacked: Backend::from_id(header.entropy).is_ok_and(|backend| backend.unpacked()),
+        }
+    }
+
+    /// Writer of a frame payload in the layout of the stream.
+    pub fn writer(&self) -> BitWriter {
+        if self.unpacked { BitWriter::unpacked() } else { BitWriter::new() }
+    }
+
+    pub fn reader<'a>(&self, payload: &'a [u8]) -> BitReader<'a> {
+        if self.unpacked {
+            BitReader::unpacked(payload)
+        } else {
+            BitReader::new(payload)
+        }
+    }
+
+    /// Upper bound of the size of an uncompressed frame payload in either layout.
+    pub fn max_payload(&self, width: u32, height: u32) -> usize {
+        let codebook = 1 + MAX_ENTRIES * (self.block_size * self.block_size) as usize;
+        2 * (width * height) as usize + codebook + 16
+    }
+
+    fn motion_bits(&self) -> u32 {
+        index_bits(self.motion_radius as usize * 2 + 1)
+    }
+}
+
+/// How a single block of a delta frame is coded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockOp {
+    /// Block is kept from the previous frame.
+    Skip,
+    /// Block indices are stored as is.
+    Raw,
+    /// Block is copied from another position of the previous frame.
+    Motion(MotionVector),
+    /// Block is replaced with a codebook tile.
+    Entry(usize),
+    /// Block is filled with a single index.
+    Fill(i32),
+}
+
+fn read_index(reader: &mut BitReader, params: &FrameParams) -> Result<i32> {
+    let value = reader.read(params.index_bits)?;
+    if value as usize >= params.colors {
+        bail!("Color index {} is out of palette range", value);
+    }
+    Ok(value as i32)
+}
+
+fn read_entry(reader: &mut BitReader, codebook: &Codebook) -> Result<usize> {
+    let entry = reader.read(codebook.entry_bits())? as usize;
+    if entry >= codebook.tiles.len() {
+        bail!("Codebook entry {} is out of range", entry);
+    }
+    Ok(entry)
+}
+
+pub fn encode_intra(writer: &mut BitWriter, frame: &Plane<i32>, params: &FrameParams) {
+    for index in frame.data.iter() {
+        writer.write(*index as u32, params.index_bits);
+    }
+    writer.align();
+}
+
+/// Writes a keyframe in codebook mode: the codebook followed by one entry per block.
+pub fn encode_intra_codebook(writer: &mut BitWriter, codebook: &Codebook, entries: &[usize], params: &FrameParams) {
+    codebook.write(writer, params.index_bits);
+    for entry in entries {
+        writer.write(*entry as u32, codebook.entry_bits());
+    }
+    writer.align();
+}
+
+/// Decodes a keyframe. The operation of every block is appended to `ops`.
+pub fn decode_intra(
+    reader: &mut BitReader,
+    frame: &mut Plane<i32>,
+    params: &FrameParams,
+    codebook: &mut Codebook,
+    ops: &mut Vec<BlockOp>,
+) -> Result<()> {
+    if params.codebook {
+        *codebook = Codebook::read(reader, params.block_size, params.index_bits, params.colors)?;
+        for block in blocks(frame.width, frame.height, params.block_size) {
+            let entry = read_entry(reader, codebook)?;
+            codebook.apply(entry, &block, frame);
+            ops.push(BlockOp::Entry(entry));
+        }
+    } else {
+        for index in frame.data.iter_mut() {
+            *index = read_index(reader, params)?;
+        }
+        ops.extend(blocks(frame.width, frame.height, params.block_size).map(|_| BlockOp::Raw));
+    }
+    reader.align();
+    Ok(())
+}
+
+/// Writes a skip bitmap with one bit per block followed by the contents of every coded block.
+/// Each coded block starts with a 2-bit opcode: direct data (raw indices, or a codebook entry
+/// in codebook mode), a motion vector or a single index that fills the whole block.
+/// With an unpacked writer every bit, opcode, index and vector component takes a byte instead.
+pub fn encode_inter(
+    writer: &mut BitWriter,
+    frame: &Plane<i32>,
+    ops: &[BlockOp],
+    params: &FrameParams,
+    codebook: &Codebook,
+) {
+    for op in ops {
+        writer.write_bit(*op != BlockOp::Skip);
+    }
+
+    let r = params.motion_radius as i32;
+    for (block, op) in blocks(frame.width, frame.height, params.block_size).zip(ops) {
+        match op {
+            BlockOp::Skip => {}
+            BlockOp::Raw => {
+                writer.write(OP_DIRECT, OP_BITS);
+                for (x, y) in block.pixels() {
+                    writer.write(frame.get(x, y) as u32, params.index_bits);
+                }
+            }
+            BlockOp::Motion(vector) => {
+                writer.write(OP_MOTION, OP_BITS);
+                writer.write((vector.dx + r) as u32, params.motion_bits());
+                writer.write((vector.dy + r) as u32, params.motion_bits());
+            }
+            BlockOp::Entry(entry) => {
+                writer.write(OP_DIRECT, OP_BITS);
+                writer.write(*entry as u32, codebook.entry_bits());
+            }
+            BlockOp::Fill(index) => {
+                writer.write(OP_FILL, OP_BITS);
+                writer.write(*index as u32, params.index_bits);
+            }
+        }
+    }
+    writer.align();
+}
+
+/// Applies a delta frame on top of the `previous` frame. `frame` must hold a copy of `previous`.
+/// The operation of every block is appended to `ops`.
+pub fn decode_inter(
+    reader: &mut BitReader,
+    frame: &mut Plane<i32>,
+    previous: &Plane<i32>,
+    params: &FrameParams,
+    codebook: &Codebook,
+    ops: &mut Vec<BlockOp>,
+) -> Result<()> {
+    let mut changed: Vec<(usize, Block)> = vec![];
+    for block in blocks(frame.width, frame.height, params.block_size) {
+        if reader.read_bit()? {
+            changed.push((ops.len(), block));
+        }
+        ops.push(BlockOp::Skip);
+    }
+
+    let r = params.motion_radius as i32;
+    for (op, block) in changed {
+        ops[op] = match reader.read(OP_BITS)? {
+            OP_MOTION => {
+                let vector = MotionVector {
+                    dx: reader.read(params.motion_bits())? as i32 - r,
+                    dy: reader.read(params.motion_bits())? as i32 - r,
+                };
+                if vector.dx.abs() > r || vector.dy.abs() > r {
+                    bail!("Motion vector is out of search range");
+                }
+                let source = match vector.source(&block, frame.width, frame.height) {
+                    Some(source) => source,
+                    None => bail!("Motion vector points outside of the frame"),
+                };
+                for ((x, y), (sx, sy)) in block.pixels().zip(source.pixels()) {
+                    frame.set(x, y, previous.get(sx, sy));
+                }
+                BlockOp::Motion(vector)
+            }
+            OP_FILL => {
+                let index = read_index(reader, params)?;
+                for (x, y) in block.pixels() {
+                    frame.set(x, y, index);
+                }
+                BlockOp::Fill(index)
+            }
+            OP_DIRECT if params.codebook => {
+                if codebook.tiles.is_empty() {
+                    bail!("Delta frame without a codebook");
+                }
+                let entry = read_entry(reader, codebook)?;
+                codebook.apply(entry, &block, frame);
+                BlockOp::Entry(entry)
+            }
+            OP_DIRECT => {
+                for (x, y) in block.pixels() {
+                    frame.set(x, y, read_index(reader, params)?);
+                }
+                BlockOp::Raw
+            }
+            op => bail!("Unknown block opcode {}", op),
+        };
+    }
+    reader.align();
+    Ok(())
+}
@@ -0,0 +1,15 @@
+pub mod audio;
+pub mod bits;
+pub mod block;
+pub mod codebook;
+pub mod cost;
+pub mod decoder;
+pub mod encoder;
+pub mod entropy;
+pub mod frame;
+pub mod motion;
+pub mod rate;
+pub mod scene;
+
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,137 @@
+use rvc_shared::plane::Plane;
+
+use crate::block::Block;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionVector {
+    pub dx: i32,
+    pub dy: i32,
+}
+
+impl MotionVector {
+    /// Area of the previous frame that the block is copied from, if it lies inside the frame.
+    pub fn source(&self, block: &Block, width: u32, height: u32) -> Option<Block> {
+        let x = block.x as i32 + self.dx;
+        let y = block.y as i32 + self.dy;
+        if x < 0 || y < 0 || x as u32 + block.width > width || y as u32 + block.height > height {
+            return None;
+        }
+        Some(Block {
+            x: x as u32,
+            y: y as u32,
+            width: block.width,
+            height: block.height,
+        })
+    }
+}
+
+/// Candidate vectors within `radius`, cheapest first. Vectors are stored with a fixed number of
+/// bits, but short ones repeat more often and compress better.
+pub fn candidates(radius: u32) -> Vec<MotionVector> {
+    let r = radius as i32;
+    let mut result = vec![];
+    for dy in -r..=r {
+        for dx in -r..=r {
+            if dx != 0 || dy != 0 {
+                result.push(MotionVector { dx, dy });
+            }
+        }
+    }
+    result.sort_by_key(|v| v.dx.abs() + v.dy.abs());
+    result
+}
+
+/// Number of pixels in which `source` of the reference differs from `block` of the frame, or
+/// `None` once it exceeds `limit`.
+fn pixel_errors(frame: &Plane<i32>, reference: &Plane<i32>, block: &Block, source: &Block, limit: u32) -> Option<u32> {
+    let mut errors = 0;
+    for ((x, y), (sx, sy)) in block.pixels().zip(source.pixels()) {
+        if frame.get(x, y) != reference.get(sx, sy) {
+            errors += 1;
+            if errors > limit {
+                return None;
+            }
+        }
+    }
+    Some(errors)
+}
+
+/// Finds the block of the previous frame that differs least from the block of the current frame,
+/// as long as it differs in at most `max_error` pixels. Ties go to the earliest of `candidates`.
+pub fn search(
+    frame: &Plane<i32>,
+    reference: &Plane<i32>,
+    block: &Block,
+    candidates: &[MotionVector],
+    max_error: u32,
+) -> Option<MotionVector> {
+    let mut best: Option<(MotionVector, u32)> = None;
+    for vector in candidates {
+        let source = match vector.source(block, frame.width, frame.height) {
+            Some(source) => source,
+            None => continue,
+        };
+        // Later candidates have to be strictly better to win
+        let limit = match best {
+            Some((_, errors)) => errors - 1,
+            None => max_error,
+        };
+        if let Some(errors) = pixel_errors(frame, reference, block, &source, limit) {
+            best = Some((*vector, errors));
+            if errors == 0 {
+                break;
+            }
+        }
+    }
+    best.map(|(vector, _)| vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: Block = Block {
+        x: 8,
+        y: 8,
+        width: 4,
+        height: 4,
+    };
+
+    fn frame() -> Plane<i32> {
+        let mut frame = Plane::new(24, 24, 0);
+        for (x, y) in BLOCK.pixels() {
+            frame.set(x, y, (x + y * 4) as i32);
+        }
+        frame
+    }
+
+    /// Reference with copies of the block at the given vectors and `errors` wrong pixels in each.
+    fn reference(frame: &Plane<i32>, copies: &[(i32, i32, u32)]) -> Plane<i32> {
+        let mut reference = Plane::new(24, 24, 0);
+        for (dx, dy, errors) in copies {
+            let source = MotionVector { dx: *dx, dy: *dy }.source(&BLOCK, 24, 24).unwrap();
+            for (i, ((x, y), (sx, sy))) in BLOCK.pixels().zip(source.pixels()).enumerate() {
+                let wrong = (i as u32) < *errors;
+                reference.set(sx, sy, if wrong { -1 } else { frame.get(x, y) });
+            }
+        }
+        reference
+    }
+
+    #[test]
+    fn search_prefers_fewest_errors() {
+        let frame = frame();
+        let candidates = candidates(4);
+        let search = |copies: &[(i32, i32, u32)], max_error| {
+            search(&frame, &reference(&frame, copies), &BLOCK, &candidates, max_error)
+        };
+        // The nearest acceptable copy is not the best one
+        assert_eq!(search(&[(1, 0, 2), (-3, 3, 0)], 4), Some(MotionVector { dx: -3, dy: 3 }));
+        assert_eq!(search(&[(1, 0, 3), (-2, 0, 1), (4, 4, 2)], 4), Some(MotionVector { dx: -2, dy: 0 }));
+        // Equally good copies go to the shorter vector
+        assert_eq!(search(&[(4, 4, 0), (0, -4, 0), (1, 0, 1)], 4), Some(MotionVector { dx: 0, dy: -4 }));
+        // Only copies within the error limit count
+        assert_eq!(search(&[(1, 0, 3)], 2), None);
+        assert_eq!(search(&[(1, 0, 3)], 3), Some(MotionVector { dx: 1, dy: 0 }));
+    }
+}
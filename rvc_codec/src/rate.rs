@@ -0,0 +1,197 @@
+use anyhow::Result;
+use bincode::{Decode, Encode};
+use std::{fs, ops::Range, path::Path};
+
+/// Target stream size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    Unlimited,
+    /// Every frame must fit the given number of bytes.
+    BytesPerFrame(u32),
+    /// Average number of bytes per second of video. Frames may use the bytes that earlier frames
+    /// left unused, up to a second's worth, so any run of frames fits its share of the rate plus
+    /// one second. A second right after a still stretch may thus take up to twice the rate.
+    BytesPerSecond(u32),
+}
+
+/// Degradation steps of a delta frame, from lossless to skipping every block.
+pub const LEVELS: u32 = 8;
+
+/// Encoder tolerances for a degradation level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quality {
+    /// Changed blocks with at most this many different pixels are skipped.
+    pub stale_pixels: u32,
+    /// Motion copies may differ from the source block in this many pixels.
+    pub motion_error: u32,
+    /// Blocks that would be stored raw are filled with their dominant index instead.
+    pub fill: bool,
+}
+
+impl Quality {
+    pub fn level(level: u32, block_size: u32) -> Quality {
+        let pixels = block_size * block_size;
+        Quality {
+            stale_pixels: pixels * level / LEVELS,
+            motion_error: pixels * level / (LEVELS * 2),
+            fill: level >= LEVELS / 2,
+        }
+    }
+}
+
+/// First-pass measurements of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Decode, Encode)]
+pub struct PassFrame {
+    pub keyframe: bool,
+    pub scene_cut: bool,
+    /// Bytes the frame takes without a budget, with its audio and palette chunks.
+    pub complexity: u32,
+    /// Blocks that differ from the previous frame, all of them in a keyframe.
+    pub changed_blocks: u32,
+}
+
+/// Statistics file written by the first pass of a two-pass encode.
+#[derive(Clone, Debug, Default, PartialEq, Decode, Encode)]
+pub struct PassStats {
+    pub frames: Vec<PassFrame>,
+}
+
+impl PassStats {
+    pub fn save(&self, filename: &Path) -> Result<()> {
+        let mut file = fs::File::create(filename)?;
+        let config = bincode::config::standard();
+        bincode::encode_into_std_write(self, &mut file, config)?;
+        Ok(())
+    }
+
+    pub fn from_file(filename: &Path) -> Result<PassStats> {
+        let mut file = fs::File::open(filename)?;
+        let config = bincode::config::standard();
+        Ok(bincode::decode_from_std_read(&mut file, config)?)
+    }
+
+    /// Frame ranges between scene cuts.
+    pub fn scenes(&self) -> Vec<Range<u32>> {
+        let mut scenes = vec![];
+        let mut start = 0;
+        for (i, frame) in self.frames.iter().enumerate().skip(1) {
+            if frame.scene_cut {
+                scenes.push(start..i as u32);
+                start = i as u32;
+            }
+        }
+        if !self.frames.is_empty() {
+            scenes.push(start..self.frames.len() as u32);
+        }
+        scenes
+    }
+}
+
+/// Leaky bucket that tracks how many bytes the next frame may use.
+pub struct RateControl {
+    per_frame: f64,
+    capacity: f64,
+    available: f64,
+    /// Bytes of each frame when the first pass spreads the budget, in proportion to complexity.
+    plan: Vec<f64>,
+    frame: usize,
+}
+
+impl RateControl {
+    pub fn new(budget: Budget, fps: f64) -> RateControl {
+        let (per_frame, capacity) = match budget {
+            Budget::Unlimited => (f64::INFINITY, f64::INFINITY),
+            Budget::BytesPerFrame(bytes) => (bytes as f64, bytes as f64),
+            Budget::BytesPerSecond(bytes) => (bytes as f64 / fps, bytes as f64),
+        };
+        RateControl {
+            per_frame,
+            capacity,
+            available: 0.0,
+            plan: vec![],
+            frame: 0,
+        }
+    }
+
+    /// Spreads a per-second budget over the clip by the first-pass complexity of the frames.
+    /// The bucket still holds at most a second of bytes, other budgets ignore the statistics.
+    pub fn with_stats(budget: Budget, fps: f64, stats: &PassStats) -> RateControl {
+        let mut rate = RateControl::new(budget, fps);
+        if let Budget::BytesPerSecond(_) = budget {
+            let total = rate.per_frame * stats.frames.len() as f64;
+            let complexity: f64 = stats.frames.iter().map(|frame| frame.complexity as f64).sum();
+            if complexity > 0.0 {
+                rate.plan = stats
+                    .frames
+                    .iter()
+                    .map(|frame| total * frame.complexity as f64 / complexity)
+                    .collect();
+            }
+        }
+        rate
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.per_frame.is_finite()
+    }
+
+    /// Starts a new frame and returns the number of bytes it may use.
+    pub fn allowance(&mut self) -> f64 {
+        if !self.is_limited() {
+            return f64::INFINITY;
+        }
+        let per_frame = self.plan.get(self.frame).copied().unwrap_or(self.per_frame);
+        self.frame += 1;
+        self.available = (self.available + per_frame).min(self.capacity);
+        self.available
+    }
+
+    pub fn spend(&mut self, bytes: usize) {
+        if self.is_limited() {
+            self.available -= bytes as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(frames: &[(bool, u32)]) -> PassStats {
+        PassStats {
+            frames: frames
+                .iter()
+                .map(|(scene_cut, complexity)| PassFrame {
+                    keyframe: *scene_cut,
+                    scene_cut: *scene_cut,
+                    complexity: *complexity,
+                    changed_blocks: 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn scenes_split_at_cuts() {
+        let stats = stats(&[(true, 1), (false, 1), (true, 1), (false, 1), (false, 1), (true, 1)]);
+        assert_eq!(stats.scenes(), [0..2, 2..5, 5..6]);
+        assert!(PassStats::default().scenes().is_empty());
+    }
+
+    #[test]
+    fn budget_follows_complexity() {
+        let stats = stats(&[(true, 300), (false, 100), (false, 100), (false, 300)]);
+        let mut rate = RateControl::with_stats(Budget::BytesPerSecond(400), 4.0, &stats);
+        let mut allowances = vec![];
+        for _ in 0..5 {
+            allowances.push(rate.allowance());
+            rate.spend(allowances[allowances.len() - 1] as usize);
+        }
+        // Frames past the statistics get an even share
+        assert_eq!(allowances, [150.0, 50.0, 50.0, 150.0, 100.0]);
+
+        // Statistics don't change other budgets
+        let mut rate = RateControl::with_stats(Budget::BytesPerFrame(70), 4.0, &stats);
+        assert_eq!(rate.allowance(), 70.0);
+    }
+}
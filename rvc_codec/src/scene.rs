@@ -0,0 +1,122 @@
+use rvc_shared::plane::Plane;
+
+/// How two consecutive source frames are compared to detect a scene cut.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneMetric {
+    /// Half the sum of absolute differences of the index histograms, relative to the frame size.
+    /// Ignores motion and dither noise, reacts to changes of the overall colour set.
+    Histogram,
+    /// Share of pixels whose index changed.
+    ChangedIndices,
+}
+
+pub fn histogram_difference(a: &Plane<i32>, b: &Plane<i32>) -> f64 {
+    let mut histogram = vec![0i64; 256];
+    for index in a.data.iter() {
+        histogram[*index as usize] += 1;
+    }
+    for index in b.data.iter() {
+        histogram[*index as usize] -= 1;
+    }
+    let total: i64 = histogram.iter().map(|h| h.abs()).sum();
+    total as f64 / (2 * a.data.len()) as f64
+}
+
+pub fn changed_share(a: &Plane<i32>, b: &Plane<i32>) -> f64 {
+    let changed = a.data.iter().zip(b.data.iter()).filter(|(x, y)| x != y).count();
+    changed as f64 / a.data.len() as f64
+}
+
+impl SceneMetric {
+    pub fn difference(&self, a: &Plane<i32>, b: &Plane<i32>) -> f64 {
+        match self {
+            SceneMetric::Histogram => histogram_difference(a, b),
+            SceneMetric::ChangedIndices => changed_share(a, b),
+        }
+    }
+}
+
+/// Decides which source frames become keyframes.
+pub struct KeyframePlacer {
+    /// Maximum distance between keyframes, 0 means no limit.
+    pub interval: u32,
+    /// Difference from the previous frame that starts a new scene, 0 disables scene-cut detection.
+    pub threshold: f64,
+    pub metric: SceneMetric,
+    since_key: u32,
+    previous: Option<Plane<i32>>,
+}
+
+impl KeyframePlacer {
+    pub fn new(interval: u32, threshold: f64, metric: SceneMetric) -> KeyframePlacer {
+        KeyframePlacer {
+            interval,
+            threshold,
+            metric,
+            since_key: 0,
+            previous: None,
+        }
+    }
+
+    pub fn is_scene_cut(&self, frame: &Plane<i32>) -> bool {
+        match &self.previous {
+            Some(previous) => self.threshold > 0.0 && self.metric.difference(previous, frame) >= self.threshold,
+            None => false,
+        }
+    }
+
+    /// Feeds the next source frame and tells whether it has to be coded as a keyframe.
+    /// `force` makes it a keyframe regardless of the content.
+    pub fn next(&mut self, frame: &Plane<i32>, force: bool) -> bool {
+        let keyframe = force
+            || self.previous.is_none()
+            || (self.interval > 0 && self.since_key + 1 >= self.interval)
+            || self.is_scene_cut(frame);
+        self.since_key = if keyframe { 0 } else { self.since_key + 1 };
+        match &mut self.previous {
+            Some(previous) => previous.data.copy_from_slice(&frame.data),
+            None => self.previous = Some(frame.clone()),
+        }
+        keyframe
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn keyframes(placer: &mut KeyframePlacer, frames: &[Plane<i32>]) -> Vec<usize> {
+        (0..frames.len()).filter(|i| placer.next(&frames[*i], false)).collect()
+    }
+
+    #[test]
+    fn keyframes_at_cuts() {
+        let frames = testing::clip(60, 20);
+        let mut placer = KeyframePlacer::new(0, 0.3, SceneMetric::ChangedIndices);
+        assert_eq!(keyframes(&mut placer, &frames), [0, 20, 40]);
+        // Without a threshold only the first frame is a keyframe
+        let mut placer = KeyframePlacer::new(0, 0.0, SceneMetric::ChangedIndices);
+        assert_eq!(keyframes(&mut placer, &frames), [0]);
+    }
+
+    #[test]
+    fn histogram_ignores_motion() {
+        // The background shift at frame 20 keeps the colour set, the darker frames from 45 on don't
+        let mut frames = testing::clip(60, 20);
+        for frame in frames[45..].iter_mut() {
+            frame.data.iter_mut().for_each(|index| *index /= 4);
+        }
+        let mut placer = KeyframePlacer::new(0, 0.3, SceneMetric::Histogram);
+        assert_eq!(keyframes(&mut placer, &frames), [0, 45]);
+    }
+
+    #[test]
+    fn interval_restarts_at_cuts() {
+        let frames = testing::clip(60, 20);
+        let mut placer = KeyframePlacer::new(8, 0.3, SceneMetric::ChangedIndices);
+        assert_eq!(keyframes(&mut placer, &frames), [0, 8, 16, 20, 28, 36, 40, 48, 56]);
+        let mut placer = KeyframePlacer::new(1, 0.0, SceneMetric::ChangedIndices);
+        assert_eq!(keyframes(&mut placer, &frames).len(), 60);
+    }
+}
@@ -0,0 +1,62 @@
+//! Synthetic clips for the tests.
+
+use rvc_shared::{colors::FloatColor, container::Header, palette::Palette, plane::Plane};
+use std::io::Cursor;
+
+use crate::encoder::{Encoder, EncoderConfig};
+
+/// Frame size that leaves partial blocks on the right and bottom edges.
+pub const WIDTH: u32 = 44;
+pub const HEIGHT: u32 = 30;
+
+pub fn palette() -> Palette {
+    let mut palette = Palette::new();
+    for i in 0..16 {
+        palette.add(FloatColor::new(i * 17, 255 - i * 13, (i * 53) % 256));
+    }
+    palette
+}
+
+pub fn header() -> Header {
+    Header::new(WIDTH, HEIGHT, 25, 1)
+}
+
+/// Textured square moving over a gradient with some flickering pixels. The background changes
+/// every `scene` frames.
+pub fn clip(frames: u32, scene: u32) -> Vec<Plane<i32>> {
+    (0..frames)
+        .map(|number| {
+            let (cut, time) = (number / scene, number % scene);
+            let (left, top) = (time * 2 % (WIDTH - 10), time % (HEIGHT - 8));
+            let mut frame = Plane::new(WIDTH, HEIGHT, 0);
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let index = if (left..left + 10).contains(&x) && (top..top + 8).contains(&y) {
+                        12 + ((x - left) / 3 + (y - top) / 2) % 4
+                    } else if (x * 7 + y * 13 + number * 5) % 29 == 0 {
+                        (x + number) % 16
+                    } else {
+                        ((x + y * 3) / 5 + cut * 5) % 12
+                    };
+                    frame.set(x, y, index as i32);
+                }
+            }
+            frame
+        })
+        .collect()
+}
+
+/// Encodes `frames` into a stream, flushing the encoder before frame `flush_at`.
+pub fn encode(config: EncoderConfig, frames: &[Plane<i32>], flush_at: usize) -> Vec<u8> {
+    let mut encoder = Encoder::new(Cursor::new(vec![]), header(), &palette(), config).unwrap();
+    let mut completed = 0;
+    for (number, frame) in frames.iter().enumerate() {
+        if number == flush_at {
+            completed += encoder.flush().unwrap().len();
+        }
+        completed += encoder.encode(frame).unwrap().len();
+    }
+    completed += encoder.flush().unwrap().len();
+    assert_eq!(completed, frames.len());
+    encoder.finish().unwrap().into_inner()
+}
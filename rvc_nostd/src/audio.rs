@@ -0,0 +1,88 @@
+use crate::{Error, Result};
+
+pub const PCM16: u8 = 0;
+pub const PCM8: u8 = 1;
+pub const ADPCM: u8 = 2;
+
+const INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107,
+    118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963,
+    1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894,
+    6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794,
+    32767,
+];
+
+const ADPCM_CHANNEL_HEADER_SIZE: usize = 4;
+const MAX_CHANNELS: usize = 255;
+
+/// Size in bytes of an audio chunk holding `samples` interleaved samples.
+pub fn chunk_size(codec: u8, channels: u32, samples: usize) -> Result<usize> {
+    Ok(match codec {
+        PCM16 => samples * 2,
+        PCM8 => samples,
+        ADPCM => channels as usize * ADPCM_CHANNEL_HEADER_SIZE + samples.div_ceil(2),
+        _ => return Err(Error::UnknownAudioCodec),
+    })
+}
+
+/// Decodes an audio chunk into interleaved 16-bit samples. `samples` must have the exact size
+/// of the chunk's contents, see `Header::audio_samples`.
+pub fn decode(codec: u8, channels: u32, data: &[u8], samples: &mut [i16]) -> Result<()> {
+    if channels == 0 || channels as usize > MAX_CHANNELS {
+        return Err(Error::InvalidHeader);
+    }
+    if data.len() != chunk_size(codec, channels, samples.len())? {
+        return Err(Error::InvalidData);
+    }
+    match codec {
+        PCM16 => {
+            for (sample, bytes) in samples.iter_mut().zip(data.chunks_exact(2)) {
+                *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+        }
+        PCM8 => {
+            for (sample, byte) in samples.iter_mut().zip(data) {
+                *sample = ((*byte ^ 0x80) as i8 as i16) << 8;
+            }
+        }
+        _ => decode_adpcm(channels as usize, data, samples)?,
+    }
+    Ok(())
+}
+
+fn decode_adpcm(channels: usize, data: &[u8], samples: &mut [i16]) -> Result<()> {
+    let mut predictors = [0i32; MAX_CHANNELS];
+    let mut indices = [0i32; MAX_CHANNELS];
+    let (header, nibbles) = data.split_at(channels * ADPCM_CHANNEL_HEADER_SIZE);
+    for (channel, state) in header.chunks_exact(ADPCM_CHANNEL_HEADER_SIZE).enumerate() {
+        predictors[channel] = i16::from_le_bytes([state[0], state[1]]) as i32;
+        indices[channel] = state[2] as i32;
+        if indices[channel] >= STEP_TABLE.len() as i32 {
+            return Err(Error::InvalidData);
+        }
+    }
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let nibble = (nibbles[i / 2] >> (i % 2 * 4)) & 0xf;
+        let channel = i % channels;
+        let step = STEP_TABLE[indices[channel] as usize];
+        let mut diff = step >> 3;
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        if nibble & 8 != 0 {
+            diff = -diff;
+        }
+        predictors[channel] = (predictors[channel] + diff).clamp(i16::MIN as i32, i16::MAX as i32);
+        indices[channel] = (indices[channel] + INDEX_TABLE[nibble as usize]).clamp(0, STEP_TABLE.len() as i32 - 1);
+        *sample = predictors[channel] as i16;
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use crate::{Error, Result};
+
+/// Reads values of arbitrary bit width, most significant bit first.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+    unpacked: bool,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            pos: 0,
+            bit: 0,
+            unpacked: false,
+        }
+    }
+
+    /// Reads payloads that store every value of up to 8 bits in a byte of its own.
+    pub fn unpacked(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            unpacked: true,
+            ..BitReader::new(data)
+        }
+    }
+
+    pub fn read(&mut self, bits: u32) -> Result<u32> {
+        if self.unpacked && bits > 0 {
+            let value = *self.data.get(self.pos).ok_or(Error::UnexpectedEnd)? as u32;
+            if bits < 8 && value >> bits != 0 {
+                return Err(Error::InvalidData);
+            }
+            self.pos += 1;
+            return Ok(value);
+        }
+        let mut value = 0;
+        for _ in 0..bits {
+            if self.pos >= self.data.len() {
+                return Err(Error::UnexpectedEnd);
+            }
+            value = (value << 1) | ((self.data[self.pos] >> (7 - self.bit)) & 1) as u32;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool> {
+        Ok(self.read(1)? == 1)
+    }
+
+    /// Moves past `count` single-bit values.
+    pub fn skip(&mut self, count: u32) -> Result<()> {
+        if self.unpacked {
+            self.pos += count as usize;
+            return if self.pos <= self.data.len() {
+                Ok(())
+            } else {
+                Err(Error::UnexpectedEnd)
+            };
+        }
+        for _ in 0..count / 32 {
+            self.read(32)?;
+        }
+        self.read(count % 32)?;
+        Ok(())
+    }
+}
+
+/// Number of bits needed to store any index of a palette with `colors` entries.
+pub fn index_bits(colors: usize) -> u32 {
+    let mut bits = 1;
+    while (1usize << bits) < colors {
+        bits += 1;
+    }
+    bits
+}
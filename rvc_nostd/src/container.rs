@@ -0,0 +1,216 @@
+use crate::{Error, Result, bits::index_bits, entropy};
+
+pub const MAGIC: [u8; 4] = *b"RVC\x1a";
+pub const VERSION: u16 = 1;
+
+pub const TAG_HEADER: [u8; 4] = *b"HEAD";
+pub const TAG_PALETTE: [u8; 4] = *b"PALT";
+pub const TAG_FRAME: [u8; 4] = *b"FRAM";
+pub const TAG_INDEX: [u8; 4] = *b"INDX";
+pub const TAG_AUDIO: [u8; 4] = *b"AUDI";
+pub const TAG_PALETTE_DELTA: [u8; 4] = *b"PDLT";
+pub const TAG_CYCLES: [u8; 4] = *b"CRNG";
+
+pub const CYCLE_REVERSE: u8 = 1;
+/// Rate of a cycling range that makes 60 steps per second.
+pub const CYCLE_RATE_60: u64 = 16384;
+
+pub const FRAME_KEY: u8 = 1;
+
+pub const MAX_COLORS: usize = 256;
+pub const MAX_CODEBOOK_ENTRIES: usize = 256;
+/// Cycling ranges don't overlap and span at least two colors.
+pub const MAX_CYCLES: usize = MAX_COLORS / 2;
+
+const HEADER_SIZE: usize = 28;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub codec: u8,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub frame_count: u32,
+    pub index_offset: u32,
+    pub block_size: u32,
+    pub motion_radius: u32,
+    pub codebook: bool,
+    pub entropy: u8,
+    pub audio: Option<AudioFormat>,
+}
+
+/// Range of palette entries whose colors rotate over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CycleRange {
+    pub start: u8,
+    /// Last entry of the range, inclusive.
+    pub end: u8,
+    /// Speed in units of `CYCLE_RATE_60`, 0 keeps the range still.
+    pub rate: u16,
+    /// Colors move towards lower indices instead of higher ones.
+    pub reverse: bool,
+}
+
+impl CycleRange {
+    pub fn len(&self) -> usize {
+        self.end as usize + 1 - self.start as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// Number of steps the range has rotated by after `time_num / time_den` seconds.
+    pub fn steps(&self, time_num: u64, time_den: u64) -> usize {
+        let steps = time_num as u128 * self.rate as u128 * 60 / (time_den as u128 * CYCLE_RATE_60 as u128);
+        let steps = (steps % self.len() as u128) as usize;
+        if self.reverse {
+            (self.len() - steps) % self.len()
+        } else {
+            steps
+        }
+    }
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+pub(crate) fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+impl Header {
+    /// Reads the header at the start of a stream.
+    pub fn parse(data: &[u8]) -> Result<Header> {
+        if data.len() < 8 || data[..4] != MAGIC {
+            return Err(Error::NotRvc);
+        }
+        if u16_at(data, 4) != VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+        let mut pos = 8;
+        let chunk = match next_chunk(data, &mut pos)? {
+            Some((TAG_HEADER, chunk)) if chunk.len() >= HEADER_SIZE => chunk,
+            _ => return Err(Error::InvalidHeader),
+        };
+        let header = Header {
+            width: u16_at(chunk, 0) as u32,
+            height: u16_at(chunk, 2) as u32,
+            fps_num: u16_at(chunk, 4) as u32,
+            fps_den: u16_at(chunk, 6) as u32,
+            frame_count: u32_at(chunk, 8),
+            index_offset: u32_at(chunk, 12),
+            block_size: chunk[16] as u32,
+            motion_radius: chunk[17] as u32,
+            codebook: chunk[18] != 0,
+            entropy: chunk[19],
+            audio: match u32_at(chunk, 20) {
+                0 => None,
+                sample_rate => Some(AudioFormat {
+                    sample_rate,
+                    channels: chunk[24] as u32,
+                    codec: chunk[25],
+                }),
+            },
+        };
+        if header.width == 0
+            || header.height == 0
+            || header.fps_num == 0
+            || header.fps_den == 0
+            || !(2..=16).contains(&header.block_size)
+            || header.motion_radius > 127
+            || header.motion_radius > header.width.max(header.height)
+            || header.audio.is_some_and(|audio| audio.channels == 0)
+        {
+            return Err(Error::InvalidHeader);
+        }
+        Ok(header)
+    }
+
+    /// Number of samples per channel that accompany `frame`.
+    pub fn audio_samples(&self, frame: u32) -> usize {
+        let rate = match &self.audio {
+            Some(audio) => audio.sample_rate as u64,
+            None => return 0,
+        };
+        let start = |frame: u64| frame * rate * self.fps_den as u64 / self.fps_num as u64;
+        (start(frame as u64 + 1) - start(frame as u64)) as usize
+    }
+
+    /// Size of a buffer that holds the interleaved samples of any frame.
+    pub fn max_audio_samples(&self) -> usize {
+        match &self.audio {
+            Some(audio) => {
+                (audio.sample_rate as u64 * self.fps_den as u64).div_ceil(self.fps_num as u64) as usize
+                    * audio.channels as usize
+            }
+            None => 0,
+        }
+    }
+
+    /// Size of the indexed framebuffer in bytes.
+    pub fn frame_size(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    /// Upper bound of a decompressed frame payload with any palette.
+    pub fn payload_size(&self) -> usize {
+        let bs = self.block_size as usize;
+        let blocks = (self.width as usize).div_ceil(bs) * (self.height as usize).div_ceil(bs);
+        let intra = if self.codebook {
+            1 + MAX_CODEBOOK_ENTRIES * bs * bs + blocks
+        } else {
+            self.frame_size()
+        };
+        let inter = if entropy::unpacked(self.entropy) {
+            // A byte for the skip flag, the opcode and every index or vector component
+            let direct = if self.codebook { 1 } else { bs * bs };
+            blocks + blocks * (1 + direct.max(2))
+        } else {
+            let motion = 2 * index_bits(self.motion_radius as usize * 2 + 1) as usize;
+            let direct = if self.codebook { 8 } else { bs * bs * 8 };
+            blocks.div_ceil(8) + (blocks * (2 + direct.max(motion))).div_ceil(8)
+        };
+        intra.max(inter)
+    }
+
+    /// Size of the scratch buffer the decoder needs: a copy of the previous frame, the current
+    /// palette, the codebook in codebook mode and the decompressed payload when an entropy
+    /// coder is used.
+    pub fn scratch_size(&self) -> usize {
+        let bs = self.block_size as usize;
+        let codebook = if self.codebook {
+            MAX_CODEBOOK_ENTRIES * bs * bs
+        } else {
+            0
+        };
+        let payload = if self.entropy != 0 { self.payload_size() } else { 0 };
+        self.frame_size() + MAX_COLORS * 3 + codebook + payload
+    }
+}
+
+/// Reads the chunk at `pos` and moves past it. Returns `None` at the end of the data.
+pub fn next_chunk<'a>(data: &'a [u8], pos: &mut usize) -> Result<Option<([u8; 4], &'a [u8])>> {
+    if *pos >= data.len() {
+        return Ok(None);
+    }
+    if *pos + 8 > data.len() {
+        return Err(Error::UnexpectedEnd);
+    }
+    let tag = [data[*pos], data[*pos + 1], data[*pos + 2], data[*pos + 3]];
+    let size = u32_at(data, *pos + 4) as usize;
+    let start = *pos + 8;
+    if size > data.len() - start {
+        return Err(Error::UnexpectedEnd);
+    }
+    *pos = start + size;
+    Ok(Some((tag, &data[start..start + size])))
+}
@@ -0,0 +1,144 @@
+use crate::{Error, Result, bits::BitReader};
+
+pub const STORE: u8 = 0;
+pub const RLE: u8 = 1;
+pub const LZ77: u8 = 2;
+pub const HUFFMAN: u8 = 3;
+
+/// Whether frame payloads of `backend` store every value in a byte of its own instead of packing
+/// the bits, so that RLE and Huffman coding see whole symbols.
+pub fn unpacked(backend: u8) -> bool {
+    backend == RLE || backend == HUFFMAN
+}
+
+const LZ77_MIN_MATCH: usize = 3;
+const HUFFMAN_MAX_LENGTH: usize = 15;
+
+/// Restores a frame payload into `out` and returns its size.
+pub fn decompress(backend: u8, data: &[u8], out: &mut [u8]) -> Result<usize> {
+    match backend {
+        STORE => {
+            out.get_mut(..data.len())
+                .ok_or(Error::BufferTooSmall)?
+                .copy_from_slice(data);
+            Ok(data.len())
+        }
+        RLE => unpack_bits(data, out),
+        LZ77 => unpack_lz77(data, out),
+        HUFFMAN => unpack_huffman(data, out),
+        _ => Err(Error::UnknownEntropy),
+    }
+}
+
+fn unpack_bits(data: &[u8], out: &mut [u8]) -> Result<usize> {
+    let mut size = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let control = data[pos] as usize;
+        pos += 1;
+        if control < 128 {
+            let count = control + 1;
+            let literals = data.get(pos..pos + count).ok_or(Error::UnexpectedEnd)?;
+            out.get_mut(size..size + count)
+                .ok_or(Error::BufferTooSmall)?
+                .copy_from_slice(literals);
+            size += count;
+            pos += count;
+        } else if control > 128 {
+            let value = *data.get(pos).ok_or(Error::UnexpectedEnd)?;
+            let count = 257 - control;
+            out.get_mut(size..size + count)
+                .ok_or(Error::BufferTooSmall)?
+                .fill(value);
+            size += count;
+            pos += 1;
+        }
+    }
+    Ok(size)
+}
+
+fn unpack_lz77(data: &[u8], out: &mut [u8]) -> Result<usize> {
+    let mut size = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let flags = data[pos];
+        pos += 1;
+        for item in 0..8 {
+            if pos >= data.len() {
+                break;
+            }
+            if flags & (1 << item) == 0 {
+                *out.get_mut(size).ok_or(Error::BufferTooSmall)? = data[pos];
+                size += 1;
+                pos += 1;
+            } else {
+                if pos + 2 > data.len() {
+                    return Err(Error::UnexpectedEnd);
+                }
+                let token = (data[pos] as usize) << 8 | data[pos + 1] as usize;
+                pos += 2;
+                let distance = (token >> 4) + 1;
+                let length = (token & 15) + LZ77_MIN_MATCH;
+                if distance > size {
+                    return Err(Error::InvalidData);
+                }
+                if size + length > out.len() {
+                    return Err(Error::BufferTooSmall);
+                }
+                for i in size..size + length {
+                    out[i] = out[i - distance];
+                }
+                size += length;
+            }
+        }
+    }
+    Ok(size)
+}
+
+fn unpack_huffman(data: &[u8], out: &mut [u8]) -> Result<usize> {
+    let mut reader = BitReader::new(data);
+    let size = reader.read(32)? as usize;
+    if size > out.len() {
+        return Err(Error::BufferTooSmall);
+    }
+    let mut lengths = [0u8; 256];
+    for length in lengths.iter_mut() {
+        *length = reader.read(4)? as u8;
+    }
+
+    // Symbols sorted by code, and the number of codes of every length
+    let mut counts = [0u32; HUFFMAN_MAX_LENGTH + 1];
+    let mut symbols = [0u8; 256];
+    let mut count = 0;
+    for length in 1..=HUFFMAN_MAX_LENGTH as u8 {
+        for (symbol, symbol_length) in lengths.iter().enumerate() {
+            if *symbol_length == length {
+                symbols[count] = symbol as u8;
+                count += 1;
+                counts[length as usize] += 1;
+            }
+        }
+    }
+
+    for byte in out[..size].iter_mut() {
+        let mut code = 0;
+        let mut first = 0;
+        let mut offset = 0;
+        let mut found = false;
+        for count in counts.iter().skip(1) {
+            code |= reader.read(1)?;
+            if code < first + count {
+                *byte = symbols[(offset + code - first) as usize];
+                found = true;
+                break;
+            }
+            offset += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        if !found {
+            return Err(Error::InvalidData);
+        }
+    }
+    Ok(size)
+}
@@ -0,0 +1,179 @@
+use crate::{
+    Error, Result,
+    bits::{BitReader, index_bits},
+};
+
+const OP_BITS: u32 = 2;
+const OP_DIRECT: u32 = 0;
+const OP_MOTION: u32 = 1;
+const OP_FILL: u32 = 2;
+
+/// Stream parameters needed to decode frame payloads.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameParams {
+    pub width: u32,
+    pub height: u32,
+    pub block_size: u32,
+    pub index_bits: u32,
+    pub colors: usize,
+    pub motion_radius: u32,
+    pub codebook: bool,
+    /// Every value of the payload takes a byte of its own, see `entropy::unpacked`.
+    pub unpacked: bool,
+}
+
+impl FrameParams {
+    fn reader<'a>(&self, payload: &'a [u8]) -> BitReader<'a> {
+        if self.unpacked {
+            BitReader::unpacked(payload)
+        } else {
+            BitReader::new(payload)
+        }
+    }
+}
+
+/// Codebook tiles stored one after another in a caller-supplied buffer.
+pub struct Codebook<'a> {
+    pub tiles: &'a mut [u8],
+    pub entries: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Block {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+fn blocks(params: &FrameParams) -> impl Iterator<Item = Block> {
+    let (width, height, size) = (params.width, params.height, params.block_size);
+    (0..height.div_ceil(size)).flat_map(move |by| {
+        (0..width.div_ceil(size)).map(move |bx| {
+            let x = bx * size;
+            let y = by * size;
+            Block {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            }
+        })
+    })
+}
+
+fn read_index(reader: &mut BitReader, params: &FrameParams) -> Result<u8> {
+    let value = reader.read(params.index_bits)?;
+    if value as usize >= params.colors {
+        return Err(Error::InvalidData);
+    }
+    Ok(value as u8)
+}
+
+fn read_entry(reader: &mut BitReader, codebook: &Codebook) -> Result<usize> {
+    let entry = reader.read(index_bits(codebook.entries))? as usize;
+    if entry >= codebook.entries {
+        return Err(Error::InvalidData);
+    }
+    Ok(entry)
+}
+
+fn apply_entry(frame: &mut [u8], block: &Block, entry: usize, codebook: &Codebook, params: &FrameParams) {
+    let size = params.block_size;
+    let tile = &codebook.tiles[entry * (size * size) as usize..];
+    for y in 0..block.height {
+        for x in 0..block.width {
+            frame[(block.x + x + (block.y + y) * params.width) as usize] = tile[(x + y * size) as usize];
+        }
+    }
+}
+
+pub fn decode_intra(payload: &[u8], frame: &mut [u8], params: &FrameParams, codebook: &mut Codebook) -> Result<()> {
+    let mut reader = params.reader(payload);
+    if !params.codebook {
+        for index in frame.iter_mut() {
+            *index = read_index(&mut reader, params)?;
+        }
+        return Ok(());
+    }
+
+    let tile_size = (params.block_size * params.block_size) as usize;
+    codebook.entries = reader.read(8)? as usize + 1;
+    for index in codebook.tiles[..codebook.entries * tile_size].iter_mut() {
+        *index = read_index(&mut reader, params)?;
+    }
+    for block in blocks(params) {
+        let entry = read_entry(&mut reader, codebook)?;
+        apply_entry(frame, &block, entry, codebook, params);
+    }
+    Ok(())
+}
+
+/// Applies a delta frame on top of `frame`, `previous` must hold a copy of it.
+pub fn decode_inter(
+    payload: &[u8],
+    frame: &mut [u8],
+    previous: &[u8],
+    params: &FrameParams,
+    codebook: &Codebook,
+) -> Result<()> {
+    // The skip bitmap comes first, the operations of coded blocks follow it
+    let count = blocks(params).count() as u32;
+    let mut bitmap = params.reader(payload);
+    let mut reader = params.reader(payload);
+    reader.skip(count)?;
+
+    let r = params.motion_radius as i32;
+    let motion_bits = index_bits(params.motion_radius as usize * 2 + 1);
+    for block in blocks(params) {
+        if !bitmap.read_bit()? {
+            continue;
+        }
+        match reader.read(OP_BITS)? {
+            OP_MOTION => {
+                let dx = reader.read(motion_bits)? as i32 - r;
+                let dy = reader.read(motion_bits)? as i32 - r;
+                let sx = block.x as i32 + dx;
+                let sy = block.y as i32 + dy;
+                if dx.abs() > r
+                    || dy.abs() > r
+                    || sx < 0
+                    || sy < 0
+                    || sx as u32 + block.width > params.width
+                    || sy as u32 + block.height > params.height
+                {
+                    return Err(Error::InvalidData);
+                }
+                for y in 0..block.height {
+                    let target = (block.x + (block.y + y) * params.width) as usize;
+                    let source = (sx as u32 + (sy as u32 + y) * params.width) as usize;
+                    frame[target..target + block.width as usize]
+                        .copy_from_slice(&previous[source..source + block.width as usize]);
+                }
+            }
+            OP_FILL => {
+                let index = read_index(&mut reader, params)?;
+                for y in 0..block.height {
+                    let target = (block.x + (block.y + y) * params.width) as usize;
+                    frame[target..target + block.width as usize].fill(index);
+                }
+            }
+            OP_DIRECT if params.codebook => {
+                if codebook.entries == 0 {
+                    return Err(Error::InvalidData);
+                }
+                let entry = read_entry(&mut reader, codebook)?;
+                apply_entry(frame, &block, entry, codebook, params);
+            }
+            OP_DIRECT => {
+                for y in 0..block.height {
+                    for x in 0..block.width {
+                        frame[(block.x + x + (block.y + y) * params.width) as usize] = read_index(&mut reader, params)?;
+                    }
+                }
+            }
+            _ => return Err(Error::InvalidData),
+        }
+    }
+    Ok(())
+}
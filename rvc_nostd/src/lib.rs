@@ -0,0 +1,328 @@
+//! Decoder of RVC streams for targets without `std` or a heap. The stream is read from a byte
+//! slice and frames are decoded into a caller-supplied indexed framebuffer of `width * height`
+//! bytes. All other state lives in a scratch buffer of `Header::scratch_size` bytes, so the
+//! memory needed by a stream is known as soon as its header is parsed.
+
+#![no_std]
+
+use core::fmt;
+
+use crate::{
+    bits::index_bits,
+    container::{
+        CYCLE_REVERSE, CycleRange, FRAME_KEY, Header, MAX_COLORS, MAX_CYCLES, TAG_AUDIO, TAG_CYCLES, TAG_FRAME,
+        TAG_INDEX, TAG_PALETTE, TAG_PALETTE_DELTA, next_chunk, u32_at,
+    },
+    frame::{Codebook, FrameParams, decode_inter, decode_intra},
+};
+
+pub mod audio;
+pub mod bits;
+pub mod container;
+pub mod entropy;
+pub mod frame;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    NotRvc,
+    UnsupportedVersion,
+    InvalidHeader,
+    MissingPalette,
+    UnexpectedEnd,
+    InvalidData,
+    UnknownEntropy,
+    UnknownAudioCodec,
+    BufferTooSmall,
+    FrameOutOfRange,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::NotRvc => "Not an RVC stream",
+            Error::UnsupportedVersion => "Unsupported RVC version",
+            Error::InvalidHeader => "Invalid stream header",
+            Error::MissingPalette => "Missing palette",
+            Error::UnexpectedEnd => "Unexpected end of data",
+            Error::InvalidData => "Invalid frame data",
+            Error::UnknownEntropy => "Unknown entropy back-end",
+            Error::UnknownAudioCodec => "Unknown audio codec",
+            Error::BufferTooSmall => "Buffer is too small",
+            Error::FrameOutOfRange => "Frame is out of range",
+        })
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// A decoded frame. Its indices are available through `Decoder::frame`.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame<'a> {
+    pub number: u32,
+    pub keyframe: bool,
+    /// Encoded sound of the frame, see `audio::decode`.
+    pub audio: Option<&'a [u8]>,
+}
+
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Position of the first chunk after the header.
+    start: usize,
+    header: Header,
+    /// Palette at the start of the stream, keyframes that follow palette changes carry their own.
+    initial_palette: &'a [u8],
+    /// Current palette as RGB triplets, `colors` of them are in use.
+    palette: &'a mut [u8],
+    colors: usize,
+    /// Cycling ranges of the palette, `cycle_count` of them are in use.
+    cycles: [CycleRange; MAX_CYCLES],
+    cycle_count: usize,
+    /// Number of colors the last frame was decoded with.
+    frame_colors: usize,
+    frame: &'a mut [u8],
+    previous: &'a mut [u8],
+    codebook: Codebook<'a>,
+    payload: &'a mut [u8],
+    current: u32,
+}
+
+fn parse_palette(data: &[u8]) -> Result<&[u8]> {
+    if data.len() < 2 {
+        return Err(Error::UnexpectedEnd);
+    }
+    let count = u16::from_le_bytes([data[0], data[1]]) as usize;
+    if count == 0 || count > MAX_COLORS {
+        return Err(Error::MissingPalette);
+    }
+    data.get(2..2 + count * 3).ok_or(Error::UnexpectedEnd)
+}
+
+/// Changes single palette entries as listed by a palette delta chunk.
+fn apply_palette_delta(palette: &mut [u8], colors: usize, data: &[u8]) -> Result<()> {
+    if data.len() < 2 {
+        return Err(Error::UnexpectedEnd);
+    }
+    let count = u16::from_le_bytes([data[0], data[1]]) as usize;
+    let entries = data.get(2..2 + count * 4).ok_or(Error::UnexpectedEnd)?;
+    for entry in entries.chunks_exact(4) {
+        let index = entry[0] as usize;
+        if index >= colors {
+            return Err(Error::InvalidData);
+        }
+        palette[index * 3..index * 3 + 3].copy_from_slice(&entry[1..]);
+    }
+    Ok(())
+}
+
+/// Reads the ranges of a cycling chunk into `cycles` and returns their number. Ranges must fit
+/// the palette of `colors` entries and must not overlap.
+fn parse_cycles(data: &[u8], colors: usize, cycles: &mut [CycleRange; MAX_CYCLES]) -> Result<usize> {
+    if data.len() < 2 {
+        return Err(Error::UnexpectedEnd);
+    }
+    let count = u16::from_le_bytes([data[0], data[1]]) as usize;
+    let ranges = data.get(2..2 + count * 6).ok_or(Error::UnexpectedEnd)?;
+    if count > MAX_CYCLES {
+        return Err(Error::InvalidData);
+    }
+    for (i, range) in ranges.chunks_exact(6).enumerate() {
+        let range = CycleRange {
+            start: range[0],
+            end: range[1],
+            rate: u16::from_le_bytes([range[2], range[3]]),
+            reverse: range[4] & CYCLE_REVERSE != 0,
+        };
+        if range.start >= range.end
+            || range.end as usize >= colors
+            || cycles[..i]
+                .iter()
+                .any(|other| range.start <= other.end && other.start <= range.end)
+        {
+            return Err(Error::InvalidData);
+        }
+        cycles[i] = range;
+    }
+    Ok(count)
+}
+
+impl<'a> Decoder<'a> {
+    /// Opens a stream. `frame` must hold at least `Header::frame_size` bytes and `scratch`
+    /// at least `Header::scratch_size` bytes.
+    pub fn new(data: &'a [u8], frame: &'a mut [u8], scratch: &'a mut [u8]) -> Result<Decoder<'a>> {
+        let header = Header::parse(data)?;
+        if header.entropy > entropy::HUFFMAN {
+            return Err(Error::UnknownEntropy);
+        }
+        if frame.len() < header.frame_size() || scratch.len() < header.scratch_size() {
+            return Err(Error::BufferTooSmall);
+        }
+        let frame = &mut frame[..header.frame_size()];
+        let (previous, scratch) = scratch.split_at_mut(header.frame_size());
+        let (palette, scratch) = scratch.split_at_mut(MAX_COLORS * 3);
+        let tiles_size = if header.codebook {
+            container::MAX_CODEBOOK_ENTRIES * (header.block_size * header.block_size) as usize
+        } else {
+            0
+        };
+        let (tiles, payload) = scratch.split_at_mut(tiles_size);
+
+        let mut position = 8;
+        next_chunk(data, &mut position)?;
+        let initial_palette = match next_chunk(data, &mut position)? {
+            Some((TAG_PALETTE, chunk)) => parse_palette(chunk)?,
+            _ => return Err(Error::MissingPalette),
+        };
+        palette[..initial_palette.len()].copy_from_slice(initial_palette);
+        Ok(Decoder {
+            data,
+            position,
+            start: position,
+            header,
+            initial_palette,
+            palette,
+            colors: initial_palette.len() / 3,
+            cycles: [CycleRange::default(); MAX_CYCLES],
+            cycle_count: 0,
+            frame_colors: initial_palette.len() / 3,
+            frame,
+            previous,
+            codebook: Codebook { tiles, entries: 0 },
+            payload,
+            current: 0,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Current palette as RGB triplets before color cycling, see `cycled_palette`.
+    pub fn palette(&self) -> &[u8] {
+        &self.palette[..self.colors * 3]
+    }
+
+    /// Cycling ranges of the current palette.
+    pub fn cycles(&self) -> &[CycleRange] {
+        &self.cycles[..self.cycle_count]
+    }
+
+    /// Copies the current palette to `rgb` with the cycling ranges rotated as they are at the time
+    /// of `frame`. `rgb` must hold at least `colors() * 3` bytes.
+    pub fn cycled_palette(&self, frame: u32, rgb: &mut [u8]) -> Result<()> {
+        let rgb = rgb.get_mut(..self.colors * 3).ok_or(Error::BufferTooSmall)?;
+        rgb.copy_from_slice(self.palette());
+        let time_num = frame as u64 * self.header.fps_den as u64;
+        for range in self.cycles() {
+            let steps = range.steps(time_num, self.header.fps_num as u64);
+            rgb[range.start as usize * 3..(range.end as usize + 1) * 3].rotate_right(steps * 3);
+        }
+        Ok(())
+    }
+
+    pub fn colors(&self) -> usize {
+        self.colors
+    }
+
+    /// Replaces the palette, which clears its cycling ranges.
+    fn set_palette(&mut self, rgb: &[u8]) {
+        self.palette[..rgb.len()].copy_from_slice(rgb);
+        self.colors = rgb.len() / 3;
+        self.cycle_count = 0;
+    }
+
+    /// Indices of the last decoded frame.
+    pub fn frame(&self) -> &[u8] {
+        self.frame
+    }
+
+    /// Number of the frame that the next call to `next_frame` will return.
+    pub fn position(&self) -> u32 {
+        self.current
+    }
+
+    pub fn next_frame(&mut self) -> Result<Option<Frame<'a>>> {
+        let mut audio = None;
+        loop {
+            let data = self.data;
+            match next_chunk(data, &mut self.position)? {
+                Some((TAG_PALETTE, chunk)) => self.set_palette(parse_palette(chunk)?),
+                Some((TAG_PALETTE_DELTA, chunk)) => apply_palette_delta(self.palette, self.colors, chunk)?,
+                Some((TAG_CYCLES, chunk)) => self.cycle_count = parse_cycles(chunk, self.colors, &mut self.cycles)?,
+                Some((TAG_AUDIO, chunk)) => audio = Some(chunk),
+                Some((TAG_FRAME, chunk)) => {
+                    let (flags, body) = chunk.split_first().ok_or(Error::UnexpectedEnd)?;
+                    let keyframe = flags & FRAME_KEY != 0;
+                    if !keyframe && self.colors != self.frame_colors {
+                        return Err(Error::InvalidData);
+                    }
+                    self.decode(keyframe, body)?;
+                    self.frame_colors = self.colors;
+                    self.current += 1;
+                    return Ok(Some(Frame {
+                        number: self.current - 1,
+                        keyframe,
+                        audio,
+                    }));
+                }
+                Some((TAG_INDEX, _)) | None => return Ok(None),
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn decode(&mut self, keyframe: bool, body: &[u8]) -> Result<()> {
+        let params = FrameParams {
+            width: self.header.width,
+            height: self.header.height,
+            block_size: self.header.block_size,
+            index_bits: index_bits(self.colors()),
+            colors: self.colors(),
+            motion_radius: self.header.motion_radius,
+            codebook: self.header.codebook,
+            unpacked: entropy::unpacked(self.header.entropy),
+        };
+        let payload = if self.header.entropy == entropy::STORE {
+            body
+        } else {
+            let size = entropy::decompress(self.header.entropy, body, self.payload)?;
+            &self.payload[..size]
+        };
+        if keyframe {
+            decode_intra(payload, self.frame, &params, &mut self.codebook)
+        } else {
+            self.previous.copy_from_slice(self.frame);
+            decode_inter(payload, self.frame, self.previous, &params, &self.codebook)
+        }
+    }
+
+    /// Positions the decoder so that the next decoded frame is `frame`.
+    pub fn seek(&mut self, frame: u32) -> Result<()> {
+        if frame >= self.header.frame_count {
+            return Err(Error::FrameOutOfRange);
+        }
+        // Without an index decoding starts over from the first frame
+        let (mut keyframe, mut offset) = (0, self.start);
+        let mut position = self.header.index_offset as usize;
+        if let Some((TAG_INDEX, index)) = next_chunk(self.data, &mut position)? {
+            let count = u32_at(index.get(..4).ok_or(Error::UnexpectedEnd)?, 0) as usize;
+            let entries = index.get(4..4 + count * 8).ok_or(Error::UnexpectedEnd)?;
+            for entry in entries.chunks_exact(8) {
+                if u32_at(entry, 0) > frame {
+                    break;
+                }
+                keyframe = u32_at(entry, 0);
+                offset = u32_at(entry, 4) as usize;
+            }
+        }
+        self.position = offset;
+        self.current = keyframe;
+        self.set_palette(self.initial_palette);
+        while self.current < frame {
+            if self.next_frame()?.is_none() {
+                return Err(Error::FrameOutOfRange);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use rvc_codec::{
+    decoder,
+    encoder::{Encoder, EncoderConfig},
+};
+use rvc_nostd::Decoder;
+use rvc_shared::{
+    colors::{FloatColor, IntColor},
+    container::Header,
+    palette::{CYCLE_RATE_60, CycleDirection, CycleRange, Palette},
+    plane::Plane,
+};
+use std::io::Cursor;
+
+fn palette(shift: i32) -> Palette {
+    let mut palette = Palette::new();
+    for i in 0..16 {
+        palette.add(FloatColor::new(i * 17, 255 - i * 13, (i * 53 + shift) % 256));
+    }
+    palette
+}
+
+#[test]
+fn cycled_palette_matches_std_decoder() {
+    let mut first = palette(0);
+    first
+        .add_cycle(CycleRange::new(1, 4, 7.0, CycleDirection::Forward))
+        .unwrap();
+    first
+        .add_cycle(CycleRange::new(8, 14, 11.5, CycleDirection::Backward))
+        .unwrap();
+    let mut second = palette(0);
+    second
+        .add_cycle(CycleRange::new(5, 9, 3.0, CycleDirection::Backward))
+        .unwrap();
+    let config = EncoderConfig {
+        keyframe_interval: 10,
+        ..Default::default()
+    };
+    let mut encoder = Encoder::new(Cursor::new(vec![]), Header::new(16, 8, 25, 1), &palette(0), config).unwrap();
+    for number in 0..60 {
+        match number {
+            3 => encoder.set_palette(&first).unwrap(),
+            25 => encoder.set_palette(&second).unwrap(),
+            40 => encoder.set_palette(&palette(40)).unwrap(),
+            _ => {}
+        }
+        let mut frame = Plane::new(16, 8, 0);
+        for (i, index) in frame.data.iter_mut().enumerate() {
+            *index = ((i as i32 + number) / 3) % 16;
+        }
+        encoder.encode(&frame).unwrap();
+    }
+    let stream = encoder.finish().unwrap().into_inner();
+
+    let mut reference = decoder::Decoder::new(Cursor::new(&stream)).unwrap();
+    let header = rvc_nostd::container::Header::parse(&stream).unwrap();
+    let (mut frame, mut scratch) = (vec![0; header.frame_size()], vec![0; header.scratch_size()]);
+    let mut decoder = Decoder::new(&stream, &mut frame, &mut scratch).unwrap();
+    let mut rgb = [0; 16 * 3];
+    let mut cycled = 0;
+    while let Some(expected) = reference.next_frame().unwrap() {
+        let expected = expected.clone();
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(decoder.cycles().len(), reference.base_palette().cycles().len());
+        decoder.cycled_palette(frame.number, &mut rgb).unwrap();
+        let colors: Vec<u8> = (0..reference.palette().len() as i32)
+            .flat_map(|i| {
+                let color = IntColor::from(reference.palette().get(i));
+                [color.r as u8, color.g as u8, color.b as u8]
+            })
+            .collect();
+        assert_eq!(rgb[..], colors[..], "frame {}", frame.number);
+        let indices: Vec<u8> = expected.data.iter().map(|index| *index as u8).collect();
+        assert_eq!(decoder.frame(), &indices[..]);
+        if rgb[..] != decoder.palette()[..] {
+            cycled += 1;
+        }
+    }
+    assert!(decoder.next_frame().unwrap().is_none());
+    assert!(cycled > 20);
+
+    assert!(decoder.cycled_palette(0, &mut [0; 16]).is_err());
+}
+
+#[test]
+fn steps_match_std_at_step_boundaries() {
+    // Rates whose steps land exactly on frame times, where the slightest rounding picks the
+    // step before
+    for (fps_num, fps_den) in [(25, 1), (30000, 1001), (50, 1), (24, 1)] {
+        for rate in [CYCLE_RATE_60 as u16, CYCLE_RATE_60 as u16 / 2, 4096, 12288, 20480, 65535, 1] {
+            for direction in [CycleDirection::Forward, CycleDirection::Backward] {
+                let range = CycleRange {
+                    start: 2,
+                    end: 8,
+                    rate,
+                    direction,
+                };
+                let nostd = rvc_nostd::container::CycleRange {
+                    start: 2,
+                    end: 8,
+                    rate,
+                    reverse: direction == CycleDirection::Backward,
+                };
+                for frame in 0..3000u64 {
+                    assert_eq!(
+                        range.steps(frame * fps_den, fps_num),
+                        nostd.steps(frame * fps_den, fps_num),
+                        "{} at {}/{} fps, frame {}",
+                        rate,
+                        fps_num,
+                        fps_den,
+                        frame
+                    );
+                }
+            }
+        }
+    }
+}
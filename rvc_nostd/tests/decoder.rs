@@ -0,0 +1,188 @@
+use rvc_codec::{
+    audio::Codec,
+    decoder,
+    encoder::{Encoder, EncoderConfig},
+    entropy::Backend,
+    rate::Budget,
+};
+use rvc_nostd::{Decoder, Error, audio};
+use rvc_shared::{
+    colors::{FloatColor, IntColor},
+    container::{AudioFormat, Header},
+    palette::Palette,
+    plane::Plane,
+};
+use std::{io::Cursor, sync::OnceLock};
+
+const WIDTH: u32 = 44;
+const HEIGHT: u32 = 30;
+
+fn palette() -> Palette {
+    let mut palette = Palette::new();
+    for i in 0..16 {
+        palette.add(FloatColor::new(i * 17, 255 - i * 13, (i * 53) % 256));
+    }
+    palette
+}
+
+/// Square moving over a gradient that changes every 20 frames, with flickering pixels.
+fn frame(number: u32) -> Plane<i32> {
+    let (cut, time) = (number / 20, number % 20);
+    let (left, top) = (time * 2 % (WIDTH - 10), time % (HEIGHT - 8));
+    let mut frame = Plane::new(WIDTH, HEIGHT, 0);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let index = if (left..left + 10).contains(&x) && (top..top + 8).contains(&y) {
+                12 + ((x - left) / 3 + (y - top) / 2) % 4
+            } else if (x * 7 + y * 13 + number * 5).is_multiple_of(29) {
+                (x + number) % 16
+            } else {
+                ((x + y * 3) / 5 + cut * 5) % 12
+            };
+            frame.set(x, y, index as i32);
+        }
+    }
+    frame
+}
+
+fn encode(config: EncoderConfig, codec: Codec) -> Vec<u8> {
+    let mut header = Header::new(WIDTH, HEIGHT, 25, 1);
+    header.audio = Some(AudioFormat {
+        sample_rate: 11025,
+        channels: 2,
+        codec: codec.id(),
+    });
+    let mut encoder = Encoder::new(Cursor::new(vec![]), header, &palette(), config).unwrap();
+    let mut time = 0;
+    for number in 0..60 {
+        let samples: Vec<i16> = (0..encoder.audio_samples())
+            .map(|i| (((time + i / 2) as f64 * 0.07).sin() * 9000.0) as i16)
+            .collect();
+        time += samples.len() / 2;
+        encoder.push_audio(&samples);
+        encoder.encode(&frame(number)).unwrap();
+    }
+    encoder.finish().unwrap().into_inner()
+}
+
+/// Streams of every entropy back-end and codebook mode, encoded once for all tests.
+fn streams() -> &'static [Vec<u8>] {
+    static STREAMS: OnceLock<Vec<Vec<u8>>> = OnceLock::new();
+    STREAMS.get_or_init(encode_all)
+}
+
+fn encode_all() -> Vec<Vec<u8>> {
+    let backends = [Backend::None, Backend::Rle, Backend::Lz77, Backend::Huffman];
+    let codecs = [Codec::Pcm16, Codec::Pcm8, Codec::Adpcm];
+    let mut streams = vec![];
+    for (i, entropy) in backends.into_iter().enumerate() {
+        // Raw blocks with motion, both codebook tile sizes and degraded frames with fills
+        let configs = [
+            EncoderConfig {
+                block_size: 4,
+                keyframe_interval: 12,
+                entropy,
+                ..Default::default()
+            },
+            EncoderConfig {
+                block_size: 2,
+                codebook_size: 256,
+                keyframe_interval: 12,
+                entropy,
+                ..Default::default()
+            },
+            EncoderConfig {
+                block_size: 4,
+                codebook_size: 16,
+                entropy,
+                ..Default::default()
+            },
+            EncoderConfig {
+                block_size: 8,
+                budget: Budget::BytesPerFrame(150),
+                entropy,
+                ..Default::default()
+            },
+        ];
+        for (j, config) in configs.into_iter().enumerate() {
+            streams.push(encode(config, codecs[(i + j) % codecs.len()]));
+        }
+    }
+    streams
+}
+
+fn rgb(palette: &Palette) -> Vec<u8> {
+    (0..palette.len() as i32)
+        .flat_map(|i| {
+            let color = IntColor::from(palette.get(i));
+            [color.r as u8, color.g as u8, color.b as u8]
+        })
+        .collect()
+}
+
+fn indices(frame: &Plane<i32>) -> Vec<u8> {
+    frame.data.iter().map(|index| *index as u8).collect()
+}
+
+#[test]
+fn frames_match_std_decoder() {
+    for stream in streams() {
+        let mut reference = decoder::Decoder::new(Cursor::new(stream)).unwrap();
+        let header = rvc_nostd::container::Header::parse(stream).unwrap();
+        let format = header.audio.unwrap();
+        let (mut frame, mut scratch) = (vec![0; header.frame_size()], vec![0; header.scratch_size()]);
+        let mut decoder = Decoder::new(stream, &mut frame, &mut scratch).unwrap();
+        let mut samples = vec![0; header.max_audio_samples() * format.channels as usize];
+        while let Some(expected) = reference.next_frame().unwrap() {
+            let expected = indices(expected);
+            let frame = decoder.next_frame().unwrap().unwrap();
+            assert_eq!(decoder.frame(), &expected[..], "frame {}", frame.number);
+            assert_eq!(decoder.palette(), &rgb(reference.palette())[..]);
+
+            let count = header.audio_samples(frame.number) * format.channels as usize;
+            audio::decode(format.codec, format.channels, frame.audio.unwrap(), &mut samples[..count]).unwrap();
+            assert_eq!(&samples[..count], reference.audio());
+        }
+        assert!(decoder.next_frame().unwrap().is_none());
+        assert_eq!(decoder.position(), 60);
+    }
+}
+
+#[test]
+fn seek_matches_std_decoder() {
+    for stream in streams() {
+        let mut reference = decoder::Decoder::new(Cursor::new(stream)).unwrap();
+        let header = rvc_nostd::container::Header::parse(stream).unwrap();
+        let (mut frame, mut scratch) = (vec![0; header.frame_size()], vec![0; header.scratch_size()]);
+        let mut decoder = Decoder::new(stream, &mut frame, &mut scratch).unwrap();
+        for target in [37, 0, 12, 11, 59, 24, 5] {
+            reference.seek(target).unwrap();
+            decoder.seek(target).unwrap();
+            assert_eq!(decoder.position(), target);
+            let expected = indices(reference.next_frame().unwrap().unwrap());
+            assert_eq!(decoder.next_frame().unwrap().unwrap().number, target);
+            assert_eq!(decoder.frame(), &expected[..], "frame {}", target);
+            assert_eq!(decoder.palette(), &rgb(reference.palette())[..]);
+        }
+        assert_eq!(decoder.seek(60).unwrap_err(), Error::FrameOutOfRange);
+    }
+}
+
+#[test]
+fn buffer_bounds() {
+    for stream in streams() {
+        let header = rvc_nostd::container::Header::parse(stream).unwrap();
+        assert_eq!(header.frame_size(), (WIDTH * HEIGHT) as usize);
+        let (frame_size, scratch_size) = (header.frame_size(), header.scratch_size());
+        let (mut frame, mut scratch) = (vec![0; frame_size - 1], vec![0; scratch_size]);
+        let error = Decoder::new(stream, &mut frame, &mut scratch).err();
+        assert_eq!(error, Some(Error::BufferTooSmall));
+        let (mut frame, mut scratch) = (vec![0; frame_size], vec![0; scratch_size - 1]);
+        let error = Decoder::new(stream, &mut frame, &mut scratch).err();
+        assert_eq!(error, Some(Error::BufferTooSmall));
+        // Exactly sized buffers are enough for every frame
+        let (mut frame, mut scratch) = (vec![0; frame_size], vec![0; scratch_size]);
+        let mut decoder = Decoder::new(stream, &mut frame, &mut scratch).unwrap();
+        while decoder.next_frame().unwrap().is_some() {}
+    }
+}
@@ -0,0 +1,812 @@
+use anyhow::{Result, anyhow, bail};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::{
+    colors::{FloatColor, IntColor},
+    palette::{CycleDirection, CycleRange, Palette},
+};
+
+pub const MAGIC: [u8; 4] = *b"RVC\x1a";
+pub const VERSION: u16 = 1;
+
+pub const TAG_HEADER: [u8; 4] = *b"HEAD";
+pub const TAG_PALETTE: [u8; 4] = *b"PALT";
+pub const TAG_FRAME: [u8; 4] = *b"FRAM";
+pub const TAG_INDEX: [u8; 4] = *b"INDX";
+pub const TAG_AUDIO: [u8; 4] = *b"AUDI";
+pub const TAG_PALETTE_DELTA: [u8; 4] = *b"PDLT";
+pub const TAG_CYCLES: [u8; 4] = *b"CRNG";
+
+pub const CYCLE_REVERSE: u8 = 1;
+
+/// Chunks that `ContainerReader` reads, others are skipped.
+const KNOWN_TAGS: [[u8; 4]; 6] = [
+    TAG_PALETTE,
+    TAG_PALETTE_DELTA,
+    TAG_CYCLES,
+    TAG_FRAME,
+    TAG_INDEX,
+    TAG_AUDIO,
+];
+
+pub const FRAME_KEY: u8 = 1;
+
+pub const CHUNK_HEADER_SIZE: usize = 8;
+/// Bytes a frame chunk adds on top of its payload.
+pub const FRAME_OVERHEAD: usize = CHUNK_HEADER_SIZE + 1;
+
+const HEADER_SIZE: usize = 28;
+/// Larger headers are accepted for extensions, but not without limits.
+const MAX_HEADER_SIZE: usize = 1024;
+/// Largest block, and codebook tile, that chunk size limits allow for.
+const MAX_BLOCK_SIZE: u32 = 16;
+/// Motion vectors are stored with their offset in a byte.
+const MAX_MOTION_RADIUS: u32 = 127;
+/// Codebooks have at most 256 tiles of 16x16 indices.
+const MAX_CODEBOOK_SIZE: usize = 256 * (MAX_BLOCK_SIZE * MAX_BLOCK_SIZE) as usize;
+/// Largest of the palette, palette delta and cycling chunks.
+const MAX_PALETTE_CHUNK_SIZE: usize = 2 + 256 * 6;
+const HEADER_OFFSET: u64 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// Codec of the audio chunks.
+    pub codec: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub frame_count: u32,
+    pub block_size: u32,
+    pub motion_radius: u32,
+    pub codebook: bool,
+    /// Compressor of the frame payloads.
+    pub entropy: u8,
+    /// Format of the audio chunks interleaved with the frames, if the stream has sound.
+    pub audio: Option<AudioFormat>,
+    index_offset: u32,
+}
+
+impl Header {
+    pub fn new(width: u32, height: u32, fps_num: u32, fps_den: u32) -> Header {
+        Header {
+            width,
+            height,
+            fps_num,
+            fps_den,
+            frame_count: 0,
+            block_size: 8,
+            motion_radius: 0,
+            codebook: false,
+            entropy: 0,
+            audio: None,
+            index_offset: 0,
+        }
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps_num as f64 / self.fps_den as f64
+    }
+
+    /// Number of samples per channel that accompany `frame`. Frame boundaries are rounded
+    /// down from the exact time, so the audio never drifts from the video.
+    pub fn audio_samples(&self, frame: u32) -> usize {
+        let rate = match &self.audio {
+            Some(audio) => audio.sample_rate as u64,
+            None => return 0,
+        };
+        let start = |frame: u64| frame * rate * self.fps_den as u64 / self.fps_num as u64;
+        (start(frame as u64 + 1) - start(frame as u64)) as usize
+    }
+
+    /// Upper bound of the size of any chunk of a valid stream. Readers refuse larger chunks
+    /// rather than buffering them.
+    pub fn max_chunk_size(&self) -> usize {
+        // Frames take at most two bytes per pixel and a codebook, entropy coding may expand them
+        let frame = 2 * (4 * (self.width * self.height) as usize + MAX_CODEBOOK_SIZE);
+        let audio = match &self.audio {
+            Some(audio) => {
+                let samples = (audio.sample_rate as u64 * self.fps_den as u64).div_ceil(self.fps_num as u64);
+                (samples as usize + 8) * audio.channels as usize * 2
+            }
+            None => 0,
+        };
+        let index = 4 + 8 * self.frame_count as usize;
+        frame.max(audio).max(index).max(MAX_PALETTE_CHUNK_SIZE)
+    }
+
+    fn write(&self, out: &mut impl Write) -> Result<()> {
+        write_u16(out, narrow(self.width, "Frame width")?)?;
+        write_u16(out, narrow(self.height, "Frame height")?)?;
+        write_u16(out, narrow(self.fps_num, "Frame rate numerator")?)?;
+        write_u16(out, narrow(self.fps_den, "Frame rate denominator")?)?;
+        write_u32(out, self.frame_count)?;
+        write_u32(out, self.index_offset)?;
+        out.write_all(&[
+            narrow(self.block_size, "Block size")?,
+            narrow(self.motion_radius, "Motion radius")?,
+            self.codebook as u8,
+            self.entropy,
+        ])?;
+        let audio = self.audio.unwrap_or(AudioFormat {
+            sample_rate: 0,
+            channels: 0,
+            codec: 0,
+        });
+        write_u32(out, audio.sample_rate)?;
+        out.write_all(&[narrow(audio.channels, "Number of audio channels")?, audio.codec])?;
+        write_u16(out, 0)?;
+        Ok(())
+    }
+
+    fn read(data: &[u8]) -> Result<Header> {
+        let mut data = data;
+        let header = Header {
+            width: read_u16(&mut data)? as u32,
+            height: read_u16(&mut data)? as u32,
+            fps_num: read_u16(&mut data)? as u32,
+            fps_den: read_u16(&mut data)? as u32,
+            frame_count: read_u32(&mut data)?,
+            index_offset: read_u32(&mut data)?,
+            block_size: read_u8(&mut data)? as u32,
+            motion_radius: read_u8(&mut data)? as u32,
+            codebook: read_u8(&mut data)? != 0,
+            entropy: read_u8(&mut data)?,
+            audio: match read_u32(&mut data)? {
+                0 => None,
+                sample_rate => Some(AudioFormat {
+                    sample_rate,
+                    channels: read_u8(&mut data)? as u32,
+                    codec: read_u8(&mut data)?,
+                }),
+            },
+        };
+        if header.width == 0
+            || header.height == 0
+            || header.fps_num == 0
+            || header.fps_den == 0
+            || !(2..=MAX_BLOCK_SIZE).contains(&header.block_size)
+            || header.motion_radius > MAX_MOTION_RADIUS
+            || header.motion_radius > header.width.max(header.height)
+            || header.audio.is_some_and(|audio| audio.channels == 0)
+        {
+            bail!("Invalid stream header");
+        }
+        Ok(header)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct IndexEntry {
+    pub frame: u32,
+    pub offset: u32,
+}
+
+pub struct Frame<'a> {
+    pub keyframe: bool,
+    pub data: &'a [u8],
+}
+
+/// New color of a single palette entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaletteEntry {
+    pub index: u8,
+    pub color: IntColor,
+}
+
+/// Chunks that precede a frame chunk apply to that frame.
+pub enum Chunk<'a> {
+    Palette(Palette),
+    /// Changes of single palette entries, the rest of the palette is kept.
+    PaletteDelta(Vec<PaletteEntry>),
+    /// Cycling ranges of the palette. They replace the previous ranges, a full palette clears them.
+    Cycles(Vec<CycleRange>),
+    Frame(Frame<'a>),
+    Index(Vec<IndexEntry>),
+    /// Encoded sound of the frame that follows.
+    Audio(&'a [u8]),
+}
+
+/// Converts a header field to its stored size, failing if it doesn't fit.
+fn narrow<T: TryFrom<u32>>(value: u32, name: &str) -> Result<T> {
+    match T::try_from(value) {
+        Ok(value) => Ok(value),
+        Err(_) => bail!("{} {} is too large for the stream header", name, value),
+    }
+}
+
+fn write_u16(out: &mut impl Write, value: u16) -> Result<()> {
+    out.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_u32(out: &mut impl Write, value: u32) -> Result<()> {
+    out.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u8(data: &mut &[u8]) -> Result<u8> {
+    let mut buf = [0u8; 1];
+    data.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16(data: &mut &[u8]) -> Result<u16> {
+    let mut buf = [0u8; 2];
+    data.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    data.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub fn encode_palette(palette: &Palette) -> Vec<u8> {
+    let mut data = Vec::with_capacity(2 + palette.len() * 3);
+    data.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for i in 0..palette.len() {
+        let color = IntColor::from(palette.get(i as i32));
+        data.extend_from_slice(&[color.r as u8, color.g as u8, color.b as u8]);
+    }
+    data
+}
+
+pub fn decode_palette(data: &[u8]) -> Result<Palette> {
+    let mut data = data;
+    let count = read_u16(&mut data)? as usize;
+    if data.len() < count * 3 {
+        bail!("Unexpected end of palette chunk");
+    }
+    let mut palette = Palette::new();
+    for rgb in data[..count * 3].chunks_exact(3) {
+        palette.add(FloatColor::from(IntColor::new(
+            rgb[0] as i32,
+            rgb[1] as i32,
+            rgb[2] as i32,
+        )));
+    }
+    Ok(palette)
+}
+
+pub fn encode_palette_delta(entries: &[PaletteEntry]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(2 + entries.len() * 4);
+    data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for entry in entries {
+        let color = entry.color;
+        data.extend_from_slice(&[entry.index, color.r as u8, color.g as u8, color.b as u8]);
+    }
+    data
+}
+
+pub fn decode_palette_delta(data: &[u8]) -> Result<Vec<PaletteEntry>> {
+    let mut data = data;
+    let count = read_u16(&mut data)? as usize;
+    if data.len() < count * 4 {
+        bail!("Unexpected end of palette delta chunk");
+    }
+    Ok(data[..count * 4]
+        .chunks_exact(4)
+        .map(|entry| PaletteEntry {
+            index: entry[0],
+            color: IntColor::new(entry[1] as i32, entry[2] as i32, entry[3] as i32),
+        })
+        .collect())
+}
+
+/// Entries of `new` that differ from `old`, both palettes must have the same size.
+pub fn palette_delta(old: &Palette, new: &Palette) -> Vec<PaletteEntry> {
+    (0..new.len().min(old.len()))
+        .map(|i| (i, IntColor::from(new.get(i as i32))))
+        .filter(|(i, color)| IntColor::from(old.get(*i as i32)) != *color)
+        .map(|(i, color)| PaletteEntry { index: i as u8, color })
+        .collect()
+}
+
+/// Applies a palette delta, failing if an entry is out of the palette range.
+pub fn apply_palette_delta(palette: &mut Palette, entries: &[PaletteEntry]) -> Result<()> {
+    for entry in entries {
+        if entry.index as usize >= palette.len() {
+            bail!("Palette delta changes color {} of {}", entry.index, palette.len());
+        }
+        palette.set(entry.index as i32, FloatColor::from(entry.color));
+    }
+    Ok(())
+}
+
+pub fn encode_cycles(ranges: &[CycleRange]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(2 + ranges.len() * 6);
+    data.extend_from_slice(&(ranges.len() as u16).to_le_bytes());
+    for range in ranges {
+        let flags = match range.direction {
+            CycleDirection::Forward => 0,
+            CycleDirection::Backward => CYCLE_REVERSE,
+        };
+        data.extend_from_slice(&[range.start, range.end]);
+        data.extend_from_slice(&range.rate.to_le_bytes());
+        data.extend_from_slice(&[flags, 0]);
+    }
+    data
+}
+
+pub fn decode_cycles(data: &[u8]) -> Result<Vec<CycleRange>> {
+    let mut data = data;
+    let count = read_u16(&mut data)? as usize;
+    if data.len() < count * 6 {
+        bail!("Unexpected end of cycling chunk");
+    }
+    Ok(data[..count * 6]
+        .chunks_exact(6)
+        .map(|range| CycleRange {
+            start: range[0],
+            end: range[1],
+            rate: u16::from_le_bytes([range[2], range[3]]),
+            direction: if range[4] & CYCLE_REVERSE != 0 {
+                CycleDirection::Backward
+            } else {
+                CycleDirection::Forward
+            },
+        })
+        .collect())
+}
+
+/// Destination of the chunks of an encoded stream. Palette and audio chunks apply to the frame
+/// that follows them.
+pub trait Muxer {
+    type Output;
+
+    /// Header of the stream, with the number of frames written so far.
+    fn header(&self) -> &Header;
+    /// Writes a palette that applies from the next frame on.
+    fn write_palette(&mut self, palette: &Palette) -> Result<()>;
+    /// Changes single entries of the palette from the next frame on.
+    fn write_palette_delta(&mut self, entries: &[PaletteEntry]) -> Result<()>;
+    /// Replaces the cycling ranges of the palette from the next frame on.
+    fn write_cycles(&mut self, ranges: &[CycleRange]) -> Result<()>;
+    /// Writes the sound of the next frame.
+    fn write_audio(&mut self, data: &[u8]) -> Result<()>;
+    fn write_frame(&mut self, keyframe: bool, data: &[u8]) -> Result<()>;
+    /// Completes the stream.
+    fn finish(self) -> Result<Self::Output>
+    where
+        Self: Sized;
+}
+
+/// Muxer of RVC files.
+pub struct ContainerWriter<W: Write + Seek> {
+    out: W,
+    header: Header,
+    index: Vec<IndexEntry>,
+    position: u32,
+    /// Start of the audio and palette chunks written ahead of the next frame.
+    frame_start: Option<u32>,
+}
+
+impl<W: Write + Seek> ContainerWriter<W> {
+    pub fn new(mut out: W, header: Header) -> Result<ContainerWriter<W>> {
+        out.write_all(&MAGIC)?;
+        write_u16(&mut out, VERSION)?;
+        write_u16(&mut out, 0)?;
+        let mut writer = ContainerWriter {
+            out,
+            header,
+            index: vec![],
+            position: 8,
+            frame_start: None,
+        };
+        let mut data = vec![];
+        writer.header.write(&mut data)?;
+        writer.write_chunk(TAG_HEADER, &data)?;
+        Ok(writer)
+    }
+
+    /// Appends a chunk. Chunk offsets are 32 bits, so streams can't grow past 4 GiB.
+    fn write_chunk(&mut self, tag: [u8; 4], data: &[u8]) -> Result<()> {
+        let end = u32::try_from(data.len())
+            .ok()
+            .and_then(|size| size.checked_add(8))
+            .and_then(|size| self.position.checked_add(size));
+        let end = match end {
+            Some(end) => end,
+            None => bail!("Stream exceeds the 4 GiB limit of the container"),
+        };
+        self.out.write_all(&tag)?;
+        write_u32(&mut self.out, data.len() as u32)?;
+        self.out.write_all(data)?;
+        self.position = end;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Muxer for ContainerWriter<W> {
+    type Output = W;
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn write_palette(&mut self, palette: &Palette) -> Result<()> {
+        self.frame_start.get_or_insert(self.position);
+        self.write_chunk(TAG_PALETTE, &encode_palette(palette))
+    }
+
+    fn write_palette_delta(&mut self, entries: &[PaletteEntry]) -> Result<()> {
+        self.frame_start.get_or_insert(self.position);
+        self.write_chunk(TAG_PALETTE_DELTA, &encode_palette_delta(entries))
+    }
+
+    fn write_cycles(&mut self, ranges: &[CycleRange]) -> Result<()> {
+        self.frame_start.get_or_insert(self.position);
+        self.write_chunk(TAG_CYCLES, &encode_cycles(ranges))
+    }
+
+    fn write_audio(&mut self, data: &[u8]) -> Result<()> {
+        if self.header.audio.is_none() {
+            bail!("Stream has no audio track");
+        }
+        self.frame_start.get_or_insert(self.position);
+        self.write_chunk(TAG_AUDIO, data)
+    }
+
+    fn write_frame(&mut self, keyframe: bool, data: &[u8]) -> Result<()> {
+        // Seeking to a keyframe must also pick up its audio and palette
+        let offset = self.frame_start.take().unwrap_or(self.position);
+        if keyframe {
+            self.index.push(IndexEntry {
+                frame: self.header.frame_count,
+                offset,
+            });
+        }
+        let mut chunk = Vec::with_capacity(data.len() + 1);
+        chunk.push(if keyframe { FRAME_KEY } else { 0 });
+        chunk.extend_from_slice(data);
+        self.write_chunk(TAG_FRAME, &chunk)?;
+        self.header.frame_count += 1;
+        Ok(())
+    }
+
+    /// Writes the keyframe index and patches the header with the final frame count.
+    fn finish(mut self) -> Result<W> {
+        self.header.index_offset = self.position;
+        let mut data = vec![];
+        write_u32(&mut data, self.index.len() as u32)?;
+        for entry in self.index.iter() {
+            write_u32(&mut data, entry.frame)?;
+            write_u32(&mut data, entry.offset)?;
+        }
+        self.write_chunk(TAG_INDEX, &data)?;
+
+        let mut data = vec![];
+        self.header.write(&mut data)?;
+        self.out.seek(SeekFrom::Start(HEADER_OFFSET))?;
+        self.out.write_all(&data)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads a stream chunk by chunk. Chunk data is read into a single reused buffer, so only the
+/// largest chunk is held in memory. Seeking needs an input that implements `Seek` as well.
+pub struct ContainerReader<R: Read> {
+    input: R,
+    header: Header,
+    index: Option<Vec<IndexEntry>>,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> ContainerReader<R> {
+    pub fn new(mut input: R) -> Result<ContainerReader<R>> {
+        let mut signature = [0u8; 8];
+        input.read_exact(&mut signature)?;
+        if signature[..4] != MAGIC {
+            bail!("Not an RVC file");
+        }
+        let version = u16::from_le_bytes([signature[4], signature[5]]);
+        if version != VERSION {
+            bail!("Unsupported RVC version {}", version);
+        }
+
+        let (tag, size) = read_chunk_header(&mut input)?.ok_or_else(|| anyhow!("Missing stream header"))?;
+        if tag != TAG_HEADER || !(HEADER_SIZE..=MAX_HEADER_SIZE).contains(&size) {
+            bail!("Missing stream header");
+        }
+        let mut buffer = vec![0u8; size];
+        input.read_exact(&mut buffer)?;
+        let header = Header::read(&buffer)?;
+        Ok(ContainerReader {
+            input,
+            header,
+            index: None,
+            buffer,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Reads the next known chunk, skipping unknown ones. Returns `None` at the end of the file.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk<'_>>> {
+        let tag = loop {
+            let (tag, size) = match read_chunk_header(&mut self.input)? {
+                Some(chunk) => chunk,
+                None => return Ok(None),
+            };
+            if !KNOWN_TAGS.contains(&tag) {
+                skip(&mut self.input, size)?;
+                continue;
+            }
+            let limit = self.header.max_chunk_size();
+            if size > limit {
+                bail!("Chunk of {} bytes exceeds the limit of {} bytes", size, limit);
+            }
+            self.buffer.resize(size, 0);
+            self.input.read_exact(&mut self.buffer)?;
+            break tag;
+        };
+        let data = &self.buffer[..];
+        Ok(Some(match tag {
+            TAG_PALETTE => Chunk::Palette(decode_palette(data)?),
+            TAG_PALETTE_DELTA => Chunk::PaletteDelta(decode_palette_delta(data)?),
+            TAG_CYCLES => Chunk::Cycles(decode_cycles(data)?),
+            TAG_FRAME => match data.split_first() {
+                Some((flags, data)) => Chunk::Frame(Frame {
+                    keyframe: flags & FRAME_KEY != 0,
+                    data,
+                }),
+                None => bail!("Empty frame chunk"),
+            },
+            TAG_INDEX => Chunk::Index(decode_index(data)?),
+            _ => Chunk::Audio(data),
+        }))
+    }
+}
+
+impl<R: Read + Seek> ContainerReader<R> {
+    /// Keyframe index at the end of the stream, read on first use.
+    pub fn index(&mut self) -> Result<&[IndexEntry]> {
+        if self.index.is_none() {
+            let mut index = vec![];
+            if self.header.index_offset != 0 {
+                let start = self.input.stream_position()?;
+                self.input.seek(SeekFrom::Start(self.header.index_offset as u64))?;
+                if let Some(Chunk::Index(entries)) = self.next_chunk()? {
+                    index = entries;
+                }
+                self.input.seek(SeekFrom::Start(start))?;
+            }
+            self.index = Some(index);
+        }
+        Ok(self.index.as_deref().unwrap_or_default())
+    }
+
+    /// Moves to the last keyframe at or before `frame` and returns its number.
+    pub fn seek_keyframe(&mut self, frame: u32) -> Result<u32> {
+        let entry = match self.index()?.iter().rev().find(|e| e.frame <= frame) {
+            Some(entry) => *entry,
+            None => bail!("No keyframe found for frame {}", frame),
+        };
+        self.input.seek(SeekFrom::Start(entry.offset as u64))?;
+        Ok(entry.frame)
+    }
+}
+
+fn decode_index(data: &[u8]) -> Result<Vec<IndexEntry>> {
+    let mut data = data;
+    let count = read_u32(&mut data)?;
+    if data.len() < count as usize * 8 {
+        bail!("Unexpected end of index chunk");
+    }
+    let mut index = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        index.push(IndexEntry {
+            frame: read_u32(&mut data)?,
+            offset: read_u32(&mut data)?,
+        });
+    }
+    Ok(index)
+}
+
+/// Reads the tag and size of the next chunk, or returns `None` at the end of the input.
+fn read_chunk_header(input: &mut impl Read) -> Result<Option<([u8; 4], usize)>> {
+    let mut tag = [0u8; 4];
+    let mut filled = 0;
+    while filled < 4 {
+        let count = input.read(&mut tag[filled..])?;
+        if count == 0 {
+            if filled == 0 {
+                return Ok(None);
+            }
+            bail!("Unexpected end of file");
+        }
+        filled += count;
+    }
+    let mut size = [0u8; 4];
+    input.read_exact(&mut size)?;
+    Ok(Some((tag, u32::from_le_bytes(size) as usize)))
+}
+
+fn skip(input: &mut impl Read, size: usize) -> Result<()> {
+    if std::io::copy(&mut input.take(size as u64), &mut std::io::sink())? != size as u64 {
+        bail!("Unexpected end of file");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn palette(colors: i32) -> Palette {
+        let mut palette = Palette::new();
+        for i in 0..colors {
+            palette.add(FloatColor::from(IntColor::new(i * 8, 255 - i * 8, i)));
+        }
+        palette
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        let mut header = Header::new(320, 200, 30000, 1001);
+        header.frame_count = 1234;
+        header.block_size = 4;
+        header.motion_radius = 7;
+        header.codebook = true;
+        header.entropy = 2;
+        header.index_offset = 5678;
+        for audio in [
+            None,
+            Some(AudioFormat {
+                sample_rate: 22050,
+                channels: 2,
+                codec: 1,
+            }),
+        ] {
+            header.audio = audio;
+            let mut data = vec![];
+            header.write(&mut data).unwrap();
+            assert_eq!(data.len(), HEADER_SIZE);
+            assert_eq!(Header::read(&data).unwrap(), header);
+        }
+    }
+
+    #[test]
+    fn header_rejects_out_of_range() {
+        let mut data = vec![];
+        assert!(Header::new(70000, 200, 25, 1).write(&mut data).is_err());
+        assert!(Header::new(320, 200, 60000, 1001).write(&mut data).is_ok());
+        assert!(Header::new(320, 200, 120000, 1001).write(&mut data).is_err());
+
+        let valid = Header::new(32, 16, 25, 1);
+        let mut invalid = vec![];
+        for (width, height, fps_num, fps_den) in [(0, 16, 25, 1), (32, 0, 25, 1), (32, 16, 0, 1), (32, 16, 25, 0)] {
+            invalid.push(Header::new(width, height, fps_num, fps_den));
+        }
+        for block_size in [0, 1, 17, 32] {
+            invalid.push(Header {
+                block_size,
+                ..valid.clone()
+            });
+        }
+        invalid.push(Header {
+            motion_radius: 33,
+            ..valid.clone()
+        });
+        for header in invalid {
+            let mut data = vec![];
+            header.write(&mut data).unwrap();
+            assert!(Header::read(&data).is_err(), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn writer_patches_header() {
+        let mut writer = ContainerWriter::new(Cursor::new(vec![]), Header::new(4, 2, 25, 1)).unwrap();
+        writer.write_palette(&palette(4)).unwrap();
+        for _ in 0..3 {
+            writer.write_frame(true, &[0; 8]).unwrap();
+        }
+        let data = writer.finish().unwrap().into_inner();
+        let mut reader = ContainerReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(reader.header().frame_count, 3);
+        assert_eq!((reader.header().width, reader.header().height), (4, 2));
+        assert_eq!(reader.index().unwrap().len(), 3);
+        assert_eq!(palette_size(reader.next_chunk().unwrap()), Some(4));
+    }
+
+    #[test]
+    fn writer_rejects_streams_past_4_gib() {
+        let mut writer = ContainerWriter::new(Cursor::new(vec![]), Header::new(4, 2, 25, 1)).unwrap();
+        let start = writer.position;
+        // Pretend most of the 4 GiB have been written already
+        writer.position = u32::MAX - 20;
+        writer.write_frame(true, &[0; 11]).unwrap();
+        assert_eq!(writer.position, u32::MAX);
+        assert!(writer.write_frame(false, &[]).is_err());
+        assert!(writer.write_palette(&palette(1)).is_err());
+        // Nothing of the rejected chunks is written
+        assert_eq!(writer.out.get_ref().len() as u32, start + 20);
+        assert_eq!(writer.header.frame_count, 1);
+        assert!(writer.finish().is_err());
+    }
+
+    fn palette_size(chunk: Option<Chunk>) -> Option<usize> {
+        match chunk {
+            Some(Chunk::Palette(palette)) => Some(palette.len()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn seek_lands_on_keyframe() {
+        let mut header = Header::new(4, 2, 25, 1);
+        header.audio = Some(AudioFormat {
+            sample_rate: 8000,
+            channels: 1,
+            codec: 0,
+        });
+        let mut writer = ContainerWriter::new(Cursor::new(vec![]), header).unwrap();
+        writer.write_palette(&palette(4)).unwrap();
+        for frame in 0..20u8 {
+            // Chunks ahead of a keyframe must be read after seeking to it
+            if frame == 12 {
+                writer.write_palette(&palette(3)).unwrap();
+                writer.write_audio(&[frame; 4]).unwrap();
+            }
+            writer.write_frame(frame % 6 == 0, &[frame]).unwrap();
+        }
+        let data = writer.finish().unwrap().into_inner();
+        let mut reader = ContainerReader::new(Cursor::new(data)).unwrap();
+        for (frame, keyframe) in [(13, 12), (0, 0), (5, 0), (6, 6), (12, 12), (19, 18)] {
+            assert_eq!(reader.seek_keyframe(frame).unwrap(), keyframe);
+            let (mut colors, mut audio) = (None, false);
+            loop {
+                match reader.next_chunk().unwrap() {
+                    Some(Chunk::Palette(palette)) => colors = Some(palette.len()),
+                    Some(Chunk::Audio(_)) => audio = true,
+                    Some(Chunk::Frame(chunk)) => {
+                        assert!(chunk.keyframe);
+                        assert_eq!(chunk.data, [keyframe as u8]);
+                        break;
+                    }
+                    _ => panic!("Expected the keyframe chunk"),
+                }
+            }
+            assert_eq!(audio, keyframe == 12);
+            let expected = match keyframe {
+                0 => Some(4),
+                12 => Some(3),
+                _ => None,
+            };
+            assert_eq!(colors, expected);
+        }
+    }
+
+    #[test]
+    fn palette_delta_roundtrip() {
+        let old = palette(6);
+        let mut new = old.clone();
+        new.set(1, FloatColor::from(IntColor::new(1, 2, 3)));
+        new.set(5, FloatColor::from(IntColor::new(250, 0, 9)));
+        let entries = palette_delta(&old, &new);
+        assert_eq!(entries.iter().map(|e| e.index).collect::<Vec<_>>(), [1, 5]);
+        let data = encode_palette_delta(&entries);
+        assert_eq!(data.len(), 2 + 2 * 4);
+        let mut result = old.clone();
+        apply_palette_delta(&mut result, &decode_palette_delta(&data).unwrap()).unwrap();
+        assert_eq!(encode_palette(&result), encode_palette(&new));
+
+        assert!(decode_palette_delta(&data[..data.len() - 1]).is_err());
+        let mut small = palette(5);
+        assert!(apply_palette_delta(&mut small, &entries).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use rayon::prelude::*;
-use rvc_shared::{
+
+use crate::{
     colors::{FloatColor, IntColor},
     dmatrix::DitherMatrix,
     palette::Palette,
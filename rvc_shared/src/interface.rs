@@ -61,11 +61,11 @@ impl ProgressBar {
         }
     }
 
-    fn step(&mut self, size: u32) {
+    pub fn step(&mut self, size: u32) {
         self.current = min(self.current + size, self.total - 1);
     }
 
-    fn finish(&mut self) {
+    pub fn finish(&mut self) {
         self.current = self.total - 1;
     }
 }
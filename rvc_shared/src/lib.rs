@@ -1,5 +1,10 @@
 pub mod colors;
+pub mod container;
 pub mod dmatrix;
+pub mod indexing;
 pub mod interface;
 pub mod palette;
+pub mod picture;
 pub mod plane;
+pub mod wav;
+pub mod y4m;
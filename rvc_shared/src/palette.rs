@@ -1,50 +1,183 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use crate::colors::{FloatColor, IntColor};
-use anyhow::Result;
+use anyhow::{Result, bail};
+use bincode::{Decode, Encode};
+
+/// Rate of a cycling range that makes 60 steps per second, as in ILBM CRNG chunks.
+pub const CYCLE_RATE_60: u32 = 16384;
+
+#[derive(Clone, Copy, Debug, PartialEq, Decode, Encode)]
+pub enum CycleDirection {
+    /// Colors move towards higher indices, the last one wraps around to the start.
+    Forward,
+    Backward,
+}
+
+/// Range of palette entries whose colors rotate over time.
+#[derive(Clone, Copy, Debug, PartialEq, Decode, Encode)]
+pub struct CycleRange {
+    pub start: u8,
+    /// Last entry of the range, inclusive.
+    pub end: u8,
+    /// Speed in units of `CYCLE_RATE_60`, 0 keeps the range still.
+    pub rate: u16,
+    pub direction: CycleDirection,
+}
+
+impl CycleRange {
+    pub fn new(start: u8, end: u8, steps_per_second: f64, direction: CycleDirection) -> CycleRange {
+        CycleRange {
+            start,
+            end,
+            rate: (steps_per_second * CYCLE_RATE_60 as f64 / 60.0)
+                .round()
+                .clamp(0.0, u16::MAX as f64) as u16,
+            direction,
+        }
+    }
+
+    pub fn steps_per_second(&self) -> f64 {
+        self.rate as f64 * 60.0 / CYCLE_RATE_60 as f64
+    }
+
+    pub fn len(&self) -> usize {
+        self.end as usize + 1 - self.start as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end < self.start
+    }
+
+    /// Number of steps the range has rotated by after `time_num / time_den` seconds. Integer math
+    /// keeps the steps at frame times exact, the same as in `rvc_nostd`.
+    pub fn steps(&self, time_num: u64, time_den: u64) -> usize {
+        let steps = time_num as u128 * self.rate as u128 * 60 / (time_den as u128 * CYCLE_RATE_60 as u128);
+        let steps = (steps % self.len() as u128) as usize;
+        match self.direction {
+            CycleDirection::Forward => steps,
+            CycleDirection::Backward => (self.len() - steps) % self.len(),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
-pub struct Palette(Vec<FloatColor>);
+pub struct Palette {
+    colors: Vec<FloatColor>,
+    cycles: Vec<CycleRange>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new()
+    }
+}
 
 impl Palette {
     pub fn new() -> Palette {
-        Palette(Vec::with_capacity(256))
+        Palette {
+            colors: Vec::with_capacity(256),
+            cycles: vec![],
+        }
     }
 
     pub fn add(&mut self, color: FloatColor) {
-        self.0.push(color);
+        self.colors.push(color);
     }
 
     pub fn get(&self, index: i32) -> FloatColor {
-        self.0[index as usize]
+        self.colors[index as usize]
+    }
+
+    pub fn set(&mut self, index: i32, color: FloatColor) {
+        self.colors[index as usize] = color;
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Adds a range of entries that rotate over time. Ranges must not overlap.
+    pub fn add_cycle(&mut self, range: CycleRange) -> Result<()> {
+        if range.start >= range.end || range.end as usize >= self.colors.len() {
+            bail!(
+                "Cycling range {}-{} doesn't fit the palette of {} colors",
+                range.start,
+                range.end,
+                self.colors.len()
+            );
+        }
+        if self
+            .cycles
+            .iter()
+            .any(|other| range.start <= other.end && other.start <= range.end)
+        {
+            bail!("Cycling range {}-{} overlaps another one", range.start, range.end);
+        }
+        self.cycles.push(range);
+        Ok(())
+    }
+
+    pub fn cycles(&self) -> &[CycleRange] {
+        &self.cycles
+    }
+
+    pub fn clear_cycles(&mut self) {
+        self.cycles.clear();
+    }
+
+    /// Colors shown `time_num / time_den` seconds into the stream, with all ranges rotated.
+    pub fn cycled(&self, time_num: u64, time_den: u64) -> Palette {
+        let mut result = self.clone();
+        for range in self.cycles.iter() {
+            let start = range.start as usize;
+            result.colors[start..=range.end as usize].rotate_right(range.steps(time_num, time_den));
+        }
+        result
     }
 
     pub fn sort(&mut self) {
-        self.0.sort_by(|a, b| a.luminocity().total_cmp(&b.luminocity()));
+        self.colors.sort_by(|a, b| a.luminocity().total_cmp(&b.luminocity()));
     }
 
+    /// Writes the colors followed by the cycling ranges.
     pub fn save(&self, filename: String) -> Result<()> {
-        let mut data = vec![IntColor::BLACK; self.0.len()];
+        let mut data = vec![IntColor::BLACK; self.colors.len()];
 
-        for (icol, fcol) in data.iter_mut().zip(self.0.iter()) {
+        for (icol, fcol) in data.iter_mut().zip(self.colors.iter()) {
             *icol = IntColor::from(fcol);
         }
 
         let mut file = fs::File::create(filename)?;
         let config = bincode::config::standard();
         bincode::encode_into_std_write(data, &mut file, config)?;
+        bincode::encode_into_std_write(&self.cycles, &mut file, config)?;
         Ok(())
     }
 
+    /// Reads a palette written by `save`. Files from before color cycling end after the colors.
     pub fn from_file(filename: PathBuf) -> Result<Palette> {
-        let mut file = fs::File::open(filename)?;
+        let data = fs::read(filename)?;
         let config = bincode::config::standard();
-        let data: Vec<IntColor> = bincode::decode_from_std_read(&mut file, config)?;
+        let (colors, size): (Vec<IntColor>, usize) = bincode::decode_from_slice(&data, config)?;
 
         let mut result = Palette::new();
 
-        for icol in data {
-            result.0.push(FloatColor::from(icol));
+        for icol in colors {
+            result.colors.push(FloatColor::from(icol));
+        }
+        if size < data.len() {
+            let (cycles, _): (Vec<CycleRange>, usize) = bincode::decode_from_slice(&data[size..], config)?;
+            for range in cycles {
+                result.add_cycle(range)?;
+            }
         }
         Ok(result)
     }
@@ -52,7 +185,7 @@ impl Palette {
     pub fn find(&self, color: FloatColor) -> i32 {
         let mut best_index = 0;
         let mut best_difference = f64::MAX;
-        for (i, palcol) in self.0.iter().enumerate() {
+        for (i, palcol) in self.colors.iter().enumerate() {
             let difference = color.difference(palcol);
             if difference < best_difference {
                 best_difference = difference;
@@ -62,3 +195,64 @@ impl Palette {
         best_index as i32
     }
 }
+
+/// Palette file of the scene that starts with `frame`, the frame number goes before the extension.
+pub fn scene_filename(filename: &Path, frame: u32) -> PathBuf {
+    let name = match (filename.file_stem(), filename.extension()) {
+        (Some(stem), Some(ext)) => format!("{}-{}.{}", stem.to_string_lossy(), frame, ext.to_string_lossy()),
+        _ => format!("{}-{}", filename.to_string_lossy(), frame),
+    };
+    filename.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_keep_cycles() {
+        let mut palette = Palette::new();
+        for i in 0..8 {
+            palette.add(FloatColor::from(IntColor::new(i * 30, 255 - i * 30, 7)));
+        }
+        palette
+            .add_cycle(CycleRange::new(1, 3, 7.5, CycleDirection::Backward))
+            .unwrap();
+        palette.add_cycle(CycleRange::new(5, 7, 60.0, CycleDirection::Forward)).unwrap();
+        let filename = std::env::temp_dir().join(format!("rvc-palette-{}.pal", std::process::id()));
+        palette.save(filename.to_string_lossy().into_owned()).unwrap();
+        let loaded = Palette::from_file(filename.clone()).unwrap();
+        assert_eq!(loaded.len(), 8);
+        assert_eq!(loaded.cycles(), palette.cycles());
+        assert_eq!(IntColor::from(loaded.get(7)), IntColor::from(palette.get(7)));
+
+        // Files without cycles end after the colors
+        let config = bincode::config::standard();
+        let colors = vec![IntColor::new(1, 2, 3), IntColor::new(4, 5, 6)];
+        fs::write(&filename, bincode::encode_to_vec(colors, config).unwrap()).unwrap();
+        let loaded = Palette::from_file(filename.clone()).unwrap();
+        fs::remove_file(filename).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.cycles().is_empty());
+    }
+
+    #[test]
+    fn steps_at_frame_times() {
+        let range = CycleRange::new(2, 8, 60.0, CycleDirection::Forward);
+        // 2.4 steps per frame at 25 fps, frame 5 lands on exactly 12
+        let steps: Vec<usize> = (0..7).map(|frame| range.steps(frame, 25)).collect();
+        assert_eq!(steps, [0, 2, 4, 0, 2, 5, 0]);
+        let backward = CycleRange {
+            direction: CycleDirection::Backward,
+            ..range
+        };
+        assert_eq!(backward.steps(5, 25), 2);
+        assert_eq!(range.steps(1000, 1), 60000 % 7);
+    }
+
+    #[test]
+    fn scene_filenames() {
+        assert_eq!(scene_filename(Path::new("out/clip.pal"), 25), Path::new("out/clip-25.pal"));
+        assert_eq!(scene_filename(Path::new("clip"), 0), Path::new("clip-0"));
+    }
+}
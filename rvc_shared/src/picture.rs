@@ -0,0 +1,15 @@
+use anyhow::Result;
+use image::{ImageBuffer, ImageFormat};
+use std::path::Path;
+
+use crate::{colors::IntColor, palette::Palette, plane::Plane};
+
+pub fn save_image(filename: &Path, image: &Plane<i32>, palette: &Palette) -> Result<()> {
+    let mut file = ImageBuffer::new(image.width, image.height);
+    for (file_pixel, img_pixel) in file.pixels_mut().zip(image.data.iter()) {
+        let c = IntColor::from(palette.get(*img_pixel));
+        *file_pixel = image::Rgb([c.r as u8, c.g as u8, c.b as u8]);
+    }
+    file.save_with_format(filename, ImageFormat::Png)?;
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use anyhow::{Result, bail};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Reads integer PCM samples from RIFF WAVE files, converting them to signed 16 bits.
+pub struct WavReader<R: Read> {
+    input: R,
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub bits_per_sample: u32,
+    /// Number of samples per channel in the file.
+    pub length: u32,
+    remaining: u32,
+    buffer: Vec<u8>,
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn skip(input: &mut impl Read, size: u64) -> Result<()> {
+    if std::io::copy(&mut input.take(size), &mut std::io::sink())? != size {
+        bail!("Unexpected end of WAV file");
+    }
+    Ok(())
+}
+
+impl<R: Read> WavReader<R> {
+    pub fn new(mut input: R) -> Result<WavReader<R>> {
+        let riff = read_bytes::<12>(&mut input)?;
+        if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+            bail!("Not a WAV file");
+        }
+
+        let mut format = None;
+        loop {
+            let tag = read_bytes::<4>(&mut input)?;
+            let size = u32::from_le_bytes(read_bytes::<4>(&mut input)?);
+            match &tag {
+                b"fmt " => {
+                    if size < 16 {
+                        bail!("Invalid WAV format chunk");
+                    }
+                    let mut data = vec![0u8; size as usize];
+                    input.read_exact(&mut data)?;
+                    let mut tag = u16::from_le_bytes([data[0], data[1]]);
+                    if tag == FORMAT_EXTENSIBLE && data.len() >= 26 {
+                        // The sub-format GUID starts with the actual format tag
+                        tag = u16::from_le_bytes([data[24], data[25]]);
+                    }
+                    if tag != FORMAT_PCM {
+                        bail!("Only PCM WAV files are supported");
+                    }
+                    format = Some((
+                        u16::from_le_bytes([data[2], data[3]]) as u32,
+                        u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+                        u16::from_le_bytes([data[14], data[15]]) as u32,
+                    ));
+                    if size % 2 == 1 {
+                        skip(&mut input, 1)?;
+                    }
+                }
+                b"data" => {
+                    let (channels, sample_rate, bits_per_sample) = match format {
+                        Some(format) => format,
+                        None => bail!("WAV data comes before the format chunk"),
+                    };
+                    if channels == 0 || sample_rate == 0 {
+                        bail!("Invalid WAV format");
+                    }
+                    if ![8, 16, 24, 32].contains(&bits_per_sample) {
+                        bail!("Unsupported WAV sample size of {} bits", bits_per_sample);
+                    }
+                    let frame_size = channels * bits_per_sample / 8;
+                    return Ok(WavReader {
+                        input,
+                        sample_rate,
+                        channels,
+                        bits_per_sample,
+                        length: size / frame_size,
+                        remaining: size / frame_size * frame_size,
+                        buffer: vec![],
+                    });
+                }
+                _ => skip(&mut input, size as u64 + (size % 2) as u64)?,
+            }
+        }
+    }
+
+    /// Fills `samples` with interleaved samples. Returns the number of samples read,
+    /// which is less than requested only at the end of the data.
+    pub fn read_samples(&mut self, samples: &mut [i16]) -> Result<usize> {
+        let sample_size = (self.bits_per_sample / 8) as usize;
+        let count = samples.len().min(self.remaining as usize / sample_size);
+        self.buffer.resize(count * sample_size, 0);
+        self.input.read_exact(&mut self.buffer)?;
+        self.remaining -= self.buffer.len() as u32;
+
+        for (sample, bytes) in samples.iter_mut().zip(self.buffer.chunks_exact(sample_size)) {
+            *sample = match sample_size {
+                1 => ((bytes[0] as i16) - 128) << 8,
+                // Keep the most significant bytes of wider samples
+                _ => i16::from_le_bytes([bytes[sample_size - 2], bytes[sample_size - 1]]),
+            };
+        }
+        Ok(count)
+    }
+}
+
+/// Writes signed 16-bit PCM WAV files. The chunk sizes are filled in by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    output: W,
+    channels: u32,
+    size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut output: W, sample_rate: u32, channels: u32) -> Result<WavWriter<W>> {
+        let frame_size = channels * 2;
+        output.write_all(b"RIFF\0\0\0\0WAVEfmt ")?;
+        output.write_all(&16u32.to_le_bytes())?;
+        output.write_all(&FORMAT_PCM.to_le_bytes())?;
+        output.write_all(&(channels as u16).to_le_bytes())?;
+        output.write_all(&sample_rate.to_le_bytes())?;
+        output.write_all(&(sample_rate * frame_size).to_le_bytes())?;
+        output.write_all(&(frame_size as u16).to_le_bytes())?;
+        output.write_all(&16u16.to_le_bytes())?;
+        output.write_all(b"data\0\0\0\0")?;
+        Ok(WavWriter {
+            output,
+            channels,
+            size: 0,
+        })
+    }
+
+    /// Appends interleaved samples.
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        if !samples.len().is_multiple_of(self.channels as usize) {
+            bail!("Samples don't fill whole frames of {} channels", self.channels);
+        }
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.output.write_all(&data)?;
+        self.size += data.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.output.seek(SeekFrom::Start(4))?;
+        self.output.write_all(&(self.size + 36).to_le_bytes())?;
+        self.output.seek(SeekFrom::Start(40))?;
+        self.output.write_all(&self.size.to_le_bytes())?;
+        self.output.seek(SeekFrom::End(0))?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// WAV file with an odd-sized chunk ahead of the format.
+    fn wav(format: u16, channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut fmt = vec![];
+        fmt.extend_from_slice(&format.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&22050u32.to_le_bytes());
+        fmt.extend_from_slice(&(22050 * (channels * bits / 8) as u32).to_le_bytes());
+        fmt.extend_from_slice(&(channels * bits / 8).to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+        if format == FORMAT_EXTENSIBLE {
+            fmt.extend_from_slice(&[22, 0, bits as u8, 0, 0, 0, 0, 0]);
+            fmt.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+            fmt.extend_from_slice(&[0; 14]);
+        }
+        let mut file = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (tag, chunk) in [(b"LIST", &b"odd"[..]), (b"fmt ", &fmt), (b"data", data)] {
+            file.extend_from_slice(tag);
+            file.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            file.extend_from_slice(chunk);
+            if chunk.len() % 2 == 1 {
+                file.push(0);
+            }
+        }
+        file
+    }
+
+    #[test]
+    fn sample_sizes() {
+        let expected = [-32768, -256, 0, 256, 32512];
+        // 8-bit samples lose the low byte of the last one
+        let layouts: [(u16, Vec<u8>); 4] = [
+            (8, vec![0, 127, 128, 129, 255, 0x92]),
+            (16, vec![0, 0x80, 0, 0xff, 0, 0, 0, 1, 0, 0x7f, 0x34, 0x12]),
+            (24, vec![9, 0, 0x80, 9, 0, 0xff, 9, 0, 0, 9, 0, 1, 9, 0, 0x7f, 9, 0x34, 0x12]),
+            (
+                32,
+                vec![
+                    9, 9, 0, 0x80, 9, 9, 0, 0xff, 9, 9, 0, 0, 9, 9, 0, 1, 9, 9, 0, 0x7f, 9, 9, 0x34, 0x12,
+                ],
+            ),
+        ];
+        for (bits, data) in layouts {
+            for format in [FORMAT_PCM, FORMAT_EXTENSIBLE] {
+                let file = wav(format, 2, bits, &data);
+                let mut reader = WavReader::new(file.as_slice()).unwrap();
+                assert_eq!((reader.sample_rate, reader.channels, reader.length), (22050, 2, 3));
+                let mut samples = [1; 4];
+                assert_eq!(reader.read_samples(&mut samples).unwrap(), 4);
+                let mut rest = [1; 4];
+                assert_eq!(reader.read_samples(&mut rest).unwrap(), 2);
+                assert_eq!(reader.read_samples(&mut rest).unwrap(), 0);
+                let mut result = samples.to_vec();
+                result.extend_from_slice(&rest[..2]);
+                assert_eq!(result[..5], expected, "{} bits", bits);
+                assert_eq!(result[5], if bits == 8 { 0x1200 } else { 0x1234 }, "{} bits", bits);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(WavReader::new(wav(3, 1, 32, &[0; 8]).as_slice()).is_err());
+        assert!(WavReader::new(wav(FORMAT_PCM, 1, 12, &[0; 8]).as_slice()).is_err());
+        assert!(WavReader::new(wav(FORMAT_PCM, 0, 16, &[0; 8]).as_slice()).is_err());
+        assert!(WavReader::new(&b"RIFF\0\0\0\0AVI "[..]).is_err());
+    }
+
+    #[test]
+    fn writer_roundtrip() {
+        let samples: Vec<i16> = (0..1001).map(|i| (i * 97 % 65536 - 32768) as i16).collect();
+        let mut writer = WavWriter::new(std::io::Cursor::new(vec![]), 11025, 1).unwrap();
+        writer.write_samples(&samples[..500]).unwrap();
+        writer.write_samples(&samples[500..]).unwrap();
+        let file = writer.finish().unwrap().into_inner();
+        assert_eq!(file.len(), 44 + samples.len() * 2);
+        let mut reader = WavReader::new(file.as_slice()).unwrap();
+        assert_eq!((reader.sample_rate, reader.channels, reader.bits_per_sample), (11025, 1, 16));
+        assert_eq!(reader.length, samples.len() as u32);
+        let mut result = vec![0; 2000];
+        assert_eq!(reader.read_samples(&mut result).unwrap(), samples.len());
+        assert_eq!(result[..samples.len()], samples);
+
+        let mut writer = WavWriter::new(std::io::Cursor::new(vec![]), 11025, 2).unwrap();
+        assert!(writer.write_samples(&samples[..3]).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::{Result, bail};
+use std::io::{Read, Write};
+
+use crate::{colors::IntColor, plane::Plane};
+
+const SIGNATURE: &str = "YUV4MPEG2";
+const FRAME_SIGNATURE: &[u8] = b"FRAME";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Chroma {
+    C420,
+    C422,
+    C444,
+    Mono,
+}
+
+impl Chroma {
+    fn parse(value: &str) -> Result<Chroma> {
+        Ok(match value {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => Chroma::C420,
+            "422" => Chroma::C422,
+            "444" => Chroma::C444,
+            "mono" => Chroma::Mono,
+            _ => bail!("Unsupported Y4M colour space C{}", value),
+        })
+    }
+
+    /// Horizontal and vertical chroma subsampling shifts.
+    fn shifts(&self) -> (u32, u32) {
+        match self {
+            Chroma::C420 => (1, 1),
+            Chroma::C422 => (1, 0),
+            Chroma::C444 | Chroma::Mono => (0, 0),
+        }
+    }
+}
+
+/// Reads YUV4MPEG2 streams with 8-bit samples frame by frame.
+pub struct Y4mReader<R: Read> {
+    input: R,
+    pub width: u32,
+    pub height: u32,
+    pub fps_num: u32,
+    pub fps_den: u32,
+    pub chroma: Chroma,
+    luma: Vec<u8>,
+    cb: Vec<u8>,
+    cr: Vec<u8>,
+}
+
+fn read_line(input: &mut impl Read) -> Result<Option<String>> {
+    let mut line = vec![];
+    let mut byte = [0u8; 1];
+    loop {
+        if input.read(&mut byte)? == 0 {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            bail!("Unexpected end of Y4M file");
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+        if line.len() > 1024 {
+            bail!("Y4M header line is too long");
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Converts a BT.601 studio range sample to RGB.
+pub fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> IntColor {
+    let y = (y as f64 - 16.0) * 1.164;
+    let cb = cb as f64 - 128.0;
+    let cr = cr as f64 - 128.0;
+    IntColor::new(
+        (y + 1.596 * cr).round().clamp(0.0, 255.0) as i32,
+        (y - 0.392 * cb - 0.813 * cr).round().clamp(0.0, 255.0) as i32,
+        (y + 2.017 * cb).round().clamp(0.0, 255.0) as i32,
+    )
+}
+
+/// Converts an RGB color to a BT.601 studio range sample.
+pub fn rgb_to_ycbcr(color: IntColor) -> (u8, u8, u8) {
+    let (r, g, b) = (color.r as f64, color.g as f64, color.b as f64);
+    let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+    let cb = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+    let cr = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+    (
+        y.round().clamp(0.0, 255.0) as u8,
+        cb.round().clamp(0.0, 255.0) as u8,
+        cr.round().clamp(0.0, 255.0) as u8,
+    )
+}
+
+impl<R: Read> Y4mReader<R> {
+    pub fn new(mut input: R) -> Result<Y4mReader<R>> {
+        let header = match read_line(&mut input)? {
+            Some(header) => header,
+            None => bail!("Empty Y4M file"),
+        };
+        let mut params = header.split(' ');
+        if params.next() != Some(SIGNATURE) {
+            bail!("Not a Y4M file");
+        }
+
+        let mut width: u32 = 0;
+        let mut height: u32 = 0;
+        let mut fps = (25, 1);
+        let mut chroma = Chroma::C420;
+        for param in params.filter(|p| !p.is_empty()) {
+            let key = param.chars().next().unwrap_or(' ');
+            let value = &param[key.len_utf8()..];
+            match key {
+                'W' => width = value.parse()?,
+                'H' => height = value.parse()?,
+                'F' => {
+                    if let Some((num, den)) = value.split_once(':') {
+                        fps = (num.parse()?, den.parse()?);
+                    }
+                }
+                'C' => chroma = Chroma::parse(value)?,
+                'I' if value != "p" && value != "?" => bail!("Interlaced Y4M files are not supported"),
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 {
+            bail!("Y4M file has no frame size");
+        }
+        if fps.0 == 0 || fps.1 == 0 {
+            bail!("Invalid Y4M frame rate {}:{}", fps.0, fps.1);
+        }
+        // Pixels are addressed with `u32` indices
+        let luma_size = match (width as usize).checked_mul(height as usize) {
+            Some(size) if size <= u32::MAX as usize => size,
+            _ => bail!("Y4M frame size {}x{} is too large", width, height),
+        };
+
+        let (sx, sy) = chroma.shifts();
+        let chroma_size = if chroma == Chroma::Mono {
+            0
+        } else {
+            width.div_ceil(1 << sx) as usize * height.div_ceil(1 << sy) as usize
+        };
+        Ok(Y4mReader {
+            input,
+            width,
+            height,
+            fps_num: fps.0,
+            fps_den: fps.1,
+            chroma,
+            luma: vec![0; luma_size],
+            cb: vec![0; chroma_size],
+            cr: vec![0; chroma_size],
+        })
+    }
+
+    /// Reads the next frame into `frame`. Returns `false` at the end of the stream.
+    pub fn read_frame(&mut self, frame: &mut Plane<IntColor>) -> Result<bool> {
+        let line = match read_line(&mut self.input)? {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+        if !line.as_bytes().starts_with(FRAME_SIGNATURE) {
+            bail!("Invalid Y4M frame header");
+        }
+        if frame.width != self.width || frame.height != self.height {
+            bail!("Frame size doesn't match Y4M size {}x{}", self.width, self.height);
+        }
+        self.input.read_exact(&mut self.luma)?;
+        self.input.read_exact(&mut self.cb)?;
+        self.input.read_exact(&mut self.cr)?;
+
+        let (sx, sy) = self.chroma.shifts();
+        let chroma_width = self.width.div_ceil(1 << sx);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let luma = self.luma[(x + y * self.width) as usize];
+                let color = if self.chroma == Chroma::Mono {
+                    ycbcr_to_rgb(luma, 128, 128)
+                } else {
+                    let i = ((x >> sx) + (y >> sy) * chroma_width) as usize;
+                    ycbcr_to_rgb(luma, self.cb[i], self.cr[i])
+                };
+                frame.set(x, y, color);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Writes YUV4MPEG2 streams without chroma subsampling.
+pub struct Y4mWriter<W: Write> {
+    output: W,
+    width: u32,
+    height: u32,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    pub fn new(mut output: W, width: u32, height: u32, fps_num: u32, fps_den: u32) -> Result<Y4mWriter<W>> {
+        writeln!(
+            output,
+            "{} W{} H{} F{}:{} Ip A1:1 C444",
+            SIGNATURE, width, height, fps_num, fps_den
+        )?;
+        Ok(Y4mWriter {
+            output,
+            width,
+            height,
+            buffer: vec![0; (width * height * 3) as usize],
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &Plane<IntColor>) -> Result<()> {
+        if frame.width != self.width || frame.height != self.height {
+            bail!("Frame size doesn't match Y4M size {}x{}", self.width, self.height);
+        }
+        let size = (self.width * self.height) as usize;
+        for (i, color) in frame.data.iter().enumerate() {
+            let (y, cb, cr) = rgb_to_ycbcr(*color);
+            self.buffer[i] = y;
+            self.buffer[i + size] = cb;
+            self.buffer[i + size * 2] = cr;
+        }
+        self.output.write_all(FRAME_SIGNATURE)?;
+        self.output.write_all(b"\n")?;
+        self.output.write_all(&self.buffer)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stream of one 3x3 frame with luma 16 + 20 * pixel number and chroma 100 + 10 * sample number.
+    fn stream(chroma: &str, chroma_samples: usize) -> Vec<u8> {
+        let mut data = format!("YUV4MPEG2 W3 H3 F30000:1001 Ip A1:1 C{}\nFRAME\n", chroma).into_bytes();
+        data.extend((0..9).map(|i| 16 + 20 * i as u8));
+        for _ in 0..2 {
+            data.extend((0..chroma_samples).map(|i| 100 + 10 * i as u8));
+        }
+        data
+    }
+
+    #[test]
+    fn bt601_colors() {
+        assert_eq!(ycbcr_to_rgb(16, 128, 128), IntColor::new(0, 0, 0));
+        assert_eq!(ycbcr_to_rgb(235, 128, 128), IntColor::new(255, 255, 255));
+        assert_eq!(ycbcr_to_rgb(81, 90, 240), IntColor::new(254, 0, 0));
+    }
+
+    #[test]
+    fn chroma_subsampling() {
+        // Chroma sample of every pixel, partial samples cover the right column and bottom row
+        let layouts = [
+            ("420jpeg", 4, [0, 0, 1, 0, 0, 1, 2, 2, 3]),
+            ("422", 6, [0, 0, 1, 2, 2, 3, 4, 4, 5]),
+            ("444", 9, [0, 1, 2, 3, 4, 5, 6, 7, 8]),
+        ];
+        for (name, samples, layout) in layouts {
+            let data = stream(name, samples);
+            let mut reader = Y4mReader::new(data.as_slice()).unwrap();
+            assert_eq!((reader.width, reader.height, reader.fps_num, reader.fps_den), (3, 3, 30000, 1001));
+            let mut frame = Plane::new(3, 3, IntColor::BLACK);
+            assert!(reader.read_frame(&mut frame).unwrap());
+            for (i, sample) in layout.iter().enumerate() {
+                let chroma = 100 + 10 * *sample as u8;
+                assert_eq!(frame.data[i], ycbcr_to_rgb(16 + 20 * i as u8, chroma, chroma), "C{}", name);
+            }
+            assert!(!reader.read_frame(&mut frame).unwrap());
+        }
+    }
+
+    #[test]
+    fn mono_is_grey() {
+        let data = stream("mono", 0);
+        let mut reader = Y4mReader::new(data.as_slice()).unwrap();
+        assert_eq!(reader.chroma, Chroma::Mono);
+        let mut frame = Plane::new(3, 3, IntColor::BLACK);
+        assert!(reader.read_frame(&mut frame).unwrap());
+        for (i, color) in frame.data.iter().enumerate() {
+            assert_eq!((color.r, color.g), (color.b, color.b));
+            assert_eq!(*color, ycbcr_to_rgb(16 + 20 * i as u8, 128, 128));
+        }
+        // Frames cut short are an error
+        let mut data = stream("mono", 0);
+        data.pop();
+        let mut reader = Y4mReader::new(data.as_slice()).unwrap();
+        assert!(reader.read_frame(&mut frame).is_err());
+    }
+
+    #[test]
+    fn writer_roundtrip() {
+        let mut frame = Plane::new(5, 2, IntColor::BLACK);
+        for (i, color) in frame.data.iter_mut().enumerate() {
+            let i = i as i32;
+            *color = IntColor::new(i * 25, 255 - i * 20, (i * 70) % 256);
+        }
+        let mut writer = Y4mWriter::new(vec![], 5, 2, 25, 1).unwrap();
+        writer.write_frame(&frame).unwrap();
+        writer.write_frame(&frame).unwrap();
+        let data = writer.finish().unwrap();
+        let mut reader = Y4mReader::new(data.as_slice()).unwrap();
+        assert_eq!(reader.chroma, Chroma::C444);
+        let mut result = Plane::new(5, 2, IntColor::BLACK);
+        for _ in 0..2 {
+            assert!(reader.read_frame(&mut result).unwrap());
+            for (a, b) in frame.data.iter().zip(result.data.iter()) {
+                // Studio range YCbCr loses a little precision
+                assert!((a.r - b.r).abs() <= 2 && (a.g - b.g).abs() <= 2 && (a.b - b.b).abs() <= 2);
+            }
+        }
+        assert!(!reader.read_frame(&mut result).unwrap());
+    }
+
+    #[test]
+    fn rejects_zero_frame_rate() {
+        for rate in ["F0:0", "F30:0", "F0:1"] {
+            let header = format!("YUV4MPEG2 W4 H2 {} Ip C444\n", rate);
+            assert!(Y4mReader::new(header.as_bytes()).is_err(), "{}", rate);
+        }
+        let reader = Y4mReader::new(&b"YUV4MPEG2 W4 H2 F30000:1001 Ip C444\n"[..]).unwrap();
+        assert_eq!((reader.fps_num, reader.fps_den), (30000, 1001));
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        for size in ["W100000 H100000", "W4294967295 H4294967295"] {
+            let header = format!("YUV4MPEG2 {} F25:1 Ip C420\n", size);
+            assert!(Y4mReader::new(header.as_bytes()).is_err(), "{}", size);
+        }
+    }
+}